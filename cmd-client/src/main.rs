@@ -1,55 +1,599 @@
-use std::time::SystemTime;
-use std::{net::UdpSocket, time::Duration};
+use std::fs::File;
+use std::io::BufReader;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
+use clap::{Parser, Subcommand};
 use rand::{prelude::Distribution, distributions::Uniform};
-use udp_leds::constants::MAX_LED_COUNT;
-use udp_leds::{constants::{MAX_MESSAGE_LENGTH, PORT}, client::ClientMessages, server::ServerMessages};
+use udp_leds::client::ServerInfo;
+use udp_leds::constants::{MAX_LED_COUNT, MULTICAST_GROUP, PORT};
+use udp_leds::effects::{Animation, Breathe, Chase, Clock, Comet, Effect, Fire, Gradient, Meteor, Plasma, Rainbow, SystemClock, Twinkle};
+use udp_leds::{config::Config, error::Error, layout::{Corner, Direction, Layout, Rectangle}, segment::Segment, fseq::Fseq, sequence::SequenceReader, server::Advertisement, Client, ClientToken, DeviceId, Frame, Rgb};
 
 const PIXEL_COUNT: usize = 64;
 
-fn gaussian(x: f64, mu: f64) -> f64 {
-    (-(x - mu).powi(2)).exp()
+/// Drives udp-leds controllers from the command line, runs the interactive menu without a command
+///
+/// Exits with 0 on success, 1 when the command fails and 2 on invalid arguments
+#[derive(Debug, Parser)]
+struct Args {
+    /// Server to talk to, as an address, an IP, a name or an id, the remembered server by default
+    #[arg(short, long, global = true)]
+    server: Option<String>,
+    /// Device id to send as, claimed from the server by default
+    #[arg(short, long, global = true, value_parser = clap::value_parser!(u8).range(0..DeviceId::COUNT as i64))]
+    device: Option<u8>,
+    /// Number of LEDs of the strip, the count advertised by the server by default
+    #[arg(short, long, global = true, value_parser = clap::value_parser!(u16).range(1..=MAX_LED_COUNT as i64))]
+    leds: Option<u16>,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Lists the servers answering the broadcast, or the mDNS services
+    Discover {
+        /// Seconds to wait for the answers
+        #[arg(short, long, default_value = "0.5", value_parser = parse_seconds)]
+        timeout: Duration,
+    },
+    /// Makes the device the active one of the server
+    Acquire,
+    /// Sets a single pixel
+    Pixel {
+        index: u8,
+        /// Color as #rrggbb, rrggbb, r,g,b or a name
+        color: Rgb,
+    },
+    /// Sets every pixel to the same color
+    Fill {
+        /// Color as #rrggbb, rrggbb, r,g,b or a name
+        color: Rgb,
+    },
+    /// Sends a frame, the pixels after the given ones are turned off
+    Frame {
+        /// Colors of the first pixels
+        #[arg(required = true)]
+        colors: Vec<Rgb>,
+    },
+    /// Plays an effect
+    Effect {
+        #[arg(value_enum)]
+        effect: EffectKind,
+        /// Seconds to play the effect for
+        #[arg(short = 't', long, default_value = "15", value_parser = parse_seconds)]
+        duration: Duration,
+        /// Main color of the effects having one
+        #[arg(short, long)]
+        color: Option<Rgb>,
+    },
+    /// Turns every pixel off
+    Off,
+}
 
-fn main() {
-    let mut rng = rand::thread_rng();
-    let mut smessage = [0; 770];
-    let mut cmessage: [u8; 770] = [0; MAX_MESSAGE_LENGTH];
+/// Parses a number of seconds, the negative, infinite and NaN ones are refused
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value.parse().map_err(|error: std::num::ParseFloatError| error.to_string())?;
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("{value} is not a number of seconds"))
+}
+
+/// Effects selectable from the command line
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum EffectKind {
+    Rainbow,
+    Chase,
+    Breathe,
+    Twinkle,
+    Fire,
+    Meteor,
+    Plasma,
+    Comet,
+    Gradient,
+}
+
+impl EffectKind {
+    /// The effect with its default parameters, in the given color if it has one
+    fn effect(self, color: Option<Rgb>) -> Box<dyn Effect + Send> {
+        let color = color.unwrap_or(Rgb::WHITE);
+        let seed = rand::random();
+        match self {
+            EffectKind::Rainbow => Box::new(Rainbow::default()),
+            EffectKind::Chase => Box::new(Chase { color, ..Default::default() }),
+            EffectKind::Breathe => Box::new(Breathe { color, ..Default::default() }),
+            EffectKind::Twinkle => Box::new(Twinkle::new(color, 0.5, Duration::from_secs(1), seed)),
+            EffectKind::Fire => Box::new(Fire::new(55, 120, seed)),
+            EffectKind::Meteor => Box::new(Meteor::new(color, 3, 30.0, seed)),
+            EffectKind::Plasma => Box::new(Plasma::default()),
+            EffectKind::Comet => Box::new(Comet { color, ..Default::default() }),
+            EffectKind::Gradient => Box::new(Gradient { colors: vec![Rgb::RED, color, Rgb::BLUE], period: Duration::from_secs(5) }),
+        }
+    }
+}
+
+/// File storing the id of the last server the client connected to
+fn remembered_server_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cmd-client-server"))
+}
+
+/// The server to reconnect to, identified by its unique id or by its name for servers without id
+fn remembered_server() -> Option<String> {
+    let name = std::fs::read_to_string(remembered_server_path()?).ok()?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+fn remember_server(advertisement: &Advertisement) {
+    let name = if advertisement.id != [0; 6] {
+        advertisement.id_string()
+    } else {
+        advertisement.name.clone()
+    };
+    if let Some(path) = remembered_server_path() {
+        if let Err(error) = std::fs::write(path, name) {
+            println!("Failed to remember the server: {error}");
+        }
+    }
+}
+
+/// File storing the token of the client, so the servers give it the same device id after a restart
+fn token_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cmd-client-token"))
+}
+
+/// The token stored by a previous run, or a new one which gets stored
+fn client_token() -> ClientToken {
+    let path = token_path();
+    if let Some(token) = path.as_ref().and_then(|path| std::fs::read_to_string(path).ok()).and_then(|token| token.trim().parse().ok()) {
+        return token;
+    }
+    let token = ClientToken::random();
+    if let Some(path) = path {
+        if let Err(error) = std::fs::write(path, token.to_string()) {
+            println!("Failed to store the client token: {error}");
+        }
+    }
+    token
+}
+
+/// Asks the server for its status every second, `polls` times
+fn poll_status(client: &Client, polls: usize) -> Result<(), Error> {
+    for poll in 0..polls {
+        if poll > 0 {
+            std::thread::sleep(Duration::from_secs(1));
+        }
+        let status = client.status()?;
+        let active = status.active.map_or("none".to_string(), |device| device.to_string());
+        let heap = match status.free_heap {
+            0 => "?".to_string(),
+            free_heap => format!("{} kB", free_heap / 1024),
+        };
+        let rssi = match status.rssi {
+            0 => "?".to_string(),
+            rssi => format!("{rssi} dBm"),
+        };
+        println!(
+            "up {}s | frames {} received, {} applied, {} dropped | {:.1} fps | active device {active} | heap {heap} | RSSI {rssi}",
+            status.uptime.as_secs(),
+            status.frames_received,
+            status.frames_applied,
+            status.frames_dropped,
+            status.fps,
+        );
+        if !status.last_error.is_empty() {
+            println!("last error: {}", status.last_error);
+        }
+    }
+    Ok(())
+}
+
+/// Applies the `key=value` changes of the line to the config, the keys are leds, order, pin and name
+fn edit_config(config: &Config, line: &str) -> Result<Config, Error> {
+    let mut config = config.clone();
+    for change in line.split_whitespace() {
+        let (key, value) = change.split_once('=').ok_or(Error::InvalidConfig)?;
+        match key {
+            "leds" => config.led_count = value.parse().map_err(|_| Error::InvalidConfig)?,
+            "order" => config.color_order = value.parse()?,
+            "pin" => config.output_pin = value.parse().map_err(|_| Error::InvalidConfig)?,
+            "name" => config.name = value.to_string(),
+            _ => return Err(Error::InvalidConfig),
+        }
+    }
+    config.validate()?;
+    Ok(config)
+}
 
-    let udp = UdpSocket::bind(format!("0.0.0.0:{PORT}")).expect("Failed to bind to port");
-    udp.set_broadcast(true).expect("Failed to set broadcast");
-    udp.set_read_timeout(Some(Duration::from_millis(100))).expect("Failed to set read timeout");
+/// Reads a segment table written as `name:start:len` entries, with a `:r` suffix for the reversed segments
+fn parse_segments(line: &str) -> Result<Vec<Segment>, Error> {
+    line.split_whitespace()
+        .map(|entry| {
+            let mut fields = entry.split(':');
+            let (Some(name), Some(start), Some(len)) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(Error::InvalidSegment);
+            };
+            let reversed = match fields.next() {
+                None => false,
+                Some("r") => true,
+                Some(_) => return Err(Error::InvalidSegment),
+            };
+            Ok(Segment {
+                name: name.to_string(),
+                start: start.parse().map_err(|_| Error::InvalidSegment)?,
+                len: len.parse().map_err(|_| Error::InvalidSegment)?,
+                reversed,
+            })
+        })
+        .collect()
+}
+
+/// Applies the `key=value` changes of the line to a rectangle layout, the keys are top, right, bottom, left,
+/// start (tl, tr, br or bl), direction (cw or ccw), corner_gap and bottom_gap
+fn edit_layout(layout: &Layout, line: &str) -> Result<Layout, Error> {
+    let mut rectangle = match layout {
+        Layout::Rectangle(rectangle) => *rectangle,
+        _ => Rectangle::default(),
+    };
+    for change in line.split_whitespace() {
+        let (key, value) = change.split_once('=').ok_or(Error::InvalidLayout)?;
+        let count = || value.parse::<u16>().map_err(|_| Error::InvalidLayout);
+        match key {
+            "top" => rectangle.top = count()?,
+            "right" => rectangle.right = count()?,
+            "bottom" => rectangle.bottom = count()?,
+            "left" => rectangle.left = count()?,
+            "start" => {
+                rectangle.start = match value {
+                    "tl" => Corner::TopLeft,
+                    "tr" => Corner::TopRight,
+                    "br" => Corner::BottomRight,
+                    "bl" => Corner::BottomLeft,
+                    _ => return Err(Error::InvalidLayout),
+                }
+            },
+            "direction" => {
+                rectangle.direction = match value {
+                    "cw" => Direction::Clockwise,
+                    "ccw" => Direction::CounterClockwise,
+                    _ => return Err(Error::InvalidLayout),
+                }
+            },
+            "corner_gap" => rectangle.corner_gap = value.parse().map_err(|_| Error::InvalidLayout)?,
+            "bottom_gap" => rectangle.bottom_gap = count()?,
+            _ => return Err(Error::InvalidLayout),
+        }
+    }
+    Ok(Layout::Rectangle(rectangle))
+}
+
+/// Uploads a firmware image to the server and restarts it, printing the progress
+fn update_firmware(client: &Client, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let image = std::fs::read(path)?;
+    println!("Uploading {} kB", image.len() / 1024);
+    let mut last_percent = None;
+    client.update_firmware(&image, |offset, size| {
+        let percent = offset as u64 * 100 / size.max(1) as u64;
+        if last_percent != Some(percent / 10) {
+            println!("{percent}%");
+            last_percent = Some(percent / 10);
+        }
+    })?;
+    Ok(())
+}
+
+/// Streams a sequence file to the server, `loops` times or forever if it is 0
+///
+/// The frames are scheduled from the start of the playback rather than from the previous frame so the
+/// time spent sending does not accumulate into a drift
+fn play_sequence(client: &Client, path: &Path, loops: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = SequenceReader::new(BufReader::new(File::open(path)?))?;
+    let header = *reader.header();
+    let duration = header.frame_duration();
+    let frames = reader.collect::<Result<Vec<Frame>, _>>()?;
+    println!("Playing {} frames of {} pixels at {} fps", frames.len(), header.led_count, header.fps);
+
+    let start = Instant::now();
+    let mut index: u32 = 0;
+    let mut round = 0;
+    while loops == 0 || round < loops {
+        for frame in &frames {
+            let due = duration * index;
+            let elapsed = start.elapsed();
+            if due > elapsed {
+                std::thread::sleep(due - elapsed);
+            }
+            client.send_frame(frame)?;
+            index += 1;
+        }
+        round += 1;
+    }
+    Ok(())
+}
+
+/// Streams a channel range of a xLights FSEQ file to the server, `loops` times or forever if it is 0
+///
+/// The LEDs of the strip are mapped to `led_count` RGB pixels starting at `start_channel` (counted from 1 like
+/// in xLights), frames are decoded while playing so long shows do not have to fit in memory
+fn play_fseq(client: &Client, path: &Path, start_channel: u32, led_count: usize, loops: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let mut fseq = Fseq::open(path)?;
+    let duration = fseq.frame_duration();
+    println!(
+        "Playing {} frames of {} channels every {} ms, channels {} to {}",
+        fseq.frame_count(),
+        fseq.channel_count(),
+        duration.as_millis(),
+        start_channel,
+        start_channel as usize + led_count * 3 - 1
+    );
+
+    let start = Instant::now();
+    let mut index: u32 = 0;
+    let mut round = 0;
+    while loops == 0 || round < loops {
+        for frame in 0..fseq.frame_count() {
+            let frame = fseq.read_frame(frame, start_channel.saturating_sub(1), led_count)?;
+            let due = duration * index;
+            let elapsed = start.elapsed();
+            if due > elapsed {
+                std::thread::sleep(due - elapsed);
+            }
+            client.send_frame(&frame)?;
+            index += 1;
+        }
+        round += 1;
+    }
+    Ok(())
+}
+
+/// Connects to the server given on the command line, the remembered one or the only one answering the broadcast
+fn connect(client: &mut Client, server: Option<&str>) -> Result<ServerInfo, Box<dyn std::error::Error>> {
+    let timeout = Duration::from_millis(500);
+    let Some(server) = server.map(str::to_string).or_else(remembered_server) else {
+        let servers = client.discover(timeout)?;
+        return match servers.as_slice() {
+            [server] => Ok(client.connect(server.addr)?),
+            [] => Err("no server found".into()),
+            _ => Err("several servers answered, pick one with --server".into()),
+        };
+    };
+    let server = if let Ok(addr) = server.parse::<SocketAddr>() {
+        client.connect(addr)?
+    } else if let Ok(ip) = server.parse::<IpAddr>() {
+        client.connect((ip, PORT))?
+    } else {
+        client.connect_by_name(&server, timeout)?
+    };
+    Ok(server)
+}
+
+/// The device id given on the command line, or one claimed from the server
+fn device(client: &Client, device: Option<u8>) -> Result<DeviceId, Error> {
+    if let Some(device) = device {
+        return DeviceId::new(device);
+    }
+    match client.claim() {
+        Ok(device) => Ok(device),
+        // Older servers do not assign ids
+        Err(Error::Unsupported | Error::Timeout) => DeviceId::new(Uniform::new(0, DeviceId::COUNT).sample(&mut rand::thread_rng())),
+        Err(error) => Err(error),
+    }
+}
+
+/// Plays an effect for `duration`, on every server of the group if there is one
+fn play_effect(client: &Client, group: &[ServerInfo], led_count: usize, kind: EffectKind, color: Option<Rgb>, duration: Duration) -> Result<(), Error> {
+    let clock = SystemClock::new();
+    let mut animation = Animation::new(kind.effect(color), clock);
+    let mut frame = Frame::new(led_count);
+    // One frame and effect per server of the group, sized to its strip
+    let mut frames: Vec<([u8; 6], Frame, Box<dyn Effect + Send>)> = group
+        .iter()
+        .map(|server| {
+            let count = match server.advertisement.led_count as usize {
+                0 => PIXEL_COUNT,
+                count => count.min(MAX_LED_COUNT),
+            };
+            (server.advertisement.id, Frame::new(count), kind.effect(color))
+        })
+        .collect();
+    while clock.elapsed() < duration {
+        if frames.is_empty() {
+            animation.render(&mut frame);
+            client.send_frame(&frame)?;
+        } else {
+            for (_, frame, effect) in &mut frames {
+                effect.render(clock.elapsed(), frame);
+            }
+            let frames: Vec<([u8; 6], &Frame)> = frames.iter().map(|(id, frame, _)| (*id, frame)).collect();
+            client.send_group_frames(&frames)?;
+        }
+        std::thread::sleep(Duration::from_millis(16));
+    }
+    Ok(())
+}
+
+/// Runs a command given on the command line
+fn run(client: &mut Client, args: &Args, command: &Command) -> Result<(), Box<dyn std::error::Error>> {
+    if let Command::Discover { timeout } = command {
+        let timeout = *timeout;
+        let mut servers = client.discover(timeout)?;
+        if servers.is_empty() {
+            servers = client.browse(timeout).unwrap_or_default();
+        }
+        if servers.is_empty() {
+            return Err("no server found".into());
+        }
+        for server in &servers {
+            println!("{} ({}) at {} with {} LEDs", server.advertisement.name, server.advertisement.id_string(), server.addr, server.advertisement.led_count);
+        }
+        return Ok(());
+    }
+
+    let server = connect(client, args.server.as_deref())?;
+    let led_count = match args.leds {
+        Some(leds) => leds as usize,
+        None => match server.advertisement.led_count as usize {
+            0 => PIXEL_COUNT,
+            count => count.min(MAX_LED_COUNT),
+        },
+    };
+    let device = device(client, args.device)?;
+    client.acquire(device)?;
+
+    match command {
+        Command::Discover { .. } | Command::Acquire => {},
+        Command::Pixel { index, color } => client.set_pixel(*index, *color)?,
+        Command::Fill { color } => {
+            let mut frame = Frame::new(led_count);
+            frame.fill(*color);
+            client.send_frame(&frame)?;
+        },
+        Command::Frame { colors } => {
+            let mut frame = Frame::new(led_count.max(colors.len()).min(MAX_LED_COUNT));
+            frame.copy_at(0, &colors[..colors.len().min(frame.len())]);
+            client.send_frame(&frame)?;
+        },
+        Command::Effect { effect, duration, color } => {
+            play_effect(client, &[], led_count, *effect, *color, *duration)?;
+        },
+        Command::Off => client.send_frame(&Frame::new(led_count))?,
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let mut client = match Client::new() {
+        Ok(client) => client,
+        Err(error) => {
+            eprintln!("Failed to create the client: {error}");
+            return ExitCode::FAILURE;
+        },
+    };
+    client.set_token(client_token());
+
+    let Some(command) = &args.command else {
+        interactive(client, args.server.as_deref());
+        return ExitCode::SUCCESS;
+    };
+    let result = run(&mut client, &args, command);
+    client.close();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        },
+    }
+}
+
+/// Runs the menu until the user quits or the input is closed
+fn interactive(mut client: Client, server: Option<&str>) {
+    let mut rng = rand::thread_rng();
     let mut input = String::new();
+    // Servers of the multicast group, each one gets its own frame
+    let mut group: Vec<ServerInfo> = Vec::new();
 
-    let broadcast = std::net::SocketAddr::from(([255, 255, 255, 255], PORT));
-    let mut server = broadcast.clone();
-    let mut device = 0;
+    if let Some(server) = server {
+        match connect(&mut client, Some(server)) {
+            Ok(info) => println!("Connected to {} at {}", info.advertisement.name, info.addr),
+            Err(error) => println!("Failed to connect to {server}: {error}"),
+        }
+    } else if let Some(name) = remembered_server() {
+        match client.connect_by_name(&name, Duration::from_millis(500)) {
+            Ok(server) => println!("Reconnected to {name} at {}", server.addr),
+            Err(error) => println!("Failed to reconnect to {name}: {error}"),
+        }
+    }
 
     loop {
         println!("Pick an action:");
-        println!("[h]ello, [s]et active, [p]ixel, [r]gb, [R]ainbow!!!, [q]uit");
+        println!("[h]ello, [c]onnect by name, [g]roup, [s]et active, [p]ixel, [r]un an effect, [R]ainbow!!!, [f]ile, [x]Lights FSEQ, [S]tatus, [C]onfig, [z]ones, [L]ayout, [o]ta, [q]uit");
 
         input.clear();
-        std::io::stdin().read_line(&mut input).expect("Failed to read line");
-        let i = input.trim();
-        match i.chars().next().unwrap() {
+        if std::io::stdin().read_line(&mut input).expect("Failed to read line") == 0 {
+            break;
+        }
+        let Some(action) = input.trim().chars().next() else {
+            continue;
+        };
+        match action {
             'h'=> {
-                cmessage = ClientMessages::Hello.into();
-                udp.send_to(&cmessage, broadcast).expect("Failed to send hello");
-                while let Ok((size, addr)) = udp.recv_from(&mut smessage) {
-                    if let Ok(ServerMessages::Hello) = ServerMessages::try_from(&smessage[..]) {
-                        println!("Found server at {addr}");
-                        server = addr;
-                        break;
+                let mut servers = client.discover(Duration::from_millis(500)).expect("Failed to send hello");
+                if servers.is_empty() {
+                    println!("No answer to the broadcast, looking for mDNS services");
+                    servers = client.browse(Duration::from_millis(500)).unwrap_or_default();
+                }
+                for (index, server) in servers.iter().enumerate() {
+                    println!("[{index}] {} ({}) at {}", server.advertisement.name, server.advertisement.id_string(), server.addr);
+                }
+                let server = match servers.len() {
+                    0 => {
+                        println!("No server found");
+                        continue;
+                    },
+                    1 => &servers[0],
+                    _ => {
+                        input.clear();
+                        println!("Enter server number");
+                        std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                        let Some(server) = input.trim().parse::<usize>().ok().and_then(|index| servers.get(index)) else {
+                            println!("Invalid input");
+                            continue;
+                        };
+                        server
                     }
+                };
+                match client.connect(server.addr) {
+                    Ok(_) => {
+                        group.clear();
+                        remember_server(&server.advertisement);
+                    },
+                    Err(error) => println!("Failed to connect to the server: {error}"),
+                }
+            },
+            'c' => {
+                input.clear();
+                println!("Enter server name or id");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                match client.connect_by_name(input.trim(), Duration::from_millis(500)) {
+                    Ok(server) => {
+                        println!("Connected to {} at {}", server.advertisement.name, server.addr);
+                        group.clear();
+                        remember_server(&server.advertisement);
+                    },
+                    Err(error) => println!("Failed to connect to the server: {error}"),
+                }
+            },
+            'g' => {
+                match client.join_group((MULTICAST_GROUP, PORT).into(), Duration::from_millis(500)) {
+                    Ok(servers) => {
+                        for server in &servers {
+                            println!("{} ({}) at {} with {} LEDs", server.advertisement.name, server.advertisement.id_string(), server.addr, server.advertisement.led_count);
+                        }
+                        println!("Joined the group of {} servers at {MULTICAST_GROUP}", servers.len());
+                        group = servers;
+                    },
+                    Err(error) => println!("Failed to join the group: {error}"),
                 }
             },
             's' => {
-                device = Uniform::new(0, 64).sample(&mut rng);
+                let device = match client.claim() {
+                    Ok(device) => device,
+                    // Groups and older servers do not assign ids
+                    Err(error @ (Error::Unsupported | Error::Timeout)) => {
+                        println!("No device id assigned ({error}), picking a random one");
+                        DeviceId::new(Uniform::new(0, DeviceId::COUNT).sample(&mut rng)).unwrap()
+                    },
+                    Err(error) => {
+                        println!("Failed to claim a device id: {error}");
+                        continue;
+                    },
+                };
                 println!("Sending set active to device {}", device);
-                cmessage = ClientMessages::set_active(device).into();
-                udp.send_to(&cmessage, server).expect("Failed to send set active");
+                client.acquire(device).expect("Failed to send set active");
             },
             'p' => {
                 input.clear();
@@ -80,30 +624,184 @@ fn main() {
                     println!("Invalid input");
                     continue;
                 };
-                cmessage = ClientMessages::set_pixel(device, pixel, r, g, b).into();
-                udp.send_to(&cmessage, server).expect("Failed to send set pixel");
+                if let Err(error) = client.set_pixel(pixel, (r, g, b)) {
+                    println!("Failed to send set pixel: {error}");
+                }
             },
             'r' => {
-                let start = SystemTime::now();
-                let mut dur = Duration::from_secs(0);
-                while dur < Duration::from_secs(15) {
-                    let r = gaussian(dur.as_secs_f64(), 2.5) * 255.0;
-                    let g = gaussian(dur.as_secs_f64(), 7.5) * 255.0;
-                    let b = gaussian(dur.as_secs_f64(), 12.5) * 255.0;
-                    let r = r as u8;
-                    let g = g as u8;
-                    let b = b as u8;
-                    let mut pix = [0; MAX_LED_COUNT * 3];
-                    for i in 0..PIXEL_COUNT {
-                        pix[i * 3] = r;
-                        pix[i * 3 + 1] = g;
-                        pix[i * 3 + 2] = b;
-                    }
-                    cmessage = ClientMessages::send_pixels(device, pix).into();
-                    udp.send_to(&cmessage, server).expect("Failed to send set pixel");
-                    std::thread::sleep(Duration::from_millis(16));
-
-                    dur = start.elapsed().unwrap();
+                input.clear();
+                println!("Enter effect: rainbow, chase, breathe, twinkle, fire, meteor, plasma, comet or gradient");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                let Ok(kind) = <EffectKind as clap::ValueEnum>::from_str(input.trim(), true) else {
+                    println!("Invalid input");
+                    continue;
+                };
+                if let Err(error) = play_effect(&client, &group, PIXEL_COUNT, kind, None, Duration::from_secs(15)) {
+                    println!("Failed to send the frame: {error}");
+                }
+            },
+            'R' => {
+                if let Err(error) = play_effect(&client, &group, PIXEL_COUNT, EffectKind::Rainbow, None, Duration::from_secs(15)) {
+                    println!("Failed to send the frame: {error}");
+                }
+            },
+            'f' => {
+                input.clear();
+                println!("Enter sequence file path");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                let path = PathBuf::from(input.trim());
+                input.clear();
+                println!("Enter number of loops (0 to loop forever)");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                let Ok(loops) = input.trim().parse::<usize>() else {
+                    println!("Invalid input");
+                    continue;
+                };
+                if let Err(error) = play_sequence(&client, &path, loops) {
+                    println!("Failed to play the sequence: {error}");
+                }
+            },
+            'x' => {
+                input.clear();
+                println!("Enter FSEQ file path");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                let path = PathBuf::from(input.trim());
+                input.clear();
+                println!("Enter start channel (1 for the first channel)");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                let Ok(start_channel) = input.trim().parse::<u32>() else {
+                    println!("Invalid input");
+                    continue;
+                };
+                input.clear();
+                println!("Enter number of LEDs of the strip");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                let Some(led_count) = input.trim().parse::<usize>().ok().filter(|count| (1..=MAX_LED_COUNT).contains(count)) else {
+                    println!("Invalid input");
+                    continue;
+                };
+                input.clear();
+                println!("Enter number of loops (0 to loop forever)");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                let Ok(loops) = input.trim().parse::<usize>() else {
+                    println!("Invalid input");
+                    continue;
+                };
+                if let Err(error) = play_fseq(&client, &path, start_channel, led_count, loops) {
+                    println!("Failed to play the FSEQ file: {error}");
+                }
+            },
+            'S' => {
+                input.clear();
+                println!("Enter number of polls, one per second");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                let Ok(polls) = input.trim().parse::<usize>() else {
+                    println!("Invalid input");
+                    continue;
+                };
+                if let Err(error) = poll_status(&client, polls) {
+                    println!("Failed to get the status: {error}");
+                }
+            },
+            'C' => {
+                let config = match client.config() {
+                    Ok(config) => config,
+                    Err(error) => {
+                        println!("Failed to get the config: {error}");
+                        continue;
+                    },
+                };
+                println!(
+                    "leds={} order={:?} pin={} name={}",
+                    config.led_count, config.color_order, config.output_pin, config.name
+                );
+                input.clear();
+                println!("Enter the changes as leds=, order=, pin= or name=, empty to keep the config");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                if input.trim().is_empty() {
+                    continue;
+                }
+                let config = match edit_config(&config, input.trim()) {
+                    Ok(config) => config,
+                    Err(error) => {
+                        println!("Invalid input: {error}");
+                        continue;
+                    },
+                };
+                match client.set_config(&config) {
+                    Ok(()) => println!("Config stored"),
+                    Err(error) => println!("Failed to set the config: {error}"),
+                }
+            },
+            'z' => {
+                match client.segments() {
+                    Ok(segments) => {
+                        for (index, segment) in segments.iter().enumerate() {
+                            let reversed = if segment.reversed { ", reversed" } else { "" };
+                            println!("[{index}] {} from LED {} for {} LEDs{reversed}", segment.name, segment.start, segment.len);
+                        }
+                    },
+                    Err(error) => {
+                        println!("Failed to get the segments: {error}");
+                        continue;
+                    },
+                }
+                input.clear();
+                println!("Enter the segments as name:start:len or name:start:len:r for reversed ones, empty to keep them, the device has to be [s]et active first");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                if input.trim().is_empty() {
+                    continue;
+                }
+                let segments = match parse_segments(input.trim()) {
+                    Ok(segments) => segments,
+                    Err(error) => {
+                        println!("Invalid input: {error}");
+                        continue;
+                    },
+                };
+                match client.set_segments(&segments) {
+                    Ok(()) => println!("Segments stored"),
+                    Err(error) => println!("Failed to set the segments: {error}"),
+                }
+            },
+            'L' => {
+                let layout = match client.layout() {
+                    Ok(layout) => layout,
+                    Err(error) => {
+                        println!("Failed to get the layout: {error}");
+                        continue;
+                    },
+                };
+                match &layout {
+                    Layout::Unknown => println!("Unknown layout"),
+                    Layout::Rectangle(rectangle) => println!("{rectangle:?}"),
+                    Layout::Points(points) => println!("{} LEDs placed by their coordinates", points.len()),
+                }
+                input.clear();
+                println!("Enter the changes as top=, right=, bottom=, left=, start=, direction=, corner_gap= or bottom_gap=, empty to keep the layout, the device has to be [s]et active first");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                if input.trim().is_empty() {
+                    continue;
+                }
+                let layout = match edit_layout(&layout, input.trim()) {
+                    Ok(layout) => layout,
+                    Err(error) => {
+                        println!("Invalid input: {error}");
+                        continue;
+                    },
+                };
+                match client.set_layout(&layout) {
+                    Ok(()) => println!("Layout stored"),
+                    Err(error) => println!("Failed to set the layout: {error}"),
+                }
+            },
+            'o' => {
+                input.clear();
+                println!("Enter firmware image path, the device has to be [s]et active first");
+                std::io::stdin().read_line(&mut input).expect("Failed to read line");
+                match update_firmware(&client, Path::new(input.trim())) {
+                    Ok(()) => println!("Firmware updated, the server is restarting"),
+                    Err(error) => println!("Failed to update the firmware: {error}"),
                 }
             },
             'q' => {
@@ -115,4 +813,6 @@ fn main() {
             }
         }
     }
+
+    client.close();
 }
@@ -0,0 +1,115 @@
+use std::f64::consts::PI;
+
+use clap::ValueEnum;
+
+/// Physical arrangement of the emulated LEDs
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Layout {
+    /// A straight strip, wrapped on several rows if needed
+    Strip,
+    /// A ring, the first LED is at the top and the LEDs go clockwise
+    Ring,
+    /// A rectangle around a screen, the first LED is at the top left corner and the LEDs go clockwise
+    Frame,
+}
+
+/// Number of LEDs on a row of a wrapped strip
+const STRIP_WIDTH: usize = 64;
+
+/// Position of every LED on a character grid
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    /// Column and row of each LED, in order
+    pub positions: Vec<(usize, usize)>,
+}
+
+impl Layout {
+    /// Places `count` LEDs on a grid
+    ///
+    /// For the frame layout `width` and `height` are the number of LEDs on the horizontal and vertical sides,
+    /// when they are not given the LEDs are spread around a 16:9 screen
+    pub fn grid(self, count: usize, width: Option<usize>, height: Option<usize>) -> Grid {
+        match self {
+            Layout::Strip => {
+                let positions = (0..count).map(|i| (i % STRIP_WIDTH, i / STRIP_WIDTH)).collect();
+                Grid {
+                    width: count.clamp(1, STRIP_WIDTH),
+                    height: count.div_ceil(STRIP_WIDTH).max(1),
+                    positions,
+                }
+            },
+            Layout::Ring => {
+                let radius = (count as f64 / (2.0 * PI)).ceil().max(3.0);
+                let size = 2 * radius as usize + 1;
+                let positions = (0..count)
+                    .map(|i| {
+                        let angle = 2.0 * PI * i as f64 / count as f64 - PI / 2.0;
+                        let x = radius + radius * angle.cos();
+                        let y = radius + radius * angle.sin();
+                        (x.round() as usize, y.round() as usize)
+                    })
+                    .collect();
+                Grid { width: size, height: size, positions }
+            },
+            Layout::Frame => {
+                let (width, height) = frame_sides(count, width, height);
+                let mut positions = Vec::with_capacity(count);
+                positions.extend((0..width).map(|x| (x + 1, 0)));
+                positions.extend((0..height).map(|y| (width + 1, y + 1)));
+                positions.extend((0..width).rev().map(|x| (x + 1, height + 1)));
+                positions.extend((0..height).rev().map(|y| (0, y + 1)));
+                positions.truncate(count);
+                Grid {
+                    width: width + 2,
+                    height: height + 2,
+                    positions,
+                }
+            },
+        }
+    }
+}
+
+/// Number of LEDs on the horizontal and vertical sides of a frame
+fn frame_sides(count: usize, width: Option<usize>, height: Option<usize>) -> (usize, usize) {
+    match (width, height) {
+        (Some(width), Some(height)) => (width, height),
+        (Some(width), None) => (width, (count.saturating_sub(2 * width) / 2).max(1)),
+        (None, Some(height)) => ((count.saturating_sub(2 * height) / 2).max(1), height),
+        (None, None) => {
+            let width = (count * 16 / 50).max(1);
+            (width, (count.saturating_sub(2 * width) / 2).max(1))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_wraps() {
+        let grid = Layout::Strip.grid(70, None, None);
+        assert_eq!((grid.width, grid.height), (64, 2));
+        assert_eq!(grid.positions[65], (1, 1));
+    }
+
+    #[test]
+    fn test_ring_starts_at_the_top() {
+        let grid = Layout::Ring.grid(24, None, None);
+        assert_eq!(grid.positions[0], (grid.width / 2, 0));
+        assert!(grid.positions.iter().all(|&(x, y)| x < grid.width && y < grid.height));
+    }
+
+    #[test]
+    fn test_frame_goes_clockwise() {
+        let grid = Layout::Frame.grid(14, Some(4), Some(3));
+        assert_eq!((grid.width, grid.height), (6, 5));
+        assert_eq!(grid.positions[0], (1, 0));
+        assert_eq!(grid.positions[4], (5, 1));
+        assert_eq!(grid.positions[7], (4, 4));
+        assert_eq!(grid.positions[11], (0, 3));
+        assert_eq!(grid.positions.len(), 14);
+    }
+}
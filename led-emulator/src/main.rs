@@ -0,0 +1,194 @@
+mod layout;
+
+use std::fmt::Write;
+use std::io::Write as _;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use udp_leds::constants::{MAX_LED_COUNT, MAX_SEALED_LENGTH, PORT};
+use udp_leds::crypto::{Cipher, Key};
+use udp_leds::config::Config;
+use udp_leds::server::{Advertisement, IdleMode};
+use udp_leds::{Frame, LedSink, Rgb, Server};
+
+use crate::layout::{Grid, Layout};
+
+/// Emulates a LED strip controller in the terminal
+#[derive(Debug, Parser)]
+struct Args {
+    /// Number of LEDs of the strip
+    #[arg(short, long, default_value_t = 64, value_parser = clap::value_parser!(u16).range(1..=MAX_LED_COUNT as i64))]
+    leds: u16,
+    /// Arrangement of the LEDs
+    #[arg(long, value_enum, default_value_t = Layout::Strip)]
+    layout: Layout,
+    /// Number of LEDs on the top and bottom sides of the frame layout
+    #[arg(long)]
+    width: Option<usize>,
+    /// Number of LEDs on the left and right sides of the frame layout
+    #[arg(long)]
+    height: Option<usize>,
+    /// Name advertised in the hello messages
+    #[arg(short, long, default_value = "emulator")]
+    name: String,
+    /// Address to listen on
+    #[arg(short, long, default_value = "0.0.0.0")]
+    bind: String,
+    /// Port to listen on
+    #[arg(short, long, default_value_t = PORT)]
+    port: u16,
+    /// Maximum refresh rate of the terminal
+    #[arg(long, default_value_t = 30)]
+    fps: u8,
+    /// Seconds of silence after which the active device is released, 0 to keep it forever
+    #[arg(long, default_value_t = 0.0)]
+    timeout: f64,
+    /// What the strip shows once the active device is released
+    #[arg(long, value_enum, default_value_t = Idle::Off)]
+    idle: Idle,
+    /// Multicast group to join, the controllers use 239.255.82.76
+    #[arg(short, long)]
+    group: Option<Ipv4Addr>,
+    /// Only accept the messages encrypted with this key (64 hexadecimal digits)
+    #[arg(long)]
+    key: Option<Key>,
+    /// Id of the key, sent along with the encrypted messages
+    #[arg(long, default_value_t = 0)]
+    key_id: u8,
+}
+
+/// Idle modes selectable from the command line
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Idle {
+    Off,
+    Breathe,
+    Rainbow,
+}
+
+impl From<Idle> for IdleMode {
+    fn from(value: Idle) -> Self {
+        match value {
+            Idle::Off => IdleMode::Off,
+            Idle::Breathe => IdleMode::Breathe(Rgb::WHITE),
+            Idle::Rainbow => IdleMode::Rainbow,
+        }
+    }
+}
+
+/// Frame remembering if it changed since it was last drawn
+struct Strip {
+    frame: Frame,
+    dirty: bool,
+}
+
+impl LedSink for Strip {
+    fn set_pixels(&mut self, pixels: &[u8]) {
+        LedSink::set_pixels(&mut self.frame, pixels);
+        self.dirty = true;
+    }
+
+    fn set_pixel(&mut self, index: usize, color: Rgb) {
+        LedSink::set_pixel(&mut self.frame, index, color);
+        self.dirty = true;
+    }
+
+    fn configure(&mut self, config: &Config) {
+        self.frame.resize(config.led_count as usize);
+        self.dirty = true;
+    }
+}
+
+/// Draws the strip with ANSI truecolor blocks, each LED takes two columns to look square
+fn draw(grid: &Grid, frame: &Frame, status: &str) -> String {
+    let mut cells = vec![vec![None; grid.width]; grid.height];
+    for (index, &(x, y)) in grid.positions.iter().enumerate() {
+        cells[y][x] = Some(frame.get(index));
+    }
+
+    let mut out = String::from("\x1b[H");
+    for row in cells {
+        for cell in row {
+            match cell {
+                Some(Rgb { r, g, b }) => write!(out, "\x1b[38;2;{r};{g};{b}m██").unwrap(),
+                None => out.push_str("  "),
+            }
+        }
+        out.push_str("\x1b[0m\x1b[K\n");
+    }
+    writeln!(out, "\x1b[K{status}").unwrap();
+    out
+}
+
+fn main() {
+    let args = Args::parse();
+    let count = args.leds as usize;
+    let mut grid = args.layout.grid(count, args.width, args.height);
+    let interval = Duration::from_secs(1) / args.fps.max(1) as u32;
+
+    let udp = UdpSocket::bind((args.bind.as_str(), args.port)).expect("Failed to bind to port");
+    udp.set_read_timeout(Some(interval)).expect("Failed to set read timeout");
+    if let Some(group) = args.group {
+        udp.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED).expect("Failed to join the multicast group");
+    }
+    let mut server = Server::new(
+        Strip { frame: Frame::new(count), dirty: true },
+        Advertisement {
+            led_count: args.leds,
+            max_fps: args.fps,
+            id: [0x02, 0, 0, 0, (args.port >> 8) as u8, args.port as u8],
+            name: args.name.clone(),
+        },
+    );
+    if args.timeout > 0.0 {
+        server.set_timeout(Some(Duration::from_secs_f64(args.timeout)));
+    }
+    server.set_idle_mode(args.idle.into());
+    if let Some(key) = &args.key {
+        server.set_cipher(Some(Cipher::new(args.key_id, key)));
+    }
+
+    let mut buf = [0; MAX_SEALED_LENGTH];
+    let mut last_draw = Instant::now() - interval;
+    let mut last_error = String::new();
+    print!("\x1b[2J");
+
+    loop {
+        // Wake up in time for the next timed frame
+        let wait = server.next_frame_in(Instant::now()).map_or(interval, |next| next.min(interval));
+        udp.set_read_timeout(Some(wait.max(Duration::from_millis(1)))).expect("Failed to set read timeout");
+        match udp.recv_from(&mut buf) {
+            Ok((size, addr)) => match server.respond(&buf[..size], addr) {
+                Ok(Some(response)) => {
+                    if let Err(error) = udp.send_to(&response, addr) {
+                        last_error = format!("Failed to answer {addr}: {error}");
+                    }
+                },
+                Ok(None) => {},
+                Err(error) => last_error = format!("Malformed message from {addr}: {error}"),
+            },
+            Err(error) if matches!(error.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {},
+            Err(error) => panic!("Failed to receive: {error}"),
+        }
+        server.tick();
+        // The emulator does not persist the config, a restart goes back to the command line options
+        if let Some(config) = server.take_config() {
+            grid = args.layout.grid(config.led_count as usize, args.width, args.height);
+            print!("\x1b[2J");
+        }
+
+        if server.sink().dirty && last_draw.elapsed() >= interval {
+            let active = match server.active() {
+                Some(device) => device.to_string(),
+                None => "none".to_string(),
+            };
+            let name = &server.advertisement().name;
+            let status = format!("{name} on {}:{} | active device: {active} | {last_error}", args.bind, args.port);
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(draw(&grid, &server.sink().frame, &status).as_bytes()).expect("Failed to draw");
+            stdout.flush().expect("Failed to draw");
+            server.sink_mut().dirty = false;
+            last_draw = Instant::now();
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use led_tools::describe_record;
+use udp_leds::capture::{CaptureWriter, Record};
+use udp_leds::constants::{MAX_SEALED_LENGTH, PORT};
+
+/// Logs every udp-leds datagram to a capture file
+///
+/// Without `--forward` the datagrams reaching this host are captured.
+/// With `--forward` the tool acts as a proxy: the clients talk to it, the datagrams are relayed to the
+/// controller and its responses are relayed back, so both directions end up in the capture.
+#[derive(Debug, Parser)]
+struct Args {
+    /// Capture file to write
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Address to listen on
+    #[arg(short, long, default_value = "0.0.0.0")]
+    bind: String,
+    /// Port to listen on
+    #[arg(short, long, default_value_t = PORT)]
+    port: u16,
+    /// Controller to relay the datagrams to
+    #[arg(short, long)]
+    forward: Option<SocketAddr>,
+    /// Do not print the datagrams
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+    let file = File::create(&args.output).expect("Failed to create the capture file");
+    let mut capture = CaptureWriter::new(BufWriter::new(file)).expect("Failed to write the capture header");
+
+    let udp = UdpSocket::bind((args.bind.as_str(), args.port)).expect("Failed to bind to port");
+    udp.set_read_timeout(Some(Duration::from_millis(5))).expect("Failed to set read timeout");
+
+    // One socket per client when relaying, so the responses of the controller can be routed back
+    let mut upstreams: HashMap<SocketAddr, UdpSocket> = HashMap::new();
+    let start = Instant::now();
+    let mut buf = [0; MAX_SEALED_LENGTH];
+
+    let record = |capture: &mut CaptureWriter<_>, source: SocketAddr, datagram: &[u8]| {
+        let record = Record { at: start.elapsed(), source, datagram: datagram.to_vec() };
+        if !args.quiet {
+            println!("{}", describe_record(&record));
+        }
+        capture.write(&record).and_then(|_| capture.flush()).expect("Failed to write the capture");
+    };
+
+    loop {
+        if let Ok((size, addr)) = udp.recv_from(&mut buf) {
+            record(&mut capture, addr, &buf[..size]);
+            if let Some(controller) = args.forward {
+                let upstream = upstreams.entry(addr).or_insert_with(|| {
+                    let socket = UdpSocket::bind("0.0.0.0:0").expect("Failed to create a relay socket");
+                    socket.set_nonblocking(true).expect("Failed to set non blocking");
+                    socket
+                });
+                if let Err(error) = upstream.send_to(&buf[..size], controller) {
+                    eprintln!("Failed to relay to {controller}: {error}");
+                }
+            }
+        }
+
+        for (client, upstream) in &upstreams {
+            while let Ok((size, addr)) = upstream.recv_from(&mut buf) {
+                record(&mut capture, addr, &buf[..size]);
+                if let Err(error) = udp.send_to(&buf[..size], client) {
+                    eprintln!("Failed to relay to {client}: {error}");
+                }
+            }
+        }
+    }
+}
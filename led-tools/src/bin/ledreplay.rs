@@ -0,0 +1,78 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use led_tools::describe_record;
+use udp_leds::capture::{CaptureReader, Decoded};
+use udp_leds::client::ClientMessages;
+
+/// Re-sends the client datagrams of a capture file to a controller
+#[derive(Debug, Parser)]
+struct Args {
+    /// Capture file to replay
+    input: PathBuf,
+    /// Controller to send the datagrams to
+    #[arg(short, long)]
+    target: SocketAddr,
+    /// Speed multiplier, 2 plays the capture twice as fast
+    #[arg(short, long, default_value_t = 1.0)]
+    speed: f64,
+    /// Wait for enter before sending each frame
+    #[arg(long)]
+    step: bool,
+    /// Do not print the datagrams
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.speed <= 0.0 || !args.speed.is_finite() {
+        eprintln!("The speed must be a positive number");
+        std::process::exit(2);
+    }
+
+    let file = File::open(&args.input).expect("Failed to open the capture file");
+    let capture = CaptureReader::new(BufReader::new(file)).expect("Invalid capture file");
+    let udp = UdpSocket::bind("0.0.0.0:0").expect("Failed to create the socket");
+
+    let start = Instant::now();
+    let mut first = None;
+    let mut input = String::new();
+    for record in capture {
+        let record = record.expect("Failed to read the capture");
+        if !record.is_client() {
+            continue;
+        }
+
+        let is_frame = matches!(record.decode(), Ok(Decoded::Client(ClientMessages::SendPixels(_, _))));
+        if args.step && is_frame {
+            println!("{}", describe_record(&record));
+            println!("Press enter to send the frame");
+            input.clear();
+            if std::io::stdin().read_line(&mut input).expect("Failed to read line") == 0 {
+                break;
+            }
+        } else if !args.step {
+            let first = *first.get_or_insert(record.at);
+            let due = (record.at - first).div_f64(args.speed);
+            let elapsed = start.elapsed();
+            if due > elapsed {
+                std::thread::sleep(due - elapsed);
+            }
+            if !args.quiet {
+                println!("{}", describe_record(&record));
+            }
+        }
+
+        if let Err(error) = udp.send_to(&record.datagram, args.target) {
+            eprintln!("Failed to send the datagram: {error}");
+        }
+    }
+
+    // Give the last datagrams a chance to leave before the socket is closed
+    std::thread::sleep(Duration::from_millis(10));
+}
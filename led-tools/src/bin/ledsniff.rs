@@ -0,0 +1,114 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Instant;
+
+use clap::Parser;
+use led_tools::{describe, pixels, preview, Opcode};
+use udp_leds::capture::{CaptureReader, Record};
+use udp_leds::constants::{MAX_SEALED_LENGTH, PORT};
+
+/// Pretty-prints the udp-leds datagrams received on the port or stored in a capture file
+///
+/// Only the datagrams reaching this host are seen, so a controller running on the same host
+/// keeps the port for itself: use `ledcapture --forward` and read the capture instead.
+#[derive(Debug, Parser)]
+struct Args {
+    /// Capture file to read instead of listening
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+    /// Address to listen on
+    #[arg(short, long, default_value = "0.0.0.0")]
+    bind: String,
+    /// Port to listen on
+    #[arg(short, long, default_value_t = PORT)]
+    port: u16,
+    /// Only show the datagrams sent from this address or address:port, can be repeated
+    #[arg(short, long)]
+    source: Vec<Source>,
+    /// Only show these kinds of datagrams, can be repeated
+    #[arg(short, long, value_enum)]
+    opcode: Vec<Opcode>,
+    /// Maximum number of pixels previewed per datagram
+    #[arg(long, default_value_t = 32)]
+    preview: usize,
+}
+
+/// Source filter, either a whole host or a single socket
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    Host(IpAddr),
+    Socket(SocketAddr),
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Ok(addr) = value.parse() {
+            return Ok(Source::Socket(addr));
+        }
+        value.parse().map(Source::Host).map_err(|_| format!("invalid address: {value}"))
+    }
+}
+
+impl Source {
+    fn matches(&self, addr: SocketAddr) -> bool {
+        match *self {
+            Source::Host(ip) => addr.ip() == ip,
+            Source::Socket(socket) => addr == socket,
+        }
+    }
+}
+
+fn print(args: &Args, record: &Record) {
+    if !args.source.is_empty() && !args.source.iter().any(|source| source.matches(record.source)) {
+        return;
+    }
+    let decoded = record.decode();
+    let opcode = decoded.as_ref().map_or(Opcode::Invalid, Opcode::of);
+    if !args.opcode.is_empty() && !args.opcode.contains(&opcode) {
+        return;
+    }
+
+    let prefix = format!("{:>10.3}s {:<21}", record.at.as_secs_f64(), record.source);
+    match decoded {
+        Ok(decoded) => {
+            // The description carries the device id of the messages coming from a device
+            let preview = pixels(&decoded).map(|pixels| preview(&pixels, args.preview)).unwrap_or_default();
+            println!("{prefix} {:<12} {} {preview}", format!("{opcode:?}"), describe(&decoded));
+        },
+        Err(error) => {
+            let head: String = record.datagram.iter().take(16).map(|byte| format!("{byte:02x}")).collect();
+            println!("{prefix} \x1b[31merror: {error}\x1b[0m ({} bytes: {head})", record.datagram.len());
+        },
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if let Some(input) = &args.input {
+        let file = File::open(input).expect("Failed to open the capture file");
+        let capture = CaptureReader::new(BufReader::new(file)).expect("Invalid capture file");
+        for record in capture {
+            print(&args, &record.expect("Failed to read the capture"));
+        }
+        return;
+    }
+
+    let udp = UdpSocket::bind((args.bind.as_str(), args.port)).expect("Failed to bind to port");
+    let start = Instant::now();
+    let mut buf = [0; MAX_SEALED_LENGTH];
+    loop {
+        match udp.recv_from(&mut buf) {
+            Ok((size, source)) => {
+                let record = Record { at: start.elapsed(), source, datagram: buf[..size].to_vec() };
+                print(&args, &record);
+            },
+            Err(error) => eprintln!("Failed to receive: {error}"),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::fmt::Write;
+
+use udp_leds::capture::{Decoded, Record};
+use udp_leds::client::ClientMessages;
+use udp_leds::config::Config;
+use udp_leds::layout::Layout;
+use udp_leds::segment::Segment;
+use udp_leds::server::ServerMessages;
+use udp_leds::Rgb;
+
+/// Kind of a datagram, used to filter the traffic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Opcode {
+    Hello,
+    Claim,
+    SetActive,
+    SendPixels,
+    SetPixel,
+    Heartbeat,
+    GroupPixels,
+    GetStatus,
+    TimeRequest,
+    TimedPixels,
+    GetConfig,
+    SetConfig,
+    OtaBegin,
+    OtaChunk,
+    OtaVerify,
+    OtaCommit,
+    OtaReboot,
+    GetSegments,
+    SetSegments,
+    SetSegmentActive,
+    SegmentPixels,
+    GetLayout,
+    SetLayout,
+    ServerHello,
+    Welcome,
+    Status,
+    TimeResponse,
+    Config,
+    OtaStatus,
+    Segments,
+    Layout,
+    /// Datagrams that could not be decoded
+    Invalid,
+}
+
+impl Opcode {
+    pub fn of(decoded: &Decoded) -> Self {
+        match decoded {
+            Decoded::Client(ClientMessages::Hello) => Opcode::Hello,
+            Decoded::Client(ClientMessages::Claim(_)) => Opcode::Claim,
+            Decoded::Client(ClientMessages::SetActive(_)) => Opcode::SetActive,
+            Decoded::Client(ClientMessages::SendPixels(_, _)) => Opcode::SendPixels,
+            Decoded::Client(ClientMessages::SetPixel(_, _, _)) => Opcode::SetPixel,
+            Decoded::Client(ClientMessages::Heartbeat(_)) => Opcode::Heartbeat,
+            Decoded::Client(ClientMessages::SendGroupPixels(_, _, _)) => Opcode::GroupPixels,
+            Decoded::Server(ServerMessages::Hello(_)) => Opcode::ServerHello,
+            Decoded::Client(ClientMessages::GetStatus) => Opcode::GetStatus,
+            Decoded::Server(ServerMessages::Welcome(_, _)) => Opcode::Welcome,
+            Decoded::Server(ServerMessages::Status(_)) => Opcode::Status,
+            Decoded::Client(ClientMessages::TimeRequest(_)) => Opcode::TimeRequest,
+            Decoded::Client(ClientMessages::SendTimedPixels(_, _, _)) => Opcode::TimedPixels,
+            Decoded::Server(ServerMessages::TimeResponse(_, _, _)) => Opcode::TimeResponse,
+            Decoded::Client(ClientMessages::GetConfig) => Opcode::GetConfig,
+            Decoded::Client(ClientMessages::SetConfig(_)) => Opcode::SetConfig,
+            Decoded::Server(ServerMessages::Config(_)) => Opcode::Config,
+            Decoded::Client(ClientMessages::OtaBegin(_, _)) => Opcode::OtaBegin,
+            Decoded::Client(ClientMessages::OtaChunk(_, _)) => Opcode::OtaChunk,
+            Decoded::Client(ClientMessages::OtaVerify) => Opcode::OtaVerify,
+            Decoded::Client(ClientMessages::OtaCommit) => Opcode::OtaCommit,
+            Decoded::Client(ClientMessages::OtaReboot) => Opcode::OtaReboot,
+            Decoded::Server(ServerMessages::OtaStatus(_)) => Opcode::OtaStatus,
+            Decoded::Client(ClientMessages::GetSegments) => Opcode::GetSegments,
+            Decoded::Client(ClientMessages::SetSegments(_)) => Opcode::SetSegments,
+            Decoded::Client(ClientMessages::SetSegmentActive(_, _)) => Opcode::SetSegmentActive,
+            Decoded::Client(ClientMessages::SendSegmentPixels(_, _, _)) => Opcode::SegmentPixels,
+            Decoded::Server(ServerMessages::Segments(_)) => Opcode::Segments,
+            Decoded::Client(ClientMessages::GetLayout) => Opcode::GetLayout,
+            Decoded::Client(ClientMessages::SetLayout(_)) => Opcode::SetLayout,
+            Decoded::Server(ServerMessages::Layout(_)) => Opcode::Layout,
+        }
+    }
+}
+
+/// Pixels of a message as ANSI truecolor blocks, at most `max` of them
+pub fn preview(pixels: &[u8], max: usize) -> String {
+    let mut out = String::new();
+    for pixel in pixels.chunks_exact(3).take(max) {
+        write!(out, "\x1b[38;2;{};{};{}m█", pixel[0], pixel[1], pixel[2]).unwrap();
+    }
+    if !out.is_empty() {
+        out.push_str("\x1b[0m");
+    }
+    if pixels.len() / 3 > max {
+        out.push('…');
+    }
+    out
+}
+
+/// Pixels carried by a message, if any
+pub fn pixels(decoded: &Decoded) -> Option<Vec<u8>> {
+    match decoded {
+        Decoded::Client(ClientMessages::SendPixels(_, frame)) => Some(frame.to_bytes()),
+        Decoded::Client(ClientMessages::SetPixel(_, _, color)) => Some(<[u8; 3]>::from(*color).to_vec()),
+        Decoded::Client(ClientMessages::SendGroupPixels(_, _, frame)) => Some(frame.to_bytes()),
+        Decoded::Client(ClientMessages::SendTimedPixels(_, _, frame)) => Some(frame.to_bytes()),
+        Decoded::Client(ClientMessages::SendSegmentPixels(_, _, frame)) => Some(frame.to_bytes()),
+        _ => None,
+    }
+}
+
+/// One line summary of a decoded message
+pub fn describe(decoded: &Decoded) -> String {
+    match decoded {
+        Decoded::Client(ClientMessages::Hello) => "client hello".to_string(),
+        Decoded::Client(ClientMessages::Claim(token)) => format!("claim token={token}"),
+        Decoded::Client(ClientMessages::SetActive(device)) => format!("set active device={device}"),
+        Decoded::Client(ClientMessages::SendPixels(device, frame)) => {
+            let lit = frame.iter().filter(|&&pixel| pixel != Rgb::BLACK).count();
+            format!("send pixels device={device} leds={} lit={lit}", frame.len())
+        },
+        Decoded::Client(ClientMessages::SetPixel(device, index, Rgb { r, g, b })) => {
+            format!("set pixel device={device} index={index} rgb=({r}, {g}, {b})")
+        },
+        Decoded::Client(ClientMessages::Heartbeat(device)) => format!("heartbeat device={device}"),
+        Decoded::Client(ClientMessages::SendGroupPixels(device, slices, frame)) => {
+            format!("send group pixels device={device} slices={} pixels={}", slices.len(), frame.len())
+        },
+        Decoded::Server(ServerMessages::Hello(advertisement)) => format!(
+            "server hello name={:?} id={} leds={} max_fps={}",
+            advertisement.name,
+            advertisement.id_string(),
+            advertisement.led_count,
+            advertisement.max_fps
+        ),
+        Decoded::Client(ClientMessages::GetStatus) => "get status".to_string(),
+        Decoded::Server(ServerMessages::Welcome(advertisement, device)) => {
+            format!("welcome name={:?} device={device}", advertisement.name)
+        },
+        Decoded::Server(ServerMessages::Status(status)) => format!(
+            "status uptime={}s received={} applied={} dropped={} fps={:.1} error={:?}",
+            status.uptime.as_secs(),
+            status.frames_received,
+            status.frames_applied,
+            status.frames_dropped,
+            status.fps,
+            status.last_error
+        ),
+        Decoded::Client(ClientMessages::TimeRequest(t1)) => format!("time request t1={t1}"),
+        Decoded::Client(ClientMessages::SendTimedPixels(device, pts, frame)) => {
+            format!("send timed pixels device={device} pts={pts} leds={}", frame.len())
+        },
+        Decoded::Server(ServerMessages::TimeResponse(t1, t2, t3)) => format!("time response t1={t1} t2={t2} t3={t3}"),
+        Decoded::Client(ClientMessages::GetConfig) => "get config".to_string(),
+        Decoded::Client(ClientMessages::SetConfig(config)) => format!("set config {}", describe_config(config)),
+        Decoded::Server(ServerMessages::Config(config)) => format!("config {}", describe_config(config)),
+        Decoded::Client(ClientMessages::OtaBegin(size, _)) => format!("ota begin size={size}"),
+        Decoded::Client(ClientMessages::OtaChunk(offset, data)) => format!("ota chunk offset={offset} len={}", data.len()),
+        Decoded::Client(ClientMessages::OtaVerify) => "ota verify".to_string(),
+        Decoded::Client(ClientMessages::OtaCommit) => "ota commit".to_string(),
+        Decoded::Client(ClientMessages::OtaReboot) => "ota reboot".to_string(),
+        Decoded::Client(ClientMessages::GetSegments) => "get segments".to_string(),
+        Decoded::Client(ClientMessages::SetSegments(segments)) => format!("set segments {}", describe_segments(segments)),
+        Decoded::Client(ClientMessages::SetSegmentActive(device, segment)) => {
+            format!("set segment active device={device} segment={segment}")
+        },
+        Decoded::Client(ClientMessages::SendSegmentPixels(device, segment, frame)) => {
+            format!("send segment pixels device={device} segment={segment} leds={}", frame.len())
+        },
+        Decoded::Server(ServerMessages::Segments(segments)) => format!("segments {}", describe_segments(segments)),
+        Decoded::Client(ClientMessages::GetLayout) => "get layout".to_string(),
+        Decoded::Client(ClientMessages::SetLayout(layout)) => format!("set layout {}", describe_layout(layout)),
+        Decoded::Server(ServerMessages::Layout(layout)) => format!("layout {}", describe_layout(layout)),
+        Decoded::Server(ServerMessages::OtaStatus(status)) => format!("ota status state={:?} offset={}", status.state, status.offset),
+    }
+}
+
+fn describe_config(config: &Config) -> String {
+    format!(
+        "leds={} order={:?} pin={} name={:?}",
+        config.led_count, config.color_order, config.output_pin, config.name
+    )
+}
+
+fn describe_segments(segments: &[Segment]) -> String {
+    let segments: Vec<String> = segments
+        .iter()
+        .map(|segment| {
+            let reversed = if segment.reversed { " reversed" } else { "" };
+            format!("{:?}={}+{}{reversed}", segment.name, segment.start, segment.len)
+        })
+        .collect();
+    format!("[{}]", segments.join(", "))
+}
+
+fn describe_layout(layout: &Layout) -> String {
+    match layout {
+        Layout::Unknown => "unknown".to_string(),
+        Layout::Rectangle(rectangle) => format!(
+            "rectangle top={} right={} bottom={} left={} start={:?} direction={:?} corner_gap={} bottom_gap={}",
+            rectangle.top,
+            rectangle.right,
+            rectangle.bottom,
+            rectangle.left,
+            rectangle.start,
+            rectangle.direction,
+            rectangle.corner_gap,
+            rectangle.bottom_gap
+        ),
+        Layout::Points(points) => format!("points count={}", points.len()),
+    }
+}
+
+/// One line summary of a captured datagram
+pub fn describe_record(record: &Record) -> String {
+    let message = match record.decode() {
+        Ok(decoded) => describe(&decoded),
+        Err(error) => format!("undecodable datagram of {} bytes: {error}", record.datagram.len()),
+    };
+    format!("{:>10.3}s {:<21} {message}", record.at.as_secs_f64(), record.source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use udp_leds::DeviceId;
+
+    #[test]
+    fn test_preview() {
+        assert_eq!(preview(&[], 4), "");
+        assert_eq!(preview(&[1, 2, 3], 4), "\x1b[38;2;1;2;3m█\x1b[0m");
+        assert_eq!(preview(&[1, 2, 3, 4, 5, 6], 1), "\x1b[38;2;1;2;3m█\x1b[0m…");
+    }
+
+    #[test]
+    fn test_opcode_and_pixels() {
+        let decoded = Decoded::Client(ClientMessages::SetPixel(DeviceId::new(4).unwrap(), 1, Rgb::new(2, 3, 4)));
+        assert_eq!(Opcode::of(&decoded), Opcode::SetPixel);
+        assert_eq!(pixels(&decoded), Some(vec![2, 3, 4]));
+        assert_eq!(pixels(&Decoded::Client(ClientMessages::Hello)), None);
+
+        let frame = udp_leds::Frame::from_bytes(&[5, 6, 7]).unwrap();
+        let decoded = Decoded::Client(ClientMessages::SendTimedPixels(DeviceId::new(4).unwrap(), 42, frame));
+        assert_eq!(Opcode::of(&decoded), Opcode::TimedPixels);
+        assert_eq!(pixels(&decoded), Some(vec![5, 6, 7]));
+        assert_eq!(describe(&decoded), "send timed pixels device=4 pts=42 leds=1");
+    }
+}
@@ -0,0 +1,482 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use futures::{Sink, Stream};
+use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio::time::{Instant, Sleep};
+
+use crate::{
+    client::{ClientMessages, ServerInfo},
+    color::Rgb,
+    constants::{MAX_MESSAGE_LENGTH, MAX_SEALED_LENGTH, PORT},
+    device::{ClientToken, DeviceId},
+    error::Error,
+    frame::Frame,
+    server::{ServerMessages, Status},
+};
+
+/**
+ * # Async client
+ * Tokio counterpart of the blocking `Client`
+ *
+ * The discovery is exposed as a `Stream` of servers and the frames are sent through a `Sink`
+ * that paces the frames according to the maximum frame rate advertised by the server
+ *
+ * ```no_run
+ * # async fn run() -> Result<(), udp_leds::error::Error> {
+ * use std::time::Duration;
+ * use futures::{SinkExt, StreamExt};
+ * use udp_leds::{async_client::AsyncClient, DeviceId, Frame};
+ *
+ * let mut client = AsyncClient::new().await?;
+ * let server = client.discover(Duration::from_millis(500)).next().await.unwrap();
+ * client.connect(server.addr).await?;
+ * client.acquire(DeviceId::new(12)?).await?;
+ *
+ * let mut frames = client.frames()?;
+ * frames.send(Frame::new(64)).await?;
+ * # Ok(())
+ * # }
+ * ```
+ */
+#[derive(Debug)]
+pub struct AsyncClient {
+    link: Link,
+    broadcast: SocketAddr,
+    server: Option<ServerInfo>,
+    device: Option<DeviceId>,
+    token: ClientToken,
+}
+
+impl AsyncClient {
+    /// Response timeout used when talking to a known server
+    pub const RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+    /// Creates a new client bound to an ephemeral port
+    pub async fn new() -> Result<Self, Error> {
+        Self::bind(("0.0.0.0", 0)).await
+    }
+
+    /// Creates a new client bound to the given address
+    pub async fn bind(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(addr).await?;
+        socket.set_broadcast(true)?;
+        Ok(AsyncClient {
+            link: Link {
+                socket: Arc::new(socket),
+                #[cfg(feature = "crypto")]
+                ciphers: Default::default(),
+            },
+            broadcast: SocketAddr::from(([255, 255, 255, 255], PORT)),
+            server: None,
+            device: None,
+            token: ClientToken::random(),
+        })
+    }
+
+    /// Sets the address the hello messages are sent to during the discovery
+    pub fn set_discovery_address(&mut self, addr: SocketAddr) {
+        self.broadcast = addr;
+    }
+
+    /// Sets the token sent when claiming a device id, to get back the ids claimed by a previous run
+    pub fn set_token(&mut self, token: ClientToken) {
+        self.token = token;
+    }
+
+    /// The token sent when claiming a device id, random unless set
+    pub fn token(&self) -> ClientToken {
+        self.token
+    }
+
+    /// The server the client is connected to
+    pub fn server(&self) -> Option<&ServerInfo> {
+        self.server.as_ref()
+    }
+
+    /// The device acquired by the client
+    pub fn device(&self) -> Option<DeviceId> {
+        self.device
+    }
+
+    /// Broadcasts a hello message and yields every server answering before the timeout
+    ///
+    /// Each server is yielded once, the stream ends when the timeout expires
+    pub fn discover(&self, timeout: Duration) -> impl Stream<Item = ServerInfo> + Unpin {
+        let link = self.link.clone();
+        let broadcast = self.broadcast;
+        let deadline = Instant::now() + timeout;
+
+        Box::pin(futures::stream::unfold((link, Vec::new(), false), move |(link, mut seen, sent)| async move {
+            if !sent && link.send_to(ClientMessages::hello(), broadcast).await.is_err() {
+                return None;
+            }
+            loop {
+                let (message, addr) = link.recv_until(deadline).await.ok()??;
+                let ServerMessages::Hello(advertisement) = message else {
+                    continue;
+                };
+                if !seen.contains(&addr) {
+                    seen.push(addr);
+                    return Some((ServerInfo { addr, advertisement }, (link, seen, true)));
+                }
+            }
+        }))
+    }
+
+    /// Connects to the server at the given address, checking that it answers the hello message
+    pub async fn connect(&mut self, addr: impl ToSocketAddrs) -> Result<ServerInfo, Error> {
+        let addr = tokio::net::lookup_host(addr).await?.next().ok_or(Error::NotConnected)?;
+        self.link.send_to(ClientMessages::hello(), addr).await?;
+
+        let deadline = Instant::now() + Self::RESPONSE_TIMEOUT;
+        while let Some((message, from)) = self.link.recv_until(deadline).await? {
+            let ServerMessages::Hello(advertisement) = message else {
+                continue;
+            };
+            if from == addr {
+                let server = ServerInfo { addr, advertisement };
+                self.server = Some(server.clone());
+                self.device = None;
+                return Ok(server);
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Asks the server for a device id, claiming again with the same token gives the same id back
+    ///
+    /// Fails with `Unsupported` if the server does not assign ids, the client then has to pick its own id
+    pub async fn claim(&self) -> Result<DeviceId, Error> {
+        self.request(ClientMessages::claim(self.token), |message| match message {
+            ServerMessages::Welcome(_, device) => Some(device),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Asks the server for its status
+    ///
+    /// Fails with `Unsupported` if the server does not report its status
+    pub async fn status(&self) -> Result<Status, Error> {
+        self.request(ClientMessages::get_status(), |message| match message {
+            ServerMessages::Status(status) => Some(status),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Sends a message to the server and waits for the answer picked by `answer`
+    async fn request<T>(&self, message: ClientMessages, answer: impl Fn(ServerMessages) -> Option<T>) -> Result<T, Error> {
+        let server = self.server.as_ref().ok_or(Error::NotConnected)?.addr;
+        self.link.send_to(message, server).await?;
+
+        let deadline = Instant::now() + Self::RESPONSE_TIMEOUT;
+        while let Some((message, from)) = self.link.recv_until(deadline).await? {
+            if from != server {
+                continue;
+            }
+            let legacy = matches!(message, ServerMessages::Hello(_));
+            if let Some(answer) = answer(message) {
+                return Ok(answer);
+            }
+            // Older servers answer the extended messages they do not know with a hello, any other message
+            // is a late answer to an earlier request
+            if legacy {
+                return Err(Error::Unsupported);
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Makes the given device the active device of the server
+    pub async fn acquire(&mut self, device: DeviceId) -> Result<(), Error> {
+        let server = self.server.as_ref().ok_or(Error::NotConnected)?;
+        self.link.send_to(ClientMessages::set_active(device), server.addr).await?;
+        self.device = Some(device);
+        Ok(())
+    }
+
+    /// Sends a single frame to the server, without any pacing
+    pub async fn send_frame(&self, frame: &Frame) -> Result<(), Error> {
+        let server = self.server.as_ref().ok_or(Error::NotConnected)?;
+        let device = self.device.ok_or(Error::NoDevice)?;
+        self.link.send_to(ClientMessages::send_pixels(device, frame.clone()), server.addr).await
+    }
+
+    /// Updates a single pixel of the strip
+    pub async fn set_pixel(&self, index: u8, color: impl Into<Rgb>) -> Result<(), Error> {
+        let server = self.server.as_ref().ok_or(Error::NotConnected)?;
+        let device = self.device.ok_or(Error::NoDevice)?;
+        self.link.send_to(ClientMessages::set_pixel(device, index, color), server.addr).await
+    }
+
+    /// Tells the server the acquired device is still there when no frame was sent for a while
+    pub async fn heartbeat(&self) -> Result<(), Error> {
+        let server = self.server.as_ref().ok_or(Error::NotConnected)?;
+        let device = self.device.ok_or(Error::NoDevice)?;
+        self.link.send_to(ClientMessages::heartbeat(device), server.addr).await
+    }
+
+    /// Seals the messages exchanged with the server at `addr` with the cipher, each server having its own keys
+    ///
+    /// The plain messages of this server are then ignored, except the answers to the discovery
+    #[cfg(feature = "crypto")]
+    pub fn set_cipher(&mut self, addr: SocketAddr, cipher: crate::crypto::Cipher) {
+        self.link.ciphers.lock().unwrap().insert(addr, cipher);
+    }
+
+    /// Exchanges plain messages with the server at `addr` again, returns its cipher
+    #[cfg(feature = "crypto")]
+    pub fn remove_cipher(&mut self, addr: SocketAddr) -> Option<crate::crypto::Cipher> {
+        self.link.ciphers.lock().unwrap().remove(&addr)
+    }
+
+    /// Returns a sink sending the frames to the server at its advertised frame rate
+    pub fn frames(&self) -> Result<FrameSink, Error> {
+        let server = self.server.as_ref().ok_or(Error::NotConnected)?;
+        let device = self.device.ok_or(Error::NoDevice)?;
+        let interval = match server.advertisement.max_fps {
+            0 => None,
+            fps => Some(Duration::from_secs(1) / fps as u32),
+        };
+        Ok(FrameSink {
+            link: self.link.clone(),
+            server: server.addr,
+            device,
+            interval,
+            delay: Box::pin(tokio::time::sleep(Duration::ZERO)),
+            pending: VecDeque::new(),
+        })
+    }
+}
+
+/**
+ * # Frame sink
+ * `Sink` sending frames to a server
+ *
+ * `poll_ready` only resolves once the frame interval of the server has elapsed since the last frame,
+ * so producers are slowed down to the advertised frame rate instead of flooding the server
+ */
+#[derive(Debug)]
+pub struct FrameSink {
+    link: Link,
+    server: SocketAddr,
+    device: DeviceId,
+    interval: Option<Duration>,
+    delay: Pin<Box<Sleep>>,
+    pending: VecDeque<Vec<u8>>,
+}
+
+impl FrameSink {
+    /// Minimum time between two frames, `None` if the server has no limit
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+}
+
+impl Sink<Frame> for FrameSink {
+    type Error = Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        if self.interval.is_some() {
+            ready!(self.delay.as_mut().poll(cx));
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Frame) -> Result<(), Self::Error> {
+        let datagram = self.link.encode(ClientMessages::send_pixels(self.device, item), self.server)?;
+        self.pending.push_back(datagram);
+        if let Some(interval) = self.interval {
+            self.delay.as_mut().reset(Instant::now() + interval);
+        }
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = &mut *self;
+        while let Some(message) = this.pending.front() {
+            ready!(this.link.socket.poll_send_to(cx, message, this.server))?;
+            this.pending.pop_front();
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// Socket of a client with the ciphers of the servers talking encrypted, shared by the client, its streams and its sinks
+#[derive(Debug, Clone)]
+struct Link {
+    socket: Arc<UdpSocket>,
+    #[cfg(feature = "crypto")]
+    ciphers: Arc<std::sync::Mutex<std::collections::HashMap<SocketAddr, crate::crypto::Cipher>>>,
+}
+
+impl Link {
+    /// Encodes a message for the server at `addr`, sealed with the cipher of the server if there is one
+    fn encode(&self, message: ClientMessages, addr: SocketAddr) -> Result<Vec<u8>, Error> {
+        let len = message.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.into();
+        #[cfg(feature = "crypto")]
+        if let Some(cipher) = self.ciphers.lock().unwrap().get_mut(&addr) {
+            return cipher.seal(&bytes[..len]);
+        }
+        #[cfg(not(feature = "crypto"))]
+        let _ = addr;
+        Ok(bytes[..len].to_vec())
+    }
+
+    async fn send_to(&self, message: ClientMessages, addr: SocketAddr) -> Result<(), Error> {
+        let datagram = self.encode(message, addr)?;
+        self.socket.send_to(&datagram, addr).await?;
+        Ok(())
+    }
+
+    /// Decodes a server message, opening it with the cipher of the server if there is one
+    fn decode(&self, datagram: &[u8], from: SocketAddr) -> Option<ServerMessages> {
+        #[cfg(feature = "crypto")]
+        if let Some(cipher) = self.ciphers.lock().unwrap().get_mut(&from) {
+            if crate::crypto::Cipher::is_sealed(datagram) {
+                return ServerMessages::try_from(&cipher.open(datagram).ok()?[..]).ok();
+            }
+            return match ServerMessages::try_from(datagram).ok()? {
+                hello @ ServerMessages::Hello(_) => Some(hello),
+                _ => None,
+            };
+        }
+        #[cfg(not(feature = "crypto"))]
+        let _ = from;
+        ServerMessages::try_from(datagram).ok()
+    }
+
+    /// Waits for the next valid server message, returns `None` once the deadline is reached
+    async fn recv_until(&self, deadline: Instant) -> Result<Option<(ServerMessages, SocketAddr)>, Error> {
+        let mut buf = [0; MAX_SEALED_LENGTH];
+        loop {
+            let (size, addr) = match tokio::time::timeout_at(deadline, self.socket.recv_from(&mut buf)).await {
+                Ok(Ok(received)) => received,
+                // An earlier datagram reached a port nobody listens on, the other servers may still answer
+                Ok(Err(error)) if matches!(error.kind(), std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::ConnectionReset) => continue,
+                Ok(Err(error)) => return Err(error.into()),
+                Err(_) => return Ok(None),
+            };
+            if let Some(message) = self.decode(&buf[..size], addr) {
+                return Ok(Some((message, addr)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+
+    use super::*;
+    use crate::server::Advertisement;
+
+    async fn fake_server() -> (UdpSocket, SocketAddr) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        (socket, addr)
+    }
+
+    async fn answer_hello(server: &UdpSocket, advertisement: Advertisement) -> SocketAddr {
+        let mut buf = [0; MAX_MESSAGE_LENGTH];
+        let (size, addr) = server.recv_from(&mut buf).await.unwrap();
+        assert_eq!(ClientMessages::try_from(&buf[..size]), Ok(ClientMessages::Hello));
+        let response = ServerMessages::Hello(advertisement);
+        let len = response.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = response.into();
+        server.send_to(&bytes[..len], addr).await.unwrap();
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_discover_stream() {
+        let advertisement = Advertisement { led_count: 10, ..Default::default() };
+        let (server, addr) = fake_server().await;
+        let mut client = AsyncClient::bind("127.0.0.1:0").await.unwrap();
+        client.set_discovery_address(addr);
+
+        let servers = client.discover(Duration::from_millis(200));
+        let (servers, _) = tokio::join!(servers.collect::<Vec<_>>(), answer_hello(&server, advertisement.clone()));
+        assert_eq!(servers, vec![ServerInfo { addr, advertisement }]);
+    }
+
+    #[tokio::test]
+    async fn test_frame_sink_paces_frames() {
+        let advertisement = Advertisement { led_count: 4, max_fps: 20, ..Default::default() };
+        let (server, addr) = fake_server().await;
+        let mut client = AsyncClient::bind("127.0.0.1:0").await.unwrap();
+        let (connected, _) = tokio::join!(client.connect(addr), answer_hello(&server, advertisement));
+        connected.unwrap();
+        client.acquire(DeviceId::new(5).unwrap()).await.unwrap();
+
+        let mut frames = client.frames().unwrap();
+        assert_eq!(frames.interval(), Some(Duration::from_millis(50)));
+        let start = Instant::now();
+        for _ in 0..3 {
+            frames.send(Frame::new(4)).await.unwrap();
+        }
+        frames.close().await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        let mut buf = [0; MAX_MESSAGE_LENGTH];
+        let (size, _) = server.recv_from(&mut buf).await.unwrap();
+        assert_eq!(ClientMessages::try_from(&buf[..size]), Ok(ClientMessages::SetActive(DeviceId::new(5).unwrap())));
+        for _ in 0..3 {
+            let (size, _) = server.recv_from(&mut buf).await.unwrap();
+            assert_eq!(ClientMessages::try_from(&buf[..size]), Ok(ClientMessages::SendPixels(DeviceId::new(5).unwrap(), Frame::new(4))));
+        }
+    }
+
+    #[cfg(feature = "crypto")]
+    #[tokio::test]
+    async fn test_sealed_messages() {
+        use crate::crypto::{Cipher, Key};
+
+        let (server, addr) = fake_server().await;
+        let mut client = AsyncClient::bind("127.0.0.1:0").await.unwrap();
+        let (connected, _) = tokio::join!(client.connect(addr), answer_hello(&server, Advertisement::default()));
+        connected.unwrap();
+        client.set_cipher(addr, Cipher::new(1, &Key::from([7; 32])));
+        client.acquire(DeviceId::new(5).unwrap()).await.unwrap();
+
+        let mut cipher = Cipher::new(1, &Key::from([7; 32]));
+        let mut buf = [0; MAX_SEALED_LENGTH];
+        let (size, from) = server.recv_from(&mut buf).await.unwrap();
+        assert_eq!(ClientMessages::try_from(&cipher.open(&buf[..size]).unwrap()[..]), Ok(ClientMessages::SetActive(DeviceId::new(5).unwrap())));
+
+        let answer = async {
+            let (size, _) = server.recv_from(&mut buf).await.unwrap();
+            assert_eq!(ClientMessages::try_from(&cipher.open(&buf[..size]).unwrap()[..]), Ok(ClientMessages::GetStatus));
+            // A plain answer is ignored once the server talks encrypted
+            let response = ServerMessages::Status(Status::default());
+            let len = response.encoded_len();
+            let bytes: [u8; MAX_MESSAGE_LENGTH] = response.into();
+            server.send_to(&bytes[..len], from).await.unwrap();
+            let status = Status { uptime: Duration::from_secs(3), ..Default::default() };
+            let response = ServerMessages::Status(status);
+            let len = response.encoded_len();
+            let bytes: [u8; MAX_MESSAGE_LENGTH] = response.into();
+            server.send_to(&cipher.seal(&bytes[..len]).unwrap(), from).await.unwrap();
+        };
+        let (status, _) = tokio::join!(client.status(), answer);
+        assert_eq!(status.unwrap().uptime, Duration::from_secs(3));
+
+        let mut frames = client.frames().unwrap();
+        frames.send(Frame::new(2)).await.unwrap();
+        let (size, _) = server.recv_from(&mut buf).await.unwrap();
+        assert_eq!(ClientMessages::try_from(&cipher.open(&buf[..size]).unwrap()[..]), Ok(ClientMessages::SendPixels(DeviceId::new(5).unwrap(), Frame::new(2))));
+    }
+}
@@ -0,0 +1,239 @@
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use crate::{
+    client::ClientMessages,
+    constants::{CLIENT_FLAG, MAX_SEALED_LENGTH, SEALED_FLAG, SERVER_FLAG},
+    error::Error,
+    server::ServerMessages,
+    varint,
+};
+
+/**
+ * # Capture files
+ * Compact log of the datagrams exchanged between the clients and the servers
+ *
+ * ## File format
+ * The file starts with the magic bytes `ULCP` followed by the version of the format
+ * Then comes one record per datagram:
+ * - the time elapsed since the previous record in microseconds (LEB128 varint)
+ * - the source address: 4 or 6 for the IP version, the IP address bytes, the port (16 bits, big endian)
+ * - the length of the datagram (varint)
+ * - the number of bytes stored (varint), the trailing zeros of the datagram are not stored
+ * - the stored bytes
+ */
+const MAGIC: &[u8; 4] = b"ULCP";
+const VERSION: u8 = 1;
+
+/// A datagram seen on the network
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Record {
+    /// Time elapsed since the start of the capture
+    pub at: Duration,
+    pub source: SocketAddr,
+    #[cfg_attr(feature = "serde", serde(with = "crate::hex::vec"))]
+    pub datagram: Vec<u8>,
+}
+
+/// A datagram decoded according to its flag
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::large_enum_variant)]
+pub enum Decoded {
+    Client(ClientMessages),
+    Server(ServerMessages),
+}
+
+impl Record {
+    /// Decodes the datagram as a client or a server message depending on its flag, the sealed ones cannot be decoded
+    pub fn decode(&self) -> Result<Decoded, Error> {
+        match self.datagram.first() {
+            Some(&CLIENT_FLAG) => Ok(Decoded::Client(ClientMessages::try_from(&self.datagram[..])?)),
+            Some(&SERVER_FLAG) => Ok(Decoded::Server(ServerMessages::try_from(&self.datagram[..])?)),
+            Some(&SEALED_FLAG) => Err(Error::Sealed),
+            Some(_) => Err(Error::InvalidFlag),
+            None => Err(Error::InvalidMessageLength),
+        }
+    }
+
+    /// Whether the datagram was sent by a client
+    pub fn is_client(&self) -> bool {
+        self.datagram.first() == Some(&CLIENT_FLAG)
+    }
+}
+
+/// Writes records to a capture file
+#[derive(Debug)]
+pub struct CaptureWriter<W: Write> {
+    inner: W,
+    last: Duration,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Writes the header of the capture
+    pub fn new(mut inner: W) -> Result<Self, Error> {
+        inner.write_all(MAGIC)?;
+        inner.write_all(&[VERSION])?;
+        Ok(CaptureWriter { inner, last: Duration::ZERO })
+    }
+
+    /// Appends a record, the records must be written in chronological order
+    pub fn write(&mut self, record: &Record) -> Result<(), Error> {
+        let delta = record.at.saturating_sub(self.last);
+        self.last = self.last.max(record.at);
+
+        let mut bytes = Vec::with_capacity(record.datagram.len() + 32);
+        varint::write(&mut bytes, delta.as_micros() as u64);
+        match record.source.ip() {
+            IpAddr::V4(ip) => {
+                bytes.push(4);
+                bytes.extend_from_slice(&ip.octets());
+            },
+            IpAddr::V6(ip) => {
+                bytes.push(6);
+                bytes.extend_from_slice(&ip.octets());
+            },
+        }
+        bytes.extend_from_slice(&record.source.port().to_be_bytes());
+
+        let stored = record.datagram.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1);
+        varint::write(&mut bytes, record.datagram.len() as u64);
+        varint::write(&mut bytes, stored as u64);
+        bytes.extend_from_slice(&record.datagram[..stored]);
+
+        self.inner.write_all(&bytes)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads the records of a capture file
+#[derive(Debug)]
+pub struct CaptureReader<R: Read> {
+    inner: R,
+    at: Duration,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Checks the header of the capture
+    pub fn new(mut inner: R) -> Result<Self, Error> {
+        let mut header = [0; 5];
+        inner.read_exact(&mut header).map_err(|_| Error::InvalidCapture)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(Error::InvalidCapture);
+        }
+        Ok(CaptureReader { inner, at: Duration::ZERO })
+    }
+
+    /// Reads the next record, `None` at the end of the capture
+    pub fn read(&mut self) -> Result<Option<Record>, Error> {
+        let mut first = [0; 1];
+        if self.inner.read(&mut first)? == 0 {
+            return Ok(None);
+        }
+        let delta = varint::read(&mut self.inner, Some(first[0])).ok_or(Error::InvalidCapture)?;
+        self.at += Duration::from_micros(delta);
+
+        let ip = match self.read_bytes(1)?[0] {
+            4 => IpAddr::from(<[u8; 4]>::try_from(self.read_bytes(4)?).unwrap()),
+            6 => IpAddr::from(<[u8; 16]>::try_from(self.read_bytes(16)?).unwrap()),
+            _ => return Err(Error::InvalidCapture),
+        };
+        let port = self.read_bytes(2)?;
+        let source = SocketAddr::new(ip, u16::from_be_bytes([port[0], port[1]]));
+
+        let len = varint::read(&mut self.inner, None).ok_or(Error::InvalidCapture)? as usize;
+        let stored = varint::read(&mut self.inner, None).ok_or(Error::InvalidCapture)? as usize;
+        if len > MAX_SEALED_LENGTH || stored > len {
+            return Err(Error::InvalidCapture);
+        }
+        let mut datagram = self.read_bytes(stored)?;
+        datagram.resize(len, 0);
+
+        Ok(Some(Record { at: self.at, source, datagram }))
+    }
+
+    fn read_bytes(&mut self, count: usize) -> Result<Vec<u8>, Error> {
+        let mut bytes = vec![0; count];
+        self.inner.read_exact(&mut bytes).map_err(|_| Error::InvalidCapture)?;
+        Ok(bytes)
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constants::MAX_MESSAGE_LENGTH, device::DeviceId, frame::Frame};
+
+    fn datagram(message: ClientMessages) -> Vec<u8> {
+        let len = message.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.into();
+        bytes[..len].to_vec()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let device = DeviceId::new(3).unwrap();
+        let mut frame = Frame::new(crate::constants::MAX_LED_COUNT);
+        frame.set(0, (1, 2, 3));
+        let records = vec![
+            Record {
+                at: Duration::from_micros(10),
+                source: "192.168.1.2:4000".parse().unwrap(),
+                datagram: datagram(ClientMessages::SetActive(device)),
+            },
+            Record {
+                at: Duration::from_secs(3),
+                source: "[::1]:4000".parse().unwrap(),
+                datagram: datagram(ClientMessages::SendPixels(device, frame.clone())),
+            },
+        ];
+
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        let bytes = writer.into_inner();
+        assert!(bytes.len() < 100);
+
+        let read: Vec<Record> = CaptureReader::new(&bytes[..]).unwrap().map(Result::unwrap).collect();
+        assert_eq!(read, records);
+        assert_eq!(read[1].decode(), Ok(Decoded::Client(ClientMessages::SendPixels(device, frame))));
+
+        let sealed = Record { at: Duration::ZERO, source: records[0].source, datagram: vec![SEALED_FLAG, 1, 2] };
+        assert_eq!(sealed.decode(), Err(Error::Sealed));
+    }
+
+    #[test]
+    fn test_invalid_capture() {
+        assert_eq!(CaptureReader::new(&b"ULCX\x01"[..]).err(), Some(Error::InvalidCapture));
+
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        writer.write(&Record {
+            at: Duration::ZERO,
+            source: "127.0.0.1:1".parse().unwrap(),
+            datagram: vec![1, 2, 3],
+        }).unwrap();
+        let bytes = writer.into_inner();
+        let mut reader = CaptureReader::new(&bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(reader.read(), Err(Error::InvalidCapture));
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{constants::{CLIENT_FLAG, INSTRUCTION_MASK, MAX_MESSAGE_LENGTH, MAX_LED_COUNT, DEVICE_MASK}, server::ServerMessages};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::{constants::{CLIENT_FLAG, INSTRUCTION_MASK, MAX_MESSAGE_LENGTH, MAX_SEALED_LENGTH, PORT}, server::{Advertisement, ServerMessages, Status}, config::Config, ota::{MAX_CHUNK_LENGTH, OtaState, OtaStatus}, segment::Segment, layout::Layout, frame::Frame, color::Rgb, device::{ClientToken, DeviceId}, error::Error, sync::{ClockSync, Sample}};
 
 /**
  * # Client messages
@@ -13,6 +16,13 @@ use crate::{constants::{CLIENT_FLAG, INSTRUCTION_MASK, MAX_MESSAGE_LENGTH, MAX_L
  * ## Hello
  * The client broadcasts a hello message to find the server
  * [CLIENT_FLAG, 0b1100_0000]
+ *
+ * ## Claim
+ * The client sends its hello with its token to get a device id from the server
+ * The server answers with a hello carrying the id it assigned, a client sending the same token again gets the same id back,
+ * even from another address, so the token should be kept across restarts
+ * The ids assigned by a server can only be used by the address that claimed them last
+ * [CLIENT_FLAG, 0b1100_0000, token (64 bits big endian)]
  * 
  * ## SetActive
  * The client sends a set active message to set the active device to the given device
@@ -22,7 +32,7 @@ use crate::{constants::{CLIENT_FLAG, INSTRUCTION_MASK, MAX_MESSAGE_LENGTH, MAX_L
  * 
  * ## SendPixels
  * The client sends a send pixels message to update the LEDs
- * The message contains a list of 24bits RGB values
+ * The message contains a list of 24bits RGB values, pixels missing from the message are turned off
  * The modifications are only applied if the current device is active
  * [CLIENT_FLAG, 0b0000_0000 | device, r1, g1, b1, r2, g2, b2, ...]
  * 
@@ -31,13 +41,138 @@ use crate::{constants::{CLIENT_FLAG, INSTRUCTION_MASK, MAX_MESSAGE_LENGTH, MAX_L
  * The message contains the index of the pixel and the 24bits RGB value
  * The modifications are only applied if the current device is active
  * [CLIENT_FLAG, 0b1000_0000 | device, index, r, g, b]
+ *
+ * ## Extended messages
+ * The four instructions are taken, so newer messages reuse the hello instruction:
+ * the 6 least significant bits of the second byte give the opcode (0 being hello) and the device is sent in the third byte
+ * Older servers answer these messages with a hello, which clients ignore
+ * [CLIENT_FLAG, 0b1100_0000 | opcode, device, ...]
+ *
+ * ## Heartbeat
+ * The active device sends heartbeats while it has nothing to display, so the server does not consider it gone
+ * If the server hears nothing from the active device before its timeout, it releases the device and goes idle
+ * [CLIENT_FLAG, 0b1100_0001, device]
+ *
+ * ## SendGroupPixels
+ * The client sends a single frame to a multicast group of servers, each server only displays its slices of the frame
+ * A slice is identified by the unique id of the server it is meant for, it copies `len` pixels starting at pixel
+ * `source` of the message to the LEDs of the server starting at LED `target` (16 bits big endian values)
+ * The modifications are only applied if the current device is active
+ * [CLIENT_FLAG, 0b1100_0010, device, slice_count, (id1, ..., id6, source, len, target)..., r1, g1, b1, ...]
+ *
+ * ## GetStatus
+ * The client asks the server for its status, whichever device is active, the server answers with a status message
+ * [CLIENT_FLAG, 0b1100_0011]
+ *
+ * ## TimeRequest
+ * The client sends the time of its clock in microseconds, the server answers with a time response
+ * which lets the client estimate the offset between the clocks
+ * [CLIENT_FLAG, 0b1100_0100, t1 (64 bits big endian)]
+ *
+ * ## SendTimedPixels
+ * The client sends a frame to show once the clock of the server reaches the presentation timestamp, in microseconds
+ * The server buffers the frames until then, so servers synchronized with the same client show their frames together
+ * The frames whose time has passed are shown right away, unless a later frame was already shown
+ * The modifications are only applied if the current device is active
+ * [CLIENT_FLAG, 0b1100_0101, device, pts (64 bits big endian), r1, g1, b1, ...]
+ *
+ * ## GetConfig
+ * The client asks the server for its config, the server answers with a config message
+ * [CLIENT_FLAG, 0b1100_0110]
+ *
+ * ## SetConfig
+ * The client changes the config of the server, which stores it and answers with the config it kept,
+ * the previous one if the new one was invalid
+ * [CLIENT_FLAG, 0b1100_0111, config...]
+ *
+ * ## Firmware update
+ * The client uploads a firmware image in chunks, the server answers every update message with its update status
+ * which gives the offset the next chunk has to start at, so an interrupted upload resumes where it stopped
+ * Beginning the image being received again resumes it, beginning another image starts over
+ * [CLIENT_FLAG, 0b1100_1000, size (32 bits big endian), sha256 (32 bytes)]
+ * [CLIENT_FLAG, 0b1100_1001, offset (32 bits big endian), data...]
+ * Once all chunks are sent, the client asks the server to check the hash, to boot the image on its next restart,
+ * then to restart
+ * [CLIENT_FLAG, 0b1100_1010]
+ * [CLIENT_FLAG, 0b1100_1011]
+ * [CLIENT_FLAG, 0b1100_1100]
+ *
+ * ## GetSegments
+ * The client asks the server for its segment table, the server answers with a segments message
+ * [CLIENT_FLAG, 0b1100_1101]
+ *
+ * ## SetSegments
+ * The client replaces the segment table of the server, which stores it and answers with the table it kept,
+ * the previous one if the new one was invalid. Replacing the table releases every segment, so only the client
+ * of the active device can do it and only while no other device is sending to a segment
+ * [CLIENT_FLAG, 0b1100_1110, segments...]
+ *
+ * ## SetSegmentActive
+ * The client makes the device the owner of a segment, identified by its index in the table
+ * Each segment has its own owner, which can differ from the active device, so several clients can update the strip
+ * at the same time. The frames of the active device leave the owned segments alone
+ * A segment whose owner is still sending is not handed over, the server releases it once the owner went silent
+ * [CLIENT_FLAG, 0b1100_1111, device, segment]
+ *
+ * ## SendSegmentPixels
+ * The client sends the pixels of a segment, pixels missing from the message are turned off
+ * The modifications are only applied if the device owns the segment
+ * [CLIENT_FLAG, 0b1101_0000, device, segment, r1, g1, b1, ...]
+ *
+ * ## GetLayout
+ * The client asks the server where its LEDs are around the screen, the server answers with a layout message
+ * [CLIENT_FLAG, 0b1101_0001]
+ *
+ * ## SetLayout
+ * The client describes where the LEDs of the server are, the server stores the layout and answers with the layout
+ * it kept, the previous one if the new one does not fit on its strip or the client does not use the active device
+ * [CLIENT_FLAG, 0b1101_0010, layout...]
  */
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::large_enum_variant)]
 pub enum ClientMessages {
     Hello,
-    SetActive(u8),
-    SendPixels(u8, [u8; MAX_LED_COUNT * 3]),
-    SetPixel(u8, u8, u8, u8, u8)
+    Claim(ClientToken),
+    SetActive(DeviceId),
+    SendPixels(DeviceId, Frame),
+    SetPixel(DeviceId, u8, Rgb),
+    Heartbeat(DeviceId),
+    SendGroupPixels(DeviceId, Vec<Slice>, Frame),
+    GetStatus,
+    TimeRequest(u64),
+    SendTimedPixels(DeviceId, u64, Frame),
+    GetConfig,
+    SetConfig(Config),
+    OtaBegin(u32, [u8; 32]),
+    OtaChunk(u32, Vec<u8>),
+    OtaVerify,
+    OtaCommit,
+    OtaReboot,
+    GetSegments,
+    SetSegments(Vec<Segment>),
+    SetSegmentActive(DeviceId, u8),
+    SendSegmentPixels(DeviceId, u8, Frame),
+    GetLayout,
+    SetLayout(Layout),
+}
+
+/// Part of a group frame meant for a single server
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Slice {
+    /// Unique id of the server, as advertised in its hello message
+    pub server: [u8; 6],
+    /// First pixel of the slice in the message
+    pub source: u16,
+    /// Number of pixels of the slice
+    pub len: u16,
+    /// First LED of the server updated by the slice
+    pub target: u16,
+}
+
+impl Slice {
+    const ENCODED_LEN: usize = 12;
 }
 
 impl ClientMessages {
@@ -46,39 +181,231 @@ impl ClientMessages {
         ClientMessages::Hello
     }
 
+    /// Creates a new claim message
+    pub fn claim(token: ClientToken) -> Self {
+        ClientMessages::Claim(token)
+    }
+
     /// Creates a new set active message
-    pub fn set_active(device: u8) -> Self {
-        assert!(device < DEVICE_MASK, "Invalid device number: {}", device);
+    pub fn set_active(device: DeviceId) -> Self {
         ClientMessages::SetActive(device)
     }
 
     /// Creates a new send pixels message
-    pub fn send_pixels(device: u8, pixels: [u8; MAX_LED_COUNT * 3]) -> Self {
-        assert!(device < DEVICE_MASK, "Invalid device number: {}", device);
-        ClientMessages::SendPixels(device, pixels)
+    pub fn send_pixels(device: DeviceId, frame: Frame) -> Self {
+        ClientMessages::SendPixels(device, frame)
     }
 
     /// Creates a new set pixel message
-    pub fn set_pixel(device: u8, pixel:u8, r: u8, g: u8, b: u8) -> Self {
-        assert!(device < DEVICE_MASK, "Invalid device number: {}", device);
-        ClientMessages::SetPixel(device, pixel, r, g, b)
+    pub fn set_pixel(device: DeviceId, pixel: u8, color: impl Into<Rgb>) -> Self {
+        ClientMessages::SetPixel(device, pixel, color.into())
+    }
+
+    /// Creates a new heartbeat message
+    pub fn heartbeat(device: DeviceId) -> Self {
+        ClientMessages::Heartbeat(device)
+    }
+
+    /// Creates a new send group pixels message carrying one frame per server
+    ///
+    /// Fails if the frames do not fit in a single message
+    pub fn send_group_pixels(device: DeviceId, frames: &[([u8; 6], &Frame)]) -> Result<Self, Error> {
+        let pixel_count: usize = frames.iter().map(|(_, frame)| frame.len()).sum();
+        if frames.len() > u8::MAX as usize || 4 + frames.len() * Slice::ENCODED_LEN + pixel_count * 3 > MAX_MESSAGE_LENGTH {
+            return Err(Error::InvalidMessageLength);
+        }
+        let mut slices = Vec::with_capacity(frames.len());
+        let mut pixels = Frame::new(pixel_count);
+        let mut source = 0;
+        for (server, frame) in frames {
+            slices.push(Slice {
+                server: *server,
+                source: source as u16,
+                len: frame.len() as u16,
+                target: 0,
+            });
+            pixels.copy_at(source, frame.as_slice());
+            source += frame.len();
+        }
+        Ok(ClientMessages::SendGroupPixels(device, slices, pixels))
+    }
+
+    /// Creates a new get status message
+    pub fn get_status() -> Self {
+        ClientMessages::GetStatus
+    }
+
+    /// Creates a new time request message carrying the time of the client clock
+    pub fn time_request(t1: u64) -> Self {
+        ClientMessages::TimeRequest(t1)
+    }
+
+    /// Creates a new send timed pixels message, the frame is shown once the server clock reaches `pts`
+    ///
+    /// Fails if the frame does not fit in a single message
+    pub fn send_timed_pixels(device: DeviceId, pts: u64, frame: Frame) -> Result<Self, Error> {
+        if 11 + frame.len() * 3 > MAX_MESSAGE_LENGTH {
+            return Err(Error::InvalidMessageLength);
+        }
+        Ok(ClientMessages::SendTimedPixels(device, pts, frame))
+    }
+
+    /// Creates a new get config message
+    pub fn get_config() -> Self {
+        ClientMessages::GetConfig
+    }
+
+    /// Creates a new set config message
+    pub fn set_config(config: Config) -> Self {
+        ClientMessages::SetConfig(config)
+    }
+
+    /// Creates a new message beginning the upload of a firmware image
+    pub fn ota_begin(image: &[u8]) -> Self {
+        ClientMessages::OtaBegin(image.len() as u32, crate::ota::hash(image))
+    }
+
+    /// Creates a new message carrying a chunk of a firmware image
+    ///
+    /// Fails if the chunk does not fit in a single message
+    pub fn ota_chunk(offset: u32, data: Vec<u8>) -> Result<Self, Error> {
+        if data.len() > MAX_CHUNK_LENGTH {
+            return Err(Error::InvalidMessageLength);
+        }
+        Ok(ClientMessages::OtaChunk(offset, data))
+    }
+
+    /// Creates a new get segments message
+    pub fn get_segments() -> Self {
+        ClientMessages::GetSegments
+    }
+
+    /// Creates a new set segments message
+    ///
+    /// Fails if the server cannot hold that many segments
+    pub fn set_segments(segments: Vec<Segment>) -> Result<Self, Error> {
+        if segments.len() > Segment::MAX_COUNT {
+            return Err(Error::InvalidSegment);
+        }
+        Ok(ClientMessages::SetSegments(segments))
+    }
+
+    /// Creates a new set segment active message
+    pub fn set_segment_active(device: DeviceId, segment: u8) -> Self {
+        ClientMessages::SetSegmentActive(device, segment)
+    }
+
+    /// Creates a new send segment pixels message
+    ///
+    /// Fails if the frame does not fit in a single message
+    pub fn send_segment_pixels(device: DeviceId, segment: u8, frame: Frame) -> Result<Self, Error> {
+        if 4 + frame.len() * 3 > MAX_MESSAGE_LENGTH {
+            return Err(Error::InvalidMessageLength);
+        }
+        Ok(ClientMessages::SendSegmentPixels(device, segment, frame))
+    }
+
+    /// Creates a new get layout message
+    pub fn get_layout() -> Self {
+        ClientMessages::GetLayout
+    }
+
+    /// Creates a new set layout message
+    ///
+    /// Fails if the layout has more LEDs than a strip
+    pub fn set_layout(layout: Layout) -> Result<Self, Error> {
+        layout.validate(crate::constants::MAX_LED_COUNT)?;
+        Ok(ClientMessages::SetLayout(layout))
     }
 
     pub fn expect_response(&self) -> bool {
         match self {
             ClientMessages::Hello => true,
+            ClientMessages::Claim(_) => true,
             ClientMessages::SetActive(_) => false,
             ClientMessages::SendPixels(_, _) => false,
-            ClientMessages::SetPixel(_, _, _, _, _) => false
+            ClientMessages::SetPixel(_, _, _) => false,
+            ClientMessages::Heartbeat(_) => false,
+            ClientMessages::SendGroupPixels(_, _, _) => false,
+            ClientMessages::GetStatus => true,
+            ClientMessages::TimeRequest(_) => true,
+            ClientMessages::SendTimedPixels(_, _, _) => false,
+            ClientMessages::GetConfig => true,
+            ClientMessages::SetConfig(_) => true,
+            ClientMessages::OtaBegin(_, _) => true,
+            ClientMessages::OtaChunk(_, _) => true,
+            ClientMessages::OtaVerify => true,
+            ClientMessages::OtaCommit => true,
+            ClientMessages::OtaReboot => true,
+            ClientMessages::GetSegments => true,
+            ClientMessages::SetSegments(_) => true,
+            ClientMessages::SetSegmentActive(_, _) => false,
+            ClientMessages::SendSegmentPixels(_, _, _) => false,
+            ClientMessages::GetLayout => true,
+            ClientMessages::SetLayout(_) => true,
         }
     }
 
     pub fn response(&self) -> Option<ServerMessages> {
         match self {
-            ClientMessages::Hello => Some(ServerMessages::Hello),
+            ClientMessages::Hello => Some(ServerMessages::hello()),
+            // The id depends on the ids already assigned by the server
+            ClientMessages::Claim(_) => None,
             ClientMessages::SetActive(_) => None,
             ClientMessages::SendPixels(_, _) => None,
-            ClientMessages::SetPixel(_, _, _, _, _) => None
+            ClientMessages::SetPixel(_, _, _) => None,
+            ClientMessages::Heartbeat(_) => None,
+            ClientMessages::SendGroupPixels(_, _, _) => None,
+            // The status depends on the state of the server
+            ClientMessages::GetStatus => None,
+            ClientMessages::TimeRequest(_) => None,
+            ClientMessages::SendTimedPixels(_, _, _) => None,
+            // The config is the one of the server
+            ClientMessages::GetConfig => None,
+            ClientMessages::SetConfig(_) => None,
+            // The update status depends on the chunks received by the server
+            ClientMessages::OtaBegin(_, _) => None,
+            ClientMessages::OtaChunk(_, _) => None,
+            ClientMessages::OtaVerify => None,
+            ClientMessages::OtaCommit => None,
+            ClientMessages::OtaReboot => None,
+            // The segment table is the one of the server
+            ClientMessages::GetSegments => None,
+            ClientMessages::SetSegments(_) => None,
+            ClientMessages::SetSegmentActive(_, _) => None,
+            ClientMessages::SendSegmentPixels(_, _, _) => None,
+            // The layout is the one of the server
+            ClientMessages::GetLayout => None,
+            ClientMessages::SetLayout(_) => None,
+        }
+    }
+
+    /// Number of meaningful bytes in the encoded message
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            ClientMessages::Hello => 2,
+            ClientMessages::Claim(_) => 10,
+            ClientMessages::SetActive(_) => 2,
+            ClientMessages::SendPixels(_, frame) => 2 + frame.len() * 3,
+            ClientMessages::SetPixel(_, _, _) => 6,
+            ClientMessages::Heartbeat(_) => 3,
+            ClientMessages::SendGroupPixels(_, slices, pixels) => 4 + slices.len() * Slice::ENCODED_LEN + pixels.len() * 3,
+            ClientMessages::GetStatus => 2,
+            ClientMessages::TimeRequest(_) => 10,
+            ClientMessages::SendTimedPixels(_, _, frame) => 11 + frame.len() * 3,
+            ClientMessages::GetConfig => 2,
+            ClientMessages::SetConfig(config) => 2 + config.encoded_len(),
+            ClientMessages::OtaBegin(_, _) => 38,
+            ClientMessages::OtaChunk(_, data) => 6 + data.len(),
+            ClientMessages::OtaVerify => 2,
+            ClientMessages::OtaCommit => 2,
+            ClientMessages::OtaReboot => 2,
+            ClientMessages::GetSegments => 2,
+            ClientMessages::SetSegments(segments) => 2 + Segment::table_len(segments),
+            ClientMessages::SetSegmentActive(_, _) => 4,
+            ClientMessages::SendSegmentPixels(_, _, frame) => 4 + frame.len() * 3,
+            ClientMessages::GetLayout => 2,
+            ClientMessages::SetLayout(layout) => 2 + layout.encoded_len(),
         }
     }
 }
@@ -95,62 +422,804 @@ impl TryFrom<&[u8]> for ClientMessages {
         }
 
         match value[1] & INSTRUCTION_MASK {
-            crate::constants::INSTRUCTION_HELLO => Ok(ClientMessages::Hello),
+            crate::constants::INSTRUCTION_HELLO => match value[1] & crate::constants::OPCODE_MASK {
+                crate::constants::OPCODE_HELLO => match value.len() {
+                    2 => Ok(ClientMessages::Hello),
+                    10 => Ok(ClientMessages::Claim(u64::from_be_bytes(value[2..10].try_into().unwrap()).into())),
+                    _ => Err(crate::error::Error::InvalidMessageLength),
+                },
+                crate::constants::OPCODE_HEARTBEAT => {
+                    if value.len() != 3 {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    Ok(ClientMessages::Heartbeat(DeviceId::from_bits(value[2])))
+                },
+                crate::constants::OPCODE_SEND_GROUP_PIXELS => {
+                    if value.len() < 4 || value.len() < 4 + value[3] as usize * Slice::ENCODED_LEN {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    let pixels_start = 4 + value[3] as usize * Slice::ENCODED_LEN;
+                    let pixels = Frame::from_bytes(&value[pixels_start..])?;
+                    let slices: Vec<Slice> = value[4..pixels_start]
+                        .chunks_exact(Slice::ENCODED_LEN)
+                        .map(|slice| Slice {
+                            server: slice[..6].try_into().unwrap(),
+                            source: u16::from_be_bytes([slice[6], slice[7]]),
+                            len: u16::from_be_bytes([slice[8], slice[9]]),
+                            target: u16::from_be_bytes([slice[10], slice[11]]),
+                        })
+                        .collect();
+                    if slices.iter().any(|slice| slice.source as usize + slice.len as usize > pixels.len()) {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    Ok(ClientMessages::SendGroupPixels(DeviceId::from_bits(value[2]), slices, pixels))
+                },
+                crate::constants::OPCODE_STATUS => {
+                    if value.len() != 2 {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    Ok(ClientMessages::GetStatus)
+                },
+                crate::constants::OPCODE_CONFIG => {
+                    if value.len() != 2 {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    Ok(ClientMessages::GetConfig)
+                },
+                crate::constants::OPCODE_SET_CONFIG => Ok(ClientMessages::SetConfig(Config::decode(&value[2..])?)),
+                crate::constants::OPCODE_TIME => {
+                    if value.len() != 10 {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    Ok(ClientMessages::TimeRequest(u64::from_be_bytes(value[2..10].try_into().unwrap())))
+                },
+                crate::constants::OPCODE_SEND_TIMED_PIXELS => {
+                    if value.len() < 11 {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    let pts = u64::from_be_bytes(value[3..11].try_into().unwrap());
+                    Ok(ClientMessages::SendTimedPixels(DeviceId::from_bits(value[2]), pts, Frame::from_bytes(&value[11..])?))
+                },
+                crate::constants::OPCODE_OTA_BEGIN => {
+                    if value.len() != 38 {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    let size = u32::from_be_bytes(value[2..6].try_into().unwrap());
+                    Ok(ClientMessages::OtaBegin(size, value[6..38].try_into().unwrap()))
+                },
+                crate::constants::OPCODE_OTA_CHUNK => {
+                    if value.len() < 6 {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    let offset = u32::from_be_bytes(value[2..6].try_into().unwrap());
+                    Ok(ClientMessages::OtaChunk(offset, value[6..].to_vec()))
+                },
+                opcode @ (crate::constants::OPCODE_OTA_VERIFY | crate::constants::OPCODE_OTA_COMMIT | crate::constants::OPCODE_OTA_REBOOT) => {
+                    if value.len() != 2 {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    Ok(match opcode {
+                        crate::constants::OPCODE_OTA_VERIFY => ClientMessages::OtaVerify,
+                        crate::constants::OPCODE_OTA_COMMIT => ClientMessages::OtaCommit,
+                        _ => ClientMessages::OtaReboot,
+                    })
+                },
+                crate::constants::OPCODE_SEGMENTS => {
+                    if value.len() != 2 {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    Ok(ClientMessages::GetSegments)
+                },
+                crate::constants::OPCODE_SET_SEGMENTS => Ok(ClientMessages::SetSegments(Segment::decode_table(&value[2..])?)),
+                crate::constants::OPCODE_SET_SEGMENT_ACTIVE => {
+                    if value.len() != 4 {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    Ok(ClientMessages::SetSegmentActive(DeviceId::from_bits(value[2]), value[3]))
+                },
+                crate::constants::OPCODE_SEND_SEGMENT_PIXELS => {
+                    if value.len() < 4 {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    Ok(ClientMessages::SendSegmentPixels(DeviceId::from_bits(value[2]), value[3], Frame::from_bytes(&value[4..])?))
+                },
+                crate::constants::OPCODE_LAYOUT => {
+                    if value.len() != 2 {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    Ok(ClientMessages::GetLayout)
+                },
+                crate::constants::OPCODE_SET_LAYOUT => Ok(ClientMessages::SetLayout(Layout::decode(&value[2..])?)),
+                _ => Err(crate::error::Error::InvalidFlag),
+            },
             crate::constants::INSTRUCTION_SET_ACTIVE => {
-                if value.len() < 2 {
+                if value.len() != 2 {
                     return Err(crate::error::Error::InvalidMessageLength);
                 }
-                Ok(ClientMessages::SetActive(value[1] & crate::constants::DEVICE_MASK))
+                Ok(ClientMessages::SetActive(DeviceId::from_bits(value[1])))
             },
             crate::constants::INSTRUCTION_SEND_PIXELS => {
-                let count = value.len() - 2;
-                let mut pixels = [0; MAX_LED_COUNT * 3];
-                pixels[..count].copy_from_slice(&value[2..MAX_LED_COUNT * 3 + 2]);
-                Ok(ClientMessages::SendPixels(value[1] & crate::constants::DEVICE_MASK, pixels))
+                Ok(ClientMessages::SendPixels(DeviceId::from_bits(value[1]), Frame::from_bytes(&value[2..])?))
             },
             crate::constants::INSTRUCTION_SET_PIXEL => {
-                if value.len() < 6 {
+                if value.len() != 6 {
                     return Err(crate::error::Error::InvalidMessageLength);
                 }
-                Ok(ClientMessages::SetPixel(value[1] & crate::constants::DEVICE_MASK, value[2], value[3], value[4], value[5]))
+                Ok(ClientMessages::SetPixel(DeviceId::from_bits(value[1]), value[2], Rgb::new(value[3], value[4], value[5])))
             },
-            _ => panic!("Unreachable")
-            
+            // The mask keeps the two high bits and each of their four values is an instruction
+            _ => unreachable!("instruction outside of the mask"),
         }
     }
 }
 
-impl Into<[u8; MAX_MESSAGE_LENGTH]> for ClientMessages  {
-    fn into(self) -> [u8; MAX_MESSAGE_LENGTH] {
-        match self {
+impl From<ClientMessages> for [u8; MAX_MESSAGE_LENGTH] {
+    fn from(value: ClientMessages) -> Self {
+        match value {
             ClientMessages::Hello => {
                 let mut message = [0; MAX_MESSAGE_LENGTH];
                 message[0] = CLIENT_FLAG;
                 message[1] = crate::constants::INSTRUCTION_HELLO;
                 message
             },
+            ClientMessages::Claim(token) => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO;
+                message[2..10].copy_from_slice(&token.get().to_be_bytes());
+                message
+            },
             ClientMessages::SetActive(device) => {
                 let mut message = [0; MAX_MESSAGE_LENGTH];
                 message[0] = CLIENT_FLAG;
-                message[1] = crate::constants::INSTRUCTION_SET_ACTIVE | device;
+                message[1] = crate::constants::INSTRUCTION_SET_ACTIVE | device.get();
                 message
             },
-            ClientMessages::SendPixels(device, pixels) => {
+            ClientMessages::SendPixels(device, frame) => {
                 let mut message = [0; MAX_MESSAGE_LENGTH];
                 message[0] = CLIENT_FLAG;
-                message[1] = crate::constants::INSTRUCTION_SEND_PIXELS | device;
-                message[2..pixels.len() + 2].copy_from_slice(&pixels);
+                message[1] = crate::constants::INSTRUCTION_SEND_PIXELS | device.get();
+                frame.write_bytes(&mut message[2..]);
                 message
             },
-            ClientMessages::SetPixel(device, pixel, r, g, b) => {
+            ClientMessages::SetPixel(device, pixel, color) => {
                 let mut message = [0; MAX_MESSAGE_LENGTH];
                 message[0] = CLIENT_FLAG;
-                message[1] = crate::constants::INSTRUCTION_SET_PIXEL | device;
+                message[1] = crate::constants::INSTRUCTION_SET_PIXEL | device.get();
                 message[2] = pixel;
-                message[3] = r;
-                message[4] = g;
-                message[5] = b;
+                message[3] = color.r;
+                message[4] = color.g;
+                message[5] = color.b;
+                message
+            },
+            ClientMessages::Heartbeat(device) => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_HEARTBEAT;
+                message[2] = device.get();
+                message
+            },
+            ClientMessages::SendGroupPixels(device, slices, pixels) => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_SEND_GROUP_PIXELS;
+                message[2] = device.get();
+                message[3] = slices.len() as u8;
+                for (encoded, slice) in message[4..].chunks_exact_mut(Slice::ENCODED_LEN).zip(&slices) {
+                    encoded[..6].copy_from_slice(&slice.server);
+                    encoded[6..8].copy_from_slice(&slice.source.to_be_bytes());
+                    encoded[8..10].copy_from_slice(&slice.len.to_be_bytes());
+                    encoded[10..12].copy_from_slice(&slice.target.to_be_bytes());
+                }
+                let pixels_start = 4 + slices.len() * Slice::ENCODED_LEN;
+                pixels.write_bytes(&mut message[pixels_start..]);
+                message
+            },
+            ClientMessages::GetStatus => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_STATUS;
+                message
+            },
+            ClientMessages::TimeRequest(t1) => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_TIME;
+                message[2..10].copy_from_slice(&t1.to_be_bytes());
+                message
+            },
+            ClientMessages::SendTimedPixels(device, pts, frame) => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_SEND_TIMED_PIXELS;
+                message[2] = device.get();
+                message[3..11].copy_from_slice(&pts.to_be_bytes());
+                frame.write_bytes(&mut message[11..]);
+                message
+            },
+            ClientMessages::GetConfig => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_CONFIG;
+                message
+            },
+            ClientMessages::SetConfig(config) => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_SET_CONFIG;
+                config.encode(&mut message[2..]);
+                message
+            },
+            ClientMessages::OtaBegin(size, hash) => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_OTA_BEGIN;
+                message[2..6].copy_from_slice(&size.to_be_bytes());
+                message[6..38].copy_from_slice(&hash);
+                message
+            },
+            ClientMessages::OtaChunk(offset, data) => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_OTA_CHUNK;
+                message[2..6].copy_from_slice(&offset.to_be_bytes());
+                message[6..6 + data.len()].copy_from_slice(&data);
+                message
+            },
+            ClientMessages::OtaVerify => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_OTA_VERIFY;
+                message
+            },
+            ClientMessages::OtaCommit => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_OTA_COMMIT;
+                message
+            },
+            ClientMessages::OtaReboot => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_OTA_REBOOT;
+                message
+            },
+            ClientMessages::GetSegments => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_SEGMENTS;
+                message
+            },
+            ClientMessages::SetSegments(segments) => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_SET_SEGMENTS;
+                Segment::encode_table(&segments, &mut message[2..]);
+                message
+            },
+            ClientMessages::SetSegmentActive(device, segment) => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_SET_SEGMENT_ACTIVE;
+                message[2] = device.get();
+                message[3] = segment;
+                message
+            },
+            ClientMessages::SendSegmentPixels(device, segment, frame) => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_SEND_SEGMENT_PIXELS;
+                message[2] = device.get();
+                message[3] = segment;
+                frame.write_bytes(&mut message[4..]);
+                message
+            },
+            ClientMessages::GetLayout => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_LAYOUT;
                 message
+            },
+            ClientMessages::SetLayout(layout) => {
+                let mut message = [0; MAX_MESSAGE_LENGTH];
+                message[0] = CLIENT_FLAG;
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_SET_LAYOUT;
+                layout.encode(&mut message[2..]);
+                message
+            },
+        }
+    }
+}
+
+/// A server that answered a hello message
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServerInfo {
+    pub addr: SocketAddr,
+    pub advertisement: Advertisement,
+}
+
+/**
+ * # Client
+ * Blocking client for the LED server
+ *
+ * Takes care of the socket setup, the discovery of the servers and the matching of their responses
+ *
+ * ```no_run
+ * use std::time::Duration;
+ * use udp_leds::{Client, DeviceId, Frame, Rgb};
+ *
+ * let mut client = Client::new().unwrap();
+ * let servers = client.discover(Duration::from_millis(500)).unwrap();
+ * client.connect(servers[0].addr).unwrap();
+ * let device = client.claim().unwrap_or(DeviceId::new(12).unwrap());
+ * client.acquire(device).unwrap();
+ *
+ * let mut frame = Frame::new(64);
+ * frame.fill(Rgb::RED);
+ * client.send_frame(&frame).unwrap();
+ * ```
+ */
+#[derive(Debug)]
+pub struct Client {
+    socket: UdpSocket,
+    broadcast: SocketAddr,
+    server: Option<SocketAddr>,
+    device: Option<DeviceId>,
+    token: ClientToken,
+    clock: ClockSync,
+    #[cfg(feature = "crypto")]
+    ciphers: std::sync::Mutex<std::collections::HashMap<SocketAddr, crate::crypto::Cipher>>,
+}
+
+impl Client {
+    /// Response timeout used when talking to a known server
+    pub const RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+    /// Times a firmware update message is sent before the update gives up
+    pub(crate) const UPDATE_ATTEMPTS: usize = 3;
+
+    /// Creates a new client bound to an ephemeral port
+    pub fn new() -> Result<Self, Error> {
+        Self::bind(("0.0.0.0", 0))
+    }
+
+    /// Creates a new client bound to the given address
+    pub fn bind(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_broadcast(true)?;
+        Ok(Client {
+            socket,
+            broadcast: SocketAddr::from(([255, 255, 255, 255], PORT)),
+            server: None,
+            device: None,
+            token: ClientToken::random(),
+            clock: ClockSync::new(),
+            #[cfg(feature = "crypto")]
+            ciphers: Default::default(),
+        })
+    }
+
+    /// Sets the address the hello messages are sent to during the discovery
+    pub fn set_discovery_address(&mut self, addr: SocketAddr) {
+        self.broadcast = addr;
+    }
+
+    /// Sets the token sent when claiming a device id, to get back the ids claimed by a previous run
+    pub fn set_token(&mut self, token: ClientToken) {
+        self.token = token;
+    }
+
+    /// The token sent when claiming a device id, random unless set
+    pub fn token(&self) -> ClientToken {
+        self.token
+    }
+
+    /// The server the client is connected to
+    pub fn server(&self) -> Option<SocketAddr> {
+        self.server
+    }
+
+    /// The device acquired by the client
+    pub fn device(&self) -> Option<DeviceId> {
+        self.device
+    }
+
+    /// Broadcasts a hello message and collects every server answering before the timeout
+    pub fn discover(&self, timeout: Duration) -> Result<Vec<ServerInfo>, Error> {
+        self.discover_at(self.broadcast, timeout)
+    }
+
+    /// Sends a hello message to the given address and collects every server answering before the timeout
+    fn discover_at(&self, addr: SocketAddr, timeout: Duration) -> Result<Vec<ServerInfo>, Error> {
+        self.send_to(ClientMessages::hello(), addr)?;
+
+        let mut servers: Vec<ServerInfo> = Vec::new();
+        let deadline = Instant::now() + timeout;
+        while let Some((message, addr)) = self.recv_until(deadline)? {
+            let ServerMessages::Hello(advertisement) = message else {
+                continue;
+            };
+            if !servers.iter().any(|server| server.addr == addr) {
+                servers.push(ServerInfo { addr, advertisement });
+            }
+        }
+        Ok(servers)
+    }
+
+    /// Discovers the servers and returns the one with the given name or unique id
+    ///
+    /// Unlike an address, the name and the id of a server survive a change of its DHCP lease
+    pub fn find(&self, name_or_id: &str, timeout: Duration) -> Result<ServerInfo, Error> {
+        self.discover(timeout)?
+            .into_iter()
+            .find(|server| server.advertisement.matches(name_or_id))
+            .ok_or(Error::ServerNotFound)
+    }
+
+    /// Connects to the server with the given name or unique id
+    pub fn connect_by_name(&mut self, name_or_id: &str, timeout: Duration) -> Result<ServerInfo, Error> {
+        let server = self.find(name_or_id, timeout)?;
+        self.connect(server.addr)
+    }
+
+    /// Looks for the servers advertised over mDNS, useful when broadcasts do not reach the servers
+    pub fn browse(&self, timeout: Duration) -> Result<Vec<ServerInfo>, Error> {
+        let services = crate::mdns::Browser::new()?.browse(timeout)?;
+        Ok(services.iter().filter_map(crate::mdns::Service::server_info).collect())
+    }
+
+    /// Connects to the server at the given address, checking that it answers the hello message
+    pub fn connect(&mut self, addr: impl ToSocketAddrs) -> Result<ServerInfo, Error> {
+        let addr = addr.to_socket_addrs()?.next().ok_or(Error::NotConnected)?;
+        self.send_to(ClientMessages::hello(), addr)?;
+
+        let deadline = Instant::now() + Self::RESPONSE_TIMEOUT;
+        while let Some((message, from)) = self.recv_until(deadline)? {
+            let ServerMessages::Hello(advertisement) = message else {
+                continue;
+            };
+            if from == addr {
+                self.server = Some(addr);
+                self.device = None;
+                self.clock.reset();
+                return Ok(ServerInfo { addr, advertisement });
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Connects to a multicast group of servers, returns the servers of the group that answered the hello message
+    ///
+    /// The messages are then sent to the whole group, use `send_group_frames` to give each server its own frame
+    pub fn join_group(&mut self, group: SocketAddr, timeout: Duration) -> Result<Vec<ServerInfo>, Error> {
+        let servers = self.discover_at(group, timeout)?;
+        self.server = Some(group);
+        self.device = None;
+        self.clock.reset();
+        Ok(servers)
+    }
+
+    /// Asks the server for a device id, claiming again with the same token gives the same id back
+    ///
+    /// Fails with `Unsupported` if the server does not assign ids, the client then has to pick its own id
+    pub fn claim(&self) -> Result<DeviceId, Error> {
+        self.request(ClientMessages::claim(self.token), |message| match message {
+            ServerMessages::Welcome(_, device) => Some(device),
+            _ => None,
+        })
+    }
+
+    /// Asks the server for its status
+    ///
+    /// Fails with `Unsupported` if the server does not report its status
+    pub fn status(&self) -> Result<Status, Error> {
+        self.request(ClientMessages::get_status(), |message| match message {
+            ServerMessages::Status(status) => Some(status),
+            _ => None,
+        })
+    }
+
+    /// Asks the server for its config
+    pub fn config(&self) -> Result<Config, Error> {
+        self.request(ClientMessages::get_config(), |message| match message {
+            ServerMessages::Config(config) => Some(config),
+            _ => None,
+        })
+    }
+
+    /// Changes the config of the server, which stores it and applies it
+    ///
+    /// Fails with `InvalidConfig` if the server kept another config, only the client which acquired the active
+    /// device can change it
+    pub fn set_config(&self, config: &Config) -> Result<(), Error> {
+        config.validate()?;
+        let kept = self.request(ClientMessages::set_config(config.clone()), |message| match message {
+            ServerMessages::Config(config) => Some(config),
+            _ => None,
+        })?;
+        if kept != *config {
+            return Err(Error::InvalidConfig);
+        }
+        Ok(())
+    }
+
+    /// Asks the server for its segment table
+    pub fn segments(&self) -> Result<Vec<Segment>, Error> {
+        self.request(ClientMessages::get_segments(), |message| match message {
+            ServerMessages::Segments(segments) => Some(segments),
+            _ => None,
+        })
+    }
+
+    /// Replaces the segment table of the server, which stores it and releases every segment
+    ///
+    /// Fails with `InvalidSegment` if the server kept another table, the segments have to fit on its strip
+    /// and only the client which acquired the active device can replace them while no other device uses a segment
+    pub fn set_segments(&self, segments: &[Segment]) -> Result<(), Error> {
+        Segment::validate_table(segments, crate::constants::MAX_LED_COUNT)?;
+        let kept = self.request(ClientMessages::set_segments(segments.to_vec())?, |message| match message {
+            ServerMessages::Segments(segments) => Some(segments),
+            _ => None,
+        })?;
+        if kept != segments {
+            return Err(Error::InvalidSegment);
+        }
+        Ok(())
+    }
+
+    /// The index of the segment with the given name in the segment table of the server
+    pub fn find_segment(&self, name: &str) -> Result<u8, Error> {
+        let segments = self.segments()?;
+        let index = segments.iter().position(|segment| segment.name == name).ok_or(Error::InvalidSegment)?;
+        Ok(index as u8)
+    }
+
+    /// Makes the device the owner of the segment, the client can then send the pixels of the segment
+    ///
+    /// The device is used for the following messages, like with `acquire`, but the rest of the strip is left to the active device
+    /// The server ignores the request while another device is still sending to the segment
+    pub fn acquire_segment(&mut self, device: DeviceId, segment: u8) -> Result<(), Error> {
+        self.send(ClientMessages::set_segment_active(device, segment))?;
+        self.device = Some(device);
+        Ok(())
+    }
+
+    /// Sends the pixels of a segment owned by the device of the client
+    pub fn send_segment(&self, segment: u8, frame: &Frame) -> Result<(), Error> {
+        let device = self.device.ok_or(Error::NoDevice)?;
+        self.send(ClientMessages::send_segment_pixels(device, segment, frame.clone())?)
+    }
+
+    /// Asks the server where its LEDs are around the screen
+    pub fn layout(&self) -> Result<Layout, Error> {
+        self.request(ClientMessages::get_layout(), |message| match message {
+            ServerMessages::Layout(layout) => Some(layout),
+            _ => None,
+        })
+    }
+
+    /// Describes where the LEDs of the server are, the server stores the layout for the other clients
+    ///
+    /// Fails with `InvalidLayout` if the server kept another layout, the layout has to fit on its strip
+    /// and only the client which acquired the active device can change it
+    pub fn set_layout(&self, layout: &Layout) -> Result<(), Error> {
+        let kept = self.request(ClientMessages::set_layout(layout.clone())?, |message| match message {
+            ServerMessages::Layout(layout) => Some(layout),
+            _ => None,
+        })?;
+        if kept != *layout {
+            return Err(Error::InvalidLayout);
+        }
+        Ok(())
+    }
+
+    /// Uploads a firmware image to the server, checks it, makes it the one to boot and restarts the server
+    ///
+    /// `progress` is called with the number of bytes received by the server and the size of the image
+    /// An upload that failed with `Timeout` resumes where it stopped when the same image is uploaded again
+    /// Only the client which acquired the active device can update the server, the others fail with `UpdateFailed`
+    /// Fails with `Unsupported` if the server cannot be updated over the network
+    pub fn update_firmware(&self, image: &[u8], mut progress: impl FnMut(u32, u32)) -> Result<(), Error> {
+        let size = u32::try_from(image.len()).map_err(|_| Error::UpdateFailed("the image is too large".to_string()))?;
+        let mut status = self.update_request(ClientMessages::ota_begin(image))?;
+        // The state of the server drives the update, so a late answer to a retried message is harmless
+        loop {
+            let message = match status.state {
+                OtaState::Receiving if status.offset < size => {
+                    progress(status.offset, size);
+                    let start = status.offset as usize;
+                    let end = (start + MAX_CHUNK_LENGTH).min(image.len());
+                    ClientMessages::ota_chunk(status.offset, image[start..end].to_vec())?
+                },
+                OtaState::Receiving => {
+                    progress(size, size);
+                    ClientMessages::OtaVerify
+                },
+                OtaState::Verified => ClientMessages::OtaCommit,
+                OtaState::Committed => break,
+                OtaState::Unsupported => return Err(Error::Unsupported),
+                state => return Err(Error::UpdateFailed(format!("the server went {state:?}"))),
+            };
+            status = self.update_request(message)?;
+        }
+        self.update_request(ClientMessages::OtaReboot)?;
+        Ok(())
+    }
+
+    /// Sends a firmware update message, again if the server does not answer
+    fn update_request(&self, message: ClientMessages) -> Result<OtaStatus, Error> {
+        let mut attempts = 1;
+        loop {
+            let answer = self.request(message.clone(), |message| match message {
+                ServerMessages::OtaStatus(status) => Some(status),
+                _ => None,
+            });
+            match answer {
+                Err(Error::Timeout) if attempts < Self::UPDATE_ATTEMPTS => attempts += 1,
+                answer => return answer,
+            }
+        }
+    }
+
+    /// Seals the messages exchanged with the server at `addr` with the cipher, each server having its own keys
+    ///
+    /// The plain messages of this server are then ignored, except the answers to the discovery
+    #[cfg(feature = "crypto")]
+    pub fn set_cipher(&mut self, addr: impl ToSocketAddrs, cipher: crate::crypto::Cipher) -> Result<(), Error> {
+        let addr = addr.to_socket_addrs()?.next().ok_or(Error::NotConnected)?;
+        self.ciphers.get_mut().unwrap().insert(addr, cipher);
+        Ok(())
+    }
+
+    /// Exchanges plain messages with the server at `addr` again, returns its cipher
+    #[cfg(feature = "crypto")]
+    pub fn remove_cipher(&mut self, addr: impl ToSocketAddrs) -> Result<Option<crate::crypto::Cipher>, Error> {
+        let addr = addr.to_socket_addrs()?.next().ok_or(Error::NotConnected)?;
+        Ok(self.ciphers.get_mut().unwrap().remove(&addr))
+    }
+
+    /// Estimates the offset of the server clock with `rounds` time requests, returns the most trusted sample
+    ///
+    /// The offset drifts with time, so long sessions should synchronize again every now and then
+    pub fn sync_clock(&mut self, rounds: usize) -> Result<Sample, Error> {
+        for _ in 0..rounds {
+            let t1 = self.clock.local(Instant::now());
+            let (t2, t3) = self.request(ClientMessages::time_request(t1), |message| match message {
+                ServerMessages::TimeResponse(origin, t2, t3) if origin == t1 => Some((t2, t3)),
+                _ => None,
+            })?;
+            let t4 = self.clock.local(Instant::now());
+            self.clock.add(Sample::new(t1, t2, t3, t4));
+        }
+        self.clock.best().ok_or(Error::NotSynchronized)
+    }
+
+    /// The estimation of the server clock
+    pub fn clock(&self) -> &ClockSync {
+        &self.clock
+    }
+
+    /// Makes the given device the active device of the server
+    pub fn acquire(&mut self, device: DeviceId) -> Result<(), Error> {
+        self.send(ClientMessages::set_active(device))?;
+        self.device = Some(device);
+        Ok(())
+    }
+
+    /// Sends a full frame to the server
+    pub fn send_frame(&self, frame: &Frame) -> Result<(), Error> {
+        let device = self.device.ok_or(Error::NoDevice)?;
+        self.send(ClientMessages::send_pixels(device, frame.clone()))
+    }
+
+    /// Sends a frame the server shows at the given instant of the client, once the clocks are synchronized
+    ///
+    /// Servers synchronized with the same client show the frames sent for the same instant together
+    pub fn send_frame_at(&self, frame: &Frame, at: Instant) -> Result<(), Error> {
+        let device = self.device.ok_or(Error::NoDevice)?;
+        let pts = self.clock.to_server(at).ok_or(Error::NotSynchronized)?;
+        self.send(ClientMessages::send_timed_pixels(device, pts, frame.clone())?)
+    }
+
+    /// Updates a single pixel of the strip
+    pub fn set_pixel(&self, index: u8, color: impl Into<Rgb>) -> Result<(), Error> {
+        let device = self.device.ok_or(Error::NoDevice)?;
+        self.send(ClientMessages::set_pixel(device, index, color))
+    }
+
+    /// Sends one frame per server of the group in a single datagram, the servers are identified by their unique id
+    pub fn send_group_frames(&self, frames: &[([u8; 6], &Frame)]) -> Result<(), Error> {
+        let device = self.device.ok_or(Error::NoDevice)?;
+        self.send(ClientMessages::send_group_pixels(device, frames)?)
+    }
+
+    /// Tells the server the acquired device is still there when no frame was sent for a while
+    pub fn heartbeat(&self) -> Result<(), Error> {
+        let device = self.device.ok_or(Error::NoDevice)?;
+        self.send(ClientMessages::heartbeat(device))
+    }
+
+    /// Closes the connection to the server
+    pub fn close(self) {}
+
+    /// Sends a message to the server and waits for the answer picked by `answer`
+    fn request<T>(&self, message: ClientMessages, answer: impl Fn(ServerMessages) -> Option<T>) -> Result<T, Error> {
+        let server = self.server.ok_or(Error::NotConnected)?;
+        self.send_to(message, server)?;
+
+        let deadline = Instant::now() + Self::RESPONSE_TIMEOUT;
+        while let Some((message, from)) = self.recv_until(deadline)? {
+            if from != server {
+                continue;
+            }
+            let legacy = matches!(message, ServerMessages::Hello(_));
+            if let Some(answer) = answer(message) {
+                return Ok(answer);
+            }
+            // Older servers answer the extended messages they do not know with a hello, any other message
+            // is a late answer to an earlier request
+            if legacy {
+                return Err(Error::Unsupported);
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    fn send(&self, message: ClientMessages) -> Result<(), Error> {
+        let server = self.server.ok_or(Error::NotConnected)?;
+        self.send_to(message, server)
+    }
+
+    fn send_to(&self, message: ClientMessages, addr: SocketAddr) -> Result<(), Error> {
+        let len = message.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.into();
+        #[cfg(feature = "crypto")]
+        if let Some(cipher) = self.ciphers.lock().unwrap().get_mut(&addr) {
+            self.socket.send_to(&cipher.seal(&bytes[..len])?, addr)?;
+            return Ok(());
+        }
+        self.socket.send_to(&bytes[..len], addr)?;
+        Ok(())
+    }
+
+    /// Decodes a server message, opening it with the cipher of the server if there is one
+    fn decode(&self, datagram: &[u8], from: SocketAddr) -> Option<ServerMessages> {
+        #[cfg(feature = "crypto")]
+        if let Some(cipher) = self.ciphers.lock().unwrap().get_mut(&from) {
+            if crate::crypto::Cipher::is_sealed(datagram) {
+                return ServerMessages::try_from(&cipher.open(datagram).ok()?[..]).ok();
+            }
+            return match ServerMessages::try_from(datagram).ok()? {
+                hello @ ServerMessages::Hello(_) => Some(hello),
+                _ => None,
+            };
+        }
+        #[cfg(not(feature = "crypto"))]
+        let _ = from;
+        ServerMessages::try_from(datagram).ok()
+    }
+
+    /// Waits for the next valid server message, returns `None` once the deadline is reached
+    fn recv_until(&self, deadline: Instant) -> Result<Option<(ServerMessages, SocketAddr)>, Error> {
+        let mut buf = [0; MAX_SEALED_LENGTH];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(deadline - now))?;
+            match self.socket.recv_from(&mut buf) {
+                Ok((size, addr)) => {
+                    if let Some(message) = self.decode(&buf[..size], addr) {
+                        return Ok(Some((message, addr)));
+                    }
+                },
+                Err(error) => match Error::from(error) {
+                    Error::Timeout => return Ok(None),
+                    // An earlier datagram reached a port nobody listens on, the other servers may still answer
+                    Error::Io(std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::ConnectionReset) => continue,
+                    error => return Err(error),
+                },
             }
         }
     }
@@ -159,6 +1228,11 @@ impl Into<[u8; MAX_MESSAGE_LENGTH]> for ClientMessages  {
 #[cfg(test)]
 mod test{
     use super::*;
+    use crate::constants::MAX_LED_COUNT;
+
+    fn device(id: u8) -> DeviceId {
+        DeviceId::new(id).unwrap()
+    }
 
     #[test]
     fn test_hello() {
@@ -170,7 +1244,7 @@ mod test{
 
     #[test]
     fn test_set_active() {
-        let message = ClientMessages::SetActive(1);
+        let message = ClientMessages::SetActive(device(1));
         let bytes: [u8; MAX_MESSAGE_LENGTH] = message.into();
         assert_eq!(bytes[0], CLIENT_FLAG);
         assert_eq!(bytes[1], crate::constants::INSTRUCTION_SET_ACTIVE | 1);
@@ -178,9 +1252,9 @@ mod test{
 
     #[test]
     fn test_send_pixels() {
-        let mut bytes = [0; MAX_LED_COUNT * 3];
-        bytes[0..13].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
-        let message = ClientMessages::SendPixels(0, bytes);
+        let frame = Frame::from_bytes(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]).unwrap();
+        let message = ClientMessages::SendPixels(device(0), frame);
+        assert_eq!(message.encoded_len(), 14);
         let bytes: [u8; MAX_MESSAGE_LENGTH] = message.into();
         assert_eq!(bytes[0], CLIENT_FLAG);
         assert_eq!(bytes[1], crate::constants::INSTRUCTION_SEND_PIXELS);
@@ -200,7 +1274,7 @@ mod test{
 
     #[test]
     fn test_set_pixel() {
-        let message = ClientMessages::SetPixel(1, 2, 3, 4, 5);
+        let message = ClientMessages::SetPixel(device(1), 2, Rgb::new(3, 4, 5));
         let bytes: [u8; MAX_MESSAGE_LENGTH] = message.into();
         assert_eq!(bytes[0], CLIENT_FLAG);
         assert_eq!(bytes[1], crate::constants::INSTRUCTION_SET_PIXEL | 1);
@@ -210,6 +1284,126 @@ mod test{
         assert_eq!(bytes[5], 5);
     }
 
+    #[test]
+    fn test_claim() {
+        let message = ClientMessages::claim(ClientToken::from(0x0102_0304_0506_0708));
+        let len = message.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.clone().into();
+        assert_eq!(bytes[..len], [CLIENT_FLAG, crate::constants::INSTRUCTION_HELLO, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(ClientMessages::try_from(&bytes[..len]), Ok(message));
+        assert_eq!(ClientMessages::try_from(&bytes[..9]), Err(crate::error::Error::InvalidMessageLength));
+    }
+
+    #[test]
+    fn test_get_status() {
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = ClientMessages::get_status().into();
+        assert_eq!(bytes[..2], [CLIENT_FLAG, 0b1100_0011]);
+        assert_eq!(ClientMessages::try_from(&bytes[..2]), Ok(ClientMessages::GetStatus));
+        assert_eq!(ClientMessages::try_from(&bytes[..3]), Err(crate::error::Error::InvalidMessageLength));
+    }
+
+    #[test]
+    fn test_config_messages() {
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = ClientMessages::get_config().into();
+        assert_eq!(bytes[..2], [CLIENT_FLAG, 0b1100_0110]);
+        assert_eq!(ClientMessages::try_from(&bytes[..2]), Ok(ClientMessages::GetConfig));
+
+        let config = Config { led_count: 60, color_order: crate::config::ColorOrder::Grb, output_pin: 32, name: "tv".to_string() };
+        let message = ClientMessages::set_config(config);
+        let len = message.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.clone().into();
+        assert_eq!(bytes[..len], [CLIENT_FLAG, 0b1100_0111, 1, 0, 60, 2, 32, 2, b't', b'v']);
+        assert_eq!(ClientMessages::try_from(&bytes[..len]), Ok(message));
+        assert_eq!(ClientMessages::try_from(&bytes[..len - 1]), Err(crate::error::Error::InvalidMessageLength));
+    }
+
+    #[test]
+    fn test_ota_messages() {
+        let message = ClientMessages::ota_begin(&[1, 2, 3]);
+        let len = message.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.clone().into();
+        assert_eq!(bytes[..6], [CLIENT_FLAG, 0b1100_1000, 0, 0, 0, 3]);
+        assert_eq!(bytes[6..len], crate::ota::hash(&[1, 2, 3]));
+        assert_eq!(ClientMessages::try_from(&bytes[..len]), Ok(message));
+
+        let message = ClientMessages::ota_chunk(0x0100, vec![9; MAX_CHUNK_LENGTH]).unwrap();
+        let len = message.encoded_len();
+        assert_eq!(len, MAX_MESSAGE_LENGTH);
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.clone().into();
+        assert_eq!(bytes[..7], [CLIENT_FLAG, 0b1100_1001, 0, 0, 1, 0, 9]);
+        assert_eq!(ClientMessages::try_from(&bytes[..len]), Ok(message));
+        assert_eq!(ClientMessages::ota_chunk(0, vec![9; MAX_CHUNK_LENGTH + 1]), Err(crate::error::Error::InvalidMessageLength));
+
+        for (message, opcode) in [(ClientMessages::OtaVerify, 10), (ClientMessages::OtaCommit, 11), (ClientMessages::OtaReboot, 12)] {
+            let bytes: [u8; MAX_MESSAGE_LENGTH] = message.clone().into();
+            assert_eq!(bytes[..2], [CLIENT_FLAG, 0b1100_0000 | opcode]);
+            assert_eq!(ClientMessages::try_from(&bytes[..2]), Ok(message));
+        }
+    }
+
+    #[test]
+    fn test_segment_messages() {
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = ClientMessages::get_segments().into();
+        assert_eq!(ClientMessages::try_from(&bytes[..2]), Ok(ClientMessages::GetSegments));
+
+        let message = ClientMessages::set_segments(vec![Segment::new("top", 0, 10)]).unwrap();
+        let len = message.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.clone().into();
+        assert_eq!(bytes[..len], [CLIENT_FLAG, 0b1100_1110, 1, 0, 0, 0, 10, 0, 3, b't', b'o', b'p']);
+        assert_eq!(ClientMessages::try_from(&bytes[..len]), Ok(message));
+        assert_eq!(ClientMessages::set_segments(vec![Segment::default(); 17]), Err(crate::error::Error::InvalidSegment));
+
+        let message = ClientMessages::set_segment_active(device(3), 1);
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.clone().into();
+        assert_eq!(bytes[..4], [CLIENT_FLAG, 0b1100_1111, 3, 1]);
+        assert_eq!(ClientMessages::try_from(&bytes[..4]), Ok(message));
+
+        let message = ClientMessages::send_segment_pixels(device(3), 1, Frame::from_bytes(&[1, 2, 3]).unwrap()).unwrap();
+        let len = message.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.clone().into();
+        assert_eq!(bytes[..len], [CLIENT_FLAG, 0b1101_0000, 3, 1, 1, 2, 3]);
+        assert_eq!(ClientMessages::try_from(&bytes[..len]), Ok(message));
+        assert_eq!(ClientMessages::try_from(&bytes[..3]), Err(crate::error::Error::InvalidMessageLength));
+    }
+
+    #[test]
+    fn test_layout_messages() {
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = ClientMessages::get_layout().into();
+        assert_eq!(bytes[..2], [CLIENT_FLAG, 0b1101_0001]);
+        assert_eq!(ClientMessages::try_from(&bytes[..2]), Ok(ClientMessages::GetLayout));
+
+        let points = vec![crate::layout::Point { x: 0, y: 255 }; crate::constants::MAX_LED_COUNT];
+        let message = ClientMessages::set_layout(Layout::Points(points.clone())).unwrap();
+        let len = message.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.clone().into();
+        assert_eq!(bytes[..5], [CLIENT_FLAG, 0b1101_0010, 2, 1, 0]);
+        assert_eq!(ClientMessages::try_from(&bytes[..len]), Ok(message));
+        let too_many = Layout::Points(vec![crate::layout::Point::default(); crate::constants::MAX_LED_COUNT + 1]);
+        assert_eq!(ClientMessages::set_layout(too_many), Err(crate::error::Error::InvalidLayout));
+    }
+
+    #[test]
+    fn test_time_messages() {
+        let message = ClientMessages::time_request(0x0102);
+        let len = message.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.clone().into();
+        assert_eq!(bytes[..len], [CLIENT_FLAG, 0b1100_0100, 0, 0, 0, 0, 0, 0, 1, 2]);
+        assert_eq!(ClientMessages::try_from(&bytes[..len]), Ok(message));
+
+        let frame = Frame::from_bytes(&[1, 2, 3]).unwrap();
+        let message = ClientMessages::send_timed_pixels(device(2), 1_000_000, frame).unwrap();
+        let len = message.encoded_len();
+        assert_eq!(len, 14);
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.clone().into();
+        assert_eq!(bytes[2], 2);
+        assert_eq!(ClientMessages::try_from(&bytes[..len]), Ok(message));
+        assert_eq!(ClientMessages::try_from(&bytes[..10]), Err(crate::error::Error::InvalidMessageLength));
+
+        // The timestamp leaves room for 253 pixels
+        assert!(ClientMessages::send_timed_pixels(device(2), 0, Frame::new(253)).is_ok());
+        assert_eq!(ClientMessages::send_timed_pixels(device(2), 0, Frame::new(254)), Err(Error::InvalidMessageLength));
+    }
+
     #[test]
     fn test_try_from_hello() {
         let bytes = [CLIENT_FLAG, crate::constants::INSTRUCTION_HELLO];
@@ -217,11 +1411,64 @@ mod test{
         assert_eq!(message, ClientMessages::Hello);
     }
 
+    #[test]
+    fn test_heartbeat() {
+        let message = ClientMessages::heartbeat(device(7));
+        let len = message.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.into();
+        assert_eq!(bytes[..len], [CLIENT_FLAG, crate::constants::INSTRUCTION_HELLO | 1, 7]);
+        assert_eq!(ClientMessages::try_from(&bytes[..len]), Ok(ClientMessages::Heartbeat(device(7))));
+        assert_eq!(ClientMessages::try_from(&bytes[..2]), Err(crate::error::Error::InvalidMessageLength));
+        assert_eq!(ClientMessages::try_from(&[CLIENT_FLAG, 0b1111_1111, 0][..]), Err(crate::error::Error::InvalidFlag));
+    }
+
+    #[test]
+    fn test_send_group_pixels() {
+        let mut first = Frame::new(2);
+        first.fill((1, 2, 3));
+        let mut second = Frame::new(3);
+        second.fill((4, 5, 6));
+        let message = ClientMessages::send_group_pixels(device(3), &[([1; 6], &first), ([2; 6], &second)]).unwrap();
+        let ClientMessages::SendGroupPixels(_, slices, _) = &message else {
+            panic!("Unexpected message {message:?}");
+        };
+        assert_eq!(slices[1], Slice { server: [2; 6], source: 2, len: 3, target: 0 });
+
+        let len = message.encoded_len();
+        assert_eq!(len, 4 + 2 * 12 + 5 * 3);
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = message.clone().into();
+        assert_eq!(ClientMessages::try_from(&bytes[..len]), Ok(message));
+        // The last slice points past the pixels
+        assert_eq!(ClientMessages::try_from(&bytes[..len - 1]), Err(crate::error::Error::InvalidMessageLength));
+
+        let big = Frame::new(MAX_LED_COUNT);
+        assert_eq!(ClientMessages::send_group_pixels(device(3), &[([1; 6], &big)]), Err(Error::InvalidMessageLength));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let frame = Frame::from_bytes(&[0xab, 0, 0x01, 0xff, 0, 0]).unwrap();
+        let message = ClientMessages::SendPixels(device(3), frame);
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(json, r#"{"SendPixels":[3,"ab0001ff0000"]}"#);
+        assert_eq!(serde_json::from_str::<ClientMessages>(&json).unwrap(), message);
+
+        let slice = Slice { server: [1; 6], source: 0, len: 1, target: 4 };
+        let message = ClientMessages::SendGroupPixels(device(1), vec![slice], Frame::from_bytes(&[1, 2, 3]).unwrap());
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(json.ends_with(r#""010203"]}"#), "{json}");
+        assert_eq!(serde_json::from_str::<ClientMessages>(&json).unwrap(), message);
+
+        assert!(serde_json::from_str::<ClientMessages>(r#"{"SendPixels":[3,"ab0"]}"#).is_err());
+        assert!(serde_json::from_str::<ClientMessages>(r#"{"SendPixels":[64,""]}"#).is_err());
+    }
+
     #[test]
     fn test_try_from_set_active() {
         let bytes = [CLIENT_FLAG, crate::constants::INSTRUCTION_SET_ACTIVE | 1];
         let message = ClientMessages::try_from(&bytes[..]).unwrap();
-        assert_eq!(message, ClientMessages::SetActive(1));
+        assert_eq!(message, ClientMessages::SetActive(device(1)));
     }
 
     #[test]
@@ -229,9 +1476,8 @@ mod test{
         let bytes = [CLIENT_FLAG, crate::constants::INSTRUCTION_SEND_PIXELS, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
         let message = ClientMessages::try_from(&bytes[..]).unwrap();
 
-        let mut bytes = [0; MAX_LED_COUNT * 3];
-        bytes[0..13].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
-        assert_eq!(message, ClientMessages::SendPixels(0,bytes));
+        let frame = Frame::from_bytes(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]).unwrap();
+        assert_eq!(message, ClientMessages::SendPixels(device(0), frame));
     }
 
     #[test]
@@ -239,7 +1485,7 @@ mod test{
         let bytes = [CLIENT_FLAG, crate::constants::INSTRUCTION_SET_PIXEL | 1, 0, 2, 3, 4];
         
         let message = ClientMessages::try_from(&bytes[..]).unwrap();
-        assert_eq!(message, ClientMessages::SetPixel(1, 0, 2, 3, 4));
+        assert_eq!(message, ClientMessages::SetPixel(device(1), 0, Rgb::new(2, 3, 4)));
     }
 
     #[test]
@@ -263,4 +1509,165 @@ mod test{
         assert_eq!(message, Err(crate::error::Error::InvalidMessageLength));
     }
 
-}
\ No newline at end of file
+    fn fake_server() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        socket
+    }
+
+    fn advertisement(name: &str) -> Advertisement {
+        Advertisement {
+            led_count: 64,
+            max_fps: 30,
+            id: [name.len() as u8; 6],
+            name: name.to_string(),
+        }
+    }
+
+    fn answer_hello(server: &UdpSocket, name: &str) {
+        let mut buf = [0; MAX_MESSAGE_LENGTH];
+        let (size, addr) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(ClientMessages::try_from(&buf[..size]), Ok(ClientMessages::Hello));
+        let response = ServerMessages::Hello(advertisement(name));
+        let len = response.encoded_len();
+        let bytes: [u8; MAX_MESSAGE_LENGTH] = response.into();
+        server.send_to(&bytes[..len], addr).unwrap();
+    }
+
+    #[test]
+    fn test_client_discover() {
+        let server = fake_server();
+        let mut client = Client::bind("127.0.0.1:0").unwrap();
+        client.set_discovery_address(server.local_addr().unwrap());
+
+        let handle = std::thread::spawn(move || {
+            answer_hello(&server, "desk");
+            server
+        });
+        let servers = client.discover(Duration::from_millis(200)).unwrap();
+        let server = handle.join().unwrap();
+        assert_eq!(servers, vec![ServerInfo {
+            addr: server.local_addr().unwrap(),
+            advertisement: advertisement("desk"),
+        }]);
+    }
+
+    #[test]
+    fn test_client_discover_every_server() {
+        let relay = fake_server();
+        let first = fake_server();
+        let second = fake_server();
+        let mut client = Client::bind("127.0.0.1:0").unwrap();
+        client.set_discovery_address(relay.local_addr().unwrap());
+
+        let second_addr = second.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            // Stands in for the broadcast by forwarding the hello to both servers
+            let mut buf = [0; MAX_MESSAGE_LENGTH];
+            let (size, client) = relay.recv_from(&mut buf).unwrap();
+            for server in [&first, &second] {
+                relay.send_to(&buf[..size], server.local_addr().unwrap()).unwrap();
+            }
+            for (server, name) in [(&first, "desk"), (&second, "tv")] {
+                let (size, _) = server.recv_from(&mut buf).unwrap();
+                assert_eq!(ClientMessages::try_from(&buf[..size]), Ok(ClientMessages::Hello));
+                let response = ServerMessages::Hello(advertisement(name));
+                let len = response.encoded_len();
+                let bytes: [u8; MAX_MESSAGE_LENGTH] = response.into();
+                server.send_to(&bytes[..len], client).unwrap();
+            }
+        });
+        let servers = client.discover(Duration::from_millis(300)).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(servers.len(), 2);
+        let tv = servers.iter().find(|server| server.advertisement.matches("TV")).unwrap();
+        assert_eq!(tv.addr, second_addr);
+    }
+
+    #[test]
+    fn test_client_connect_timeout() {
+        let server = fake_server();
+        let mut client = Client::bind("127.0.0.1:0").unwrap();
+        assert_eq!(client.connect(server.local_addr().unwrap()), Err(Error::Timeout));
+        assert_eq!(client.server(), None);
+    }
+
+    #[test]
+    fn test_client_claim() {
+        let server = fake_server();
+        let addr = server.local_addr().unwrap();
+        let mut client = Client::bind("127.0.0.1:0").unwrap();
+        assert_eq!(client.claim(), Err(Error::NotConnected));
+        client.set_token(ClientToken::from(7));
+
+        let handle = std::thread::spawn(move || {
+            answer_hello(&server, "desk");
+            let mut buf = [0; MAX_MESSAGE_LENGTH];
+            let (size, addr) = server.recv_from(&mut buf).unwrap();
+            assert_eq!(ClientMessages::try_from(&buf[..size]), Ok(ClientMessages::Claim(ClientToken::from(7))));
+            let response = ServerMessages::Welcome(advertisement("desk"), device(9));
+            let len = response.encoded_len();
+            let bytes: [u8; MAX_MESSAGE_LENGTH] = response.into();
+            server.send_to(&bytes[..len], addr).unwrap();
+
+            // Then answers like a server predating the claims
+            server.recv_from(&mut buf).unwrap();
+            let response = ServerMessages::Hello(advertisement("desk"));
+            let len = response.encoded_len();
+            let bytes: [u8; MAX_MESSAGE_LENGTH] = response.into();
+            server.send_to(&bytes[..len], addr).unwrap();
+        });
+        client.connect(addr).unwrap();
+        assert_eq!(client.claim(), Ok(device(9)));
+        assert_eq!(client.claim(), Err(Error::Unsupported));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_client_skips_late_answers() {
+        let server = fake_server();
+        let addr = server.local_addr().unwrap();
+        let mut client = Client::bind("127.0.0.1:0").unwrap();
+
+        let handle = std::thread::spawn(move || {
+            answer_hello(&server, "desk");
+            let mut buf = [0; MAX_MESSAGE_LENGTH];
+            let (_, addr) = server.recv_from(&mut buf).unwrap();
+            // Answer to a request which timed out before
+            for response in [ServerMessages::Config(Config::default()), ServerMessages::Welcome(advertisement("desk"), device(9))] {
+                let len = response.encoded_len();
+                let bytes: [u8; MAX_MESSAGE_LENGTH] = response.into();
+                server.send_to(&bytes[..len], addr).unwrap();
+            }
+        });
+        client.connect(addr).unwrap();
+        assert_eq!(client.claim(), Ok(device(9)));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_client_send_frame() {
+        let server = fake_server();
+        let addr = server.local_addr().unwrap();
+        let mut client = Client::bind("127.0.0.1:0").unwrap();
+        assert_eq!(client.send_frame(&Frame::new(1)), Err(Error::NoDevice));
+
+        let handle = std::thread::spawn(move || {
+            answer_hello(&server, "desk");
+            server
+        });
+        client.connect(addr).unwrap();
+        let server = handle.join().unwrap();
+        client.acquire(device(3)).unwrap();
+        let mut frame = Frame::new(2);
+        frame.set(1, (7, 8, 9));
+        client.send_frame(&frame).unwrap();
+
+        let mut buf = [0; MAX_MESSAGE_LENGTH];
+        let (size, _) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(ClientMessages::try_from(&buf[..size]), Ok(ClientMessages::SetActive(device(3))));
+        let (size, _) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(ClientMessages::try_from(&buf[..size]), Ok(ClientMessages::SendPixels(device(3), frame)));
+    }
+}
@@ -0,0 +1,143 @@
+/// A 24 bits RGB color, as sent on the wire
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb::new(0, 0, 0);
+    pub const WHITE: Rgb = Rgb::new(255, 255, 255);
+    pub const RED: Rgb = Rgb::new(255, 0, 0);
+    pub const GREEN: Rgb = Rgb::new(0, 255, 0);
+    pub const BLUE: Rgb = Rgb::new(0, 0, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    /// Multiplies every component by `level`, clamped between 0 and 1
+    pub fn scale(self, level: f32) -> Self {
+        let level = level.clamp(0.0, 1.0);
+        let scale = |value: u8| (value as f32 * level).round() as u8;
+        Rgb::new(scale(self.r), scale(self.g), scale(self.b))
+    }
+
+    /// Mixes two colors, `t` going from 0 (only `self`) to 1 (only `other`)
+    pub fn lerp(self, other: Rgb, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * t).round() as u8;
+        Rgb::new(mix(self.r, other.r), mix(self.g, other.g), mix(self.b, other.b))
+    }
+
+    /// Fully saturated color of the given hue, the hue wraps around every 1.0
+    pub fn from_hue(hue: f32) -> Self {
+        let h = hue.rem_euclid(1.0) * 6.0;
+        let x = (255.0 * (1.0 - (h % 2.0 - 1.0).abs())).round() as u8;
+        match h as u8 {
+            0 => Rgb::new(255, x, 0),
+            1 => Rgb::new(x, 255, 0),
+            2 => Rgb::new(0, 255, x),
+            3 => Rgb::new(0, x, 255),
+            4 => Rgb::new(x, 0, 255),
+            _ => Rgb::new(255, 0, x),
+        }
+    }
+}
+
+impl std::str::FromStr for Rgb {
+    type Err = crate::error::Error;
+
+    /// Reads a color written as `#rrggbb`, `rrggbb`, `r,g,b` or by its name (black, white, red, green or blue)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.to_ascii_lowercase().as_str() {
+            "black" => return Ok(Rgb::BLACK),
+            "white" => return Ok(Rgb::WHITE),
+            "red" => return Ok(Rgb::RED),
+            "green" => return Ok(Rgb::GREEN),
+            "blue" => return Ok(Rgb::BLUE),
+            _ => {},
+        }
+        if s.contains(',') {
+            let components = s
+                .split(',')
+                .map(|component| component.trim().parse::<u8>().map_err(|_| crate::error::Error::InvalidColor))
+                .collect::<Result<Vec<u8>, _>>()?;
+            let [r, g, b] = components[..] else {
+                return Err(crate::error::Error::InvalidColor);
+            };
+            return Ok(Rgb::new(r, g, b));
+        }
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(crate::error::Error::InvalidColor);
+        }
+        let component = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| crate::error::Error::InvalidColor);
+        Ok(Rgb::new(component(0)?, component(2)?, component(4)?))
+    }
+}
+
+impl From<(u8, u8, u8)> for Rgb {
+    fn from((r, g, b): (u8, u8, u8)) -> Self {
+        Rgb::new(r, g, b)
+    }
+}
+
+impl From<[u8; 3]> for Rgb {
+    fn from([r, g, b]: [u8; 3]) -> Self {
+        Rgb::new(r, g, b)
+    }
+}
+
+impl From<Rgb> for [u8; 3] {
+    fn from(value: Rgb) -> Self {
+        [value.r, value.g, value.b]
+    }
+}
+
+impl From<Rgb> for (u8, u8, u8) {
+    fn from(value: Rgb) -> Self {
+        (value.r, value.g, value.b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let color = Rgb::from((1, 2, 3));
+        assert_eq!(color, Rgb::from([1, 2, 3]));
+        assert_eq!(<[u8; 3]>::from(color), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("#ff8000".parse(), Ok(Rgb::new(255, 128, 0)));
+        assert_eq!("FF8000".parse(), Ok(Rgb::new(255, 128, 0)));
+        assert_eq!("255, 128,0".parse(), Ok(Rgb::new(255, 128, 0)));
+        assert_eq!("Red".parse(), Ok(Rgb::RED));
+        assert_eq!("#ff80".parse::<Rgb>(), Err(crate::error::Error::InvalidColor));
+        assert_eq!("1,2".parse::<Rgb>(), Err(crate::error::Error::InvalidColor));
+        assert_eq!("256,0,0".parse::<Rgb>(), Err(crate::error::Error::InvalidColor));
+        assert_eq!("#gg0000".parse::<Rgb>(), Err(crate::error::Error::InvalidColor));
+    }
+
+    #[test]
+    fn test_scale_and_lerp() {
+        assert_eq!(Rgb::new(200, 100, 0).scale(0.5), Rgb::new(100, 50, 0));
+        assert_eq!(Rgb::BLACK.lerp(Rgb::new(200, 100, 10), 0.5), Rgb::new(100, 50, 5));
+        assert_eq!(Rgb::RED.lerp(Rgb::BLUE, 2.0), Rgb::BLUE);
+    }
+
+    #[test]
+    fn test_from_hue() {
+        assert_eq!(Rgb::from_hue(0.0), Rgb::RED);
+        assert_eq!(Rgb::from_hue(0.5), Rgb::new(0, 255, 255));
+        assert_eq!(Rgb::from_hue(-2.0 / 3.0), Rgb::GREEN);
+    }
+}
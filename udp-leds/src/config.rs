@@ -0,0 +1,213 @@
+use crate::{color::Rgb, constants::MAX_LED_COUNT, error::Error, server::Advertisement};
+
+/// Order in which the LEDs expect the color channels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorOrder {
+    #[default]
+    Rgb,
+    Rbg,
+    Grb,
+    Gbr,
+    Brg,
+    Bgr,
+}
+
+impl ColorOrder {
+    /// The channels of the color in the order the LEDs expect them
+    pub fn apply(self, color: Rgb) -> [u8; 3] {
+        let Rgb { r, g, b } = color;
+        match self {
+            ColorOrder::Rgb => [r, g, b],
+            ColorOrder::Rbg => [r, b, g],
+            ColorOrder::Grb => [g, r, b],
+            ColorOrder::Gbr => [g, b, r],
+            ColorOrder::Brg => [b, r, g],
+            ColorOrder::Bgr => [b, g, r],
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorOrder {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ColorOrder::Rgb),
+            1 => Ok(ColorOrder::Rbg),
+            2 => Ok(ColorOrder::Grb),
+            3 => Ok(ColorOrder::Gbr),
+            4 => Ok(ColorOrder::Brg),
+            5 => Ok(ColorOrder::Bgr),
+            _ => Err(Error::InvalidConfig),
+        }
+    }
+}
+
+impl From<ColorOrder> for u8 {
+    fn from(value: ColorOrder) -> Self {
+        value as u8
+    }
+}
+
+impl std::str::FromStr for ColorOrder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rgb" => Ok(ColorOrder::Rgb),
+            "rbg" => Ok(ColorOrder::Rbg),
+            "grb" => Ok(ColorOrder::Grb),
+            "gbr" => Ok(ColorOrder::Gbr),
+            "brg" => Ok(ColorOrder::Brg),
+            "bgr" => Ok(ColorOrder::Bgr),
+            _ => Err(Error::InvalidConfig),
+        }
+    }
+}
+
+/**
+ * # Config
+ * Settings of a controller that used to be compile time constants, read and changed over the protocol
+ * and stored by the controller so they survive a restart
+ *
+ * ## Encoding
+ * The config starts with its version, later versions only append fields so a decoder reads the fields
+ * it knows and ignores the rest
+ * [version, led_count_hi, led_count_lo, color_order, output_pin, name_len, name...]
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Config {
+    /// Number of LEDs of the strip
+    pub led_count: u16,
+    pub color_order: ColorOrder,
+    /// GPIO driving the data line of the strip, one of `OUTPUT_PINS`, a change is applied once the controller restarts
+    pub output_pin: u8,
+    /// Name advertised by the controller, at most 32 bytes of UTF-8
+    pub name: String,
+}
+
+impl Config {
+    /// Version of the encoding written by this crate
+    pub const VERSION: u8 = 1;
+    const HEADER_LEN: usize = 6;
+    /// GPIOs of the ESP32 able to drive the strip, the flash pins (6 to 11), the input only pins (34 to 39)
+    /// and the pins of the serial console (1 and 3) are left out
+    pub const OUTPUT_PINS: [u8; 20] = [0, 2, 4, 5, 12, 13, 14, 15, 16, 17, 18, 19, 21, 22, 23, 25, 26, 27, 32, 33];
+
+    /// Checks the values can be applied by a controller
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.led_count == 0 || self.led_count as usize > MAX_LED_COUNT {
+            return Err(Error::InvalidConfig);
+        }
+        if !Self::OUTPUT_PINS.contains(&self.output_pin) {
+            return Err(Error::InvalidConfig);
+        }
+        if self.name.is_empty() || self.name.len() > Advertisement::MAX_NAME_LENGTH {
+            return Err(Error::InvalidConfig);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn encoded_len(&self) -> usize {
+        Self::HEADER_LEN + self.encoded_name().len()
+    }
+
+    pub(crate) fn encode(&self, out: &mut [u8]) {
+        out[0] = Self::VERSION;
+        out[1..3].copy_from_slice(&self.led_count.to_be_bytes());
+        out[3] = self.color_order.into();
+        out[4] = self.output_pin;
+        let name = self.encoded_name();
+        out[5] = name.len() as u8;
+        out[Self::HEADER_LEN..Self::HEADER_LEN + name.len()].copy_from_slice(name);
+    }
+
+    pub(crate) fn decode(value: &[u8]) -> Result<Self, Error> {
+        if value.len() < Self::HEADER_LEN || value.len() < Self::HEADER_LEN + value[5] as usize {
+            return Err(Error::InvalidMessageLength);
+        }
+        if value[0] == 0 {
+            return Err(Error::InvalidConfig);
+        }
+        Ok(Config {
+            led_count: u16::from_be_bytes([value[1], value[2]]),
+            color_order: ColorOrder::try_from(value[3])?,
+            output_pin: value[4],
+            name: String::from_utf8_lossy(&value[Self::HEADER_LEN..Self::HEADER_LEN + value[5] as usize]).into_owned(),
+        })
+    }
+
+    /// Encodes the config on its own, as stored by the controllers
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; self.encoded_len()];
+        self.encode(&mut bytes);
+        bytes
+    }
+
+    /// Decodes a config stored by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::decode(bytes)
+    }
+
+    fn encoded_name(&self) -> &[u8] {
+        let mut len = self.name.len().min(Advertisement::MAX_NAME_LENGTH);
+        while !self.name.is_char_boundary(len) {
+            len -= 1;
+        }
+        &self.name.as_bytes()[..len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config { led_count: 120, color_order: ColorOrder::Grb, output_pin: 32, name: "desk".to_string() }
+    }
+
+    #[test]
+    fn test_color_order() {
+        let color = Rgb::new(1, 2, 3);
+        assert_eq!(ColorOrder::Rgb.apply(color), [1, 2, 3]);
+        assert_eq!(ColorOrder::Grb.apply(color), [2, 1, 3]);
+        assert_eq!(ColorOrder::Bgr.apply(color), [3, 2, 1]);
+        for value in 0..6 {
+            assert_eq!(u8::from(ColorOrder::try_from(value).unwrap()), value);
+        }
+        assert_eq!(ColorOrder::try_from(6), Err(Error::InvalidConfig));
+        assert_eq!("GRB".parse(), Ok(ColorOrder::Grb));
+        assert_eq!("rgbw".parse::<ColorOrder>(), Err(Error::InvalidConfig));
+    }
+
+    #[test]
+    fn test_encoding() {
+        let bytes = config().to_bytes();
+        assert_eq!(bytes, [1, 0, 120, 2, 32, 4, b'd', b'e', b's', b'k']);
+        assert_eq!(Config::from_bytes(&bytes), Ok(config()));
+        assert_eq!(Config::from_bytes(&bytes[..9]), Err(Error::InvalidMessageLength));
+
+        // The fields of later versions are skipped
+        let mut later = bytes.clone();
+        later[0] = 2;
+        later.extend_from_slice(&[9, 9]);
+        assert_eq!(Config::from_bytes(&later), Ok(config()));
+        later[0] = 0;
+        assert_eq!(Config::from_bytes(&later), Err(Error::InvalidConfig));
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(config().validate(), Ok(()));
+        assert_eq!(Config { led_count: 0, ..config() }.validate(), Err(Error::InvalidConfig));
+        assert_eq!(Config { led_count: 257, ..config() }.validate(), Err(Error::InvalidConfig));
+        assert_eq!(Config { name: String::new(), ..config() }.validate(), Err(Error::InvalidConfig));
+        // Flash, input only and missing pins
+        for output_pin in [6, 11, 34, 39, 40] {
+            assert_eq!(Config { output_pin, ..config() }.validate(), Err(Error::InvalidConfig));
+        }
+        assert_eq!(Config { name: "x".repeat(33), ..config() }.validate(), Err(Error::InvalidConfig));
+    }
+}
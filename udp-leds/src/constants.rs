@@ -1,9 +1,17 @@
 pub const MAX_LED_COUNT: usize = 256;
 pub const MAX_MESSAGE_LENGTH: usize = MAX_LED_COUNT * 3 + 2;
 pub const PORT: u16 = 52772;
+/// Multicast group joined by the servers, frames sent to it reach every server of the group at once
+pub const MULTICAST_GROUP: std::net::Ipv4Addr = std::net::Ipv4Addr::new(239, 255, 82, 76);
 
 pub(crate) const SERVER_FLAG: u8 = 0b1110_0110;
 pub(crate) const CLIENT_FLAG: u8 = 0b0110_1011;
+/// Flag of the encrypted datagrams, whichever side sent them
+pub(crate) const SEALED_FLAG: u8 = 0b1001_1101;
+
+/// Bytes added by the encryption: the flag, the key id, the nonce and the tag
+pub const SEAL_OVERHEAD: usize = 2 + 24 + 16;
+pub const MAX_SEALED_LENGTH: usize = MAX_MESSAGE_LENGTH + SEAL_OVERHEAD;
 
 pub(crate) const INSTRUCTION_MASK: u8 = 0b1100_0000;
 pub(crate) const DEVICE_MASK: u8 = 0b0011_1111;
@@ -12,3 +20,31 @@ pub(crate) const INSTRUCTION_HELLO: u8 = 0b1100_0000;
 pub(crate) const INSTRUCTION_SET_ACTIVE: u8 = 0b0100_0000;
 pub(crate) const INSTRUCTION_SEND_PIXELS: u8 = 0b0000_0000;
 pub(crate) const INSTRUCTION_SET_PIXEL: u8 = 0b1000_0000;
+
+/// Extended messages reuse the hello instruction, the 6 least significant bits give the opcode instead of the device
+pub(crate) const OPCODE_MASK: u8 = 0b0011_1111;
+pub(crate) const OPCODE_HELLO: u8 = 0;
+pub(crate) const OPCODE_HEARTBEAT: u8 = 1;
+pub(crate) const OPCODE_SEND_GROUP_PIXELS: u8 = 2;
+/// The server answers a status request with the same opcode
+pub(crate) const OPCODE_STATUS: u8 = 3;
+/// The server answers a time request with the same opcode
+pub(crate) const OPCODE_TIME: u8 = 4;
+pub(crate) const OPCODE_SEND_TIMED_PIXELS: u8 = 5;
+/// The server answers both config messages with its config under this opcode
+pub(crate) const OPCODE_CONFIG: u8 = 6;
+pub(crate) const OPCODE_SET_CONFIG: u8 = 7;
+/// The server answers the update messages with its update status under this opcode
+pub(crate) const OPCODE_OTA_BEGIN: u8 = 8;
+pub(crate) const OPCODE_OTA_CHUNK: u8 = 9;
+pub(crate) const OPCODE_OTA_VERIFY: u8 = 10;
+pub(crate) const OPCODE_OTA_COMMIT: u8 = 11;
+pub(crate) const OPCODE_OTA_REBOOT: u8 = 12;
+/// The server answers both segment table messages with its segment table under this opcode
+pub(crate) const OPCODE_SEGMENTS: u8 = 13;
+pub(crate) const OPCODE_SET_SEGMENTS: u8 = 14;
+pub(crate) const OPCODE_SET_SEGMENT_ACTIVE: u8 = 15;
+pub(crate) const OPCODE_SEND_SEGMENT_PIXELS: u8 = 16;
+/// The server answers both layout messages with its layout under this opcode
+pub(crate) const OPCODE_LAYOUT: u8 = 17;
+pub(crate) const OPCODE_SET_LAYOUT: u8 = 18;
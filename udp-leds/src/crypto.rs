@@ -0,0 +1,336 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+use crate::{
+    constants::{MAX_SEALED_LENGTH, SEALED_FLAG, SEAL_OVERHEAD},
+    error::Error,
+    random::random_bytes,
+};
+
+/// Secret key shared by a controller and its clients
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key([u8; 32]);
+
+impl From<[u8; 32]> for Key {
+    fn from(value: [u8; 32]) -> Self {
+        Key(value)
+    }
+}
+
+/// Keys are never printed
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(Error::InvalidKey);
+        }
+        let mut key = [0; 32];
+        for (byte, digits) in key.iter_mut().zip(s.as_bytes().chunks_exact(2)) {
+            let digits = std::str::from_utf8(digits).map_err(|_| Error::InvalidKey)?;
+            *byte = u8::from_str_radix(digits, 16).map_err(|_| Error::InvalidKey)?;
+        }
+        Ok(Key(key))
+    }
+}
+
+/// Counters already received from one sender
+#[derive(Debug, Clone)]
+struct Window {
+    key_id: u8,
+    prefix: [u8; 16],
+    last: u64,
+    /// Bit `n` is set when the counter `last - n` was received
+    seen: u64,
+}
+
+impl Window {
+    /// Marks the counter as received, returns false if it already was or is too old to tell
+    fn accept(&mut self, counter: u64) -> bool {
+        if counter > self.last {
+            let shift = counter - self.last;
+            self.seen = if shift >= 64 { 0 } else { self.seen << shift };
+            self.seen |= 1;
+            self.last = counter;
+            return true;
+        }
+        let age = self.last - counter;
+        if age >= 64 || self.seen & (1 << age) != 0 {
+            return false;
+        }
+        self.seen |= 1 << age;
+        true
+    }
+}
+
+/**
+ * # Encryption
+ * Optional layer sealing the messages with XChaCha20-Poly1305, so they can be neither read nor forged on shared networks
+ *
+ * Each controller has its own keys, numbered with a key id byte so they can be rotated:
+ * the controller accepts both keys while its clients move to the new one, then the old one is removed
+ *
+ * ## Sealed datagram
+ * [SEALED_FLAG, key id, nonce (192 bits), encrypted message, tag (128 bits)]
+ * The flag and the key id are authenticated along with the message
+ *
+ * ## Nonces
+ * The nonce is a random prefix drawn for each cipher (128 bits) followed by a counter (64 bits big endian) incremented
+ * for each sealed message. The controller and its clients seal with the same key in both directions, the prefix
+ * is long enough that two ciphers, even created after a restart, never draw the same one and reuse a nonce
+ * The receiver remembers the last counters of the last `MAX_SENDERS` senders and drops the datagrams it already
+ * received, so a datagram duplicated by the network is not applied twice
+ *
+ * ## Replays
+ * This is no protection against replays: the first datagram of an unknown sender is always accepted, so a captured
+ * datagram is accepted again once the receiver restarted or forgot its sender. There is no handshake nor clock
+ * to tell a fresh datagram from an old one, the key only keeps the strangers from reading and forging the messages
+ */
+#[derive(Clone)]
+pub struct Cipher {
+    keys: Vec<(u8, XChaCha20Poly1305)>,
+    current: u8,
+    prefix: [u8; 16],
+    counter: u64,
+    windows: VecDeque<Window>,
+}
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cipher")
+            .field("keys", &self.keys.iter().map(|(id, _)| id).collect::<Vec<_>>())
+            .field("current", &self.current)
+            .field("counter", &self.counter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Cipher {
+    /// Number of senders whose counters are remembered
+    pub const MAX_SENDERS: usize = 16;
+
+    /// Creates a cipher sealing the messages with the given key
+    pub fn new(key_id: u8, key: &Key) -> Self {
+        Cipher {
+            keys: vec![(key_id, XChaCha20Poly1305::new(&key.0.into()))],
+            current: key_id,
+            prefix: random_bytes(),
+            counter: 0,
+            windows: VecDeque::with_capacity(Self::MAX_SENDERS),
+        }
+    }
+
+    /// Accepts the datagrams sealed with another key, replacing the key that had this id
+    pub fn add_key(&mut self, key_id: u8, key: &Key) {
+        self.remove_key(key_id);
+        self.keys.push((key_id, XChaCha20Poly1305::new(&key.0.into())));
+    }
+
+    /// Stops accepting the datagrams sealed with the given key
+    pub fn remove_key(&mut self, key_id: u8) {
+        self.keys.retain(|&(id, _)| id != key_id);
+        self.windows.retain(|window| window.key_id != key_id);
+    }
+
+    /// Seals the next messages with the given key
+    pub fn use_key(&mut self, key_id: u8) -> Result<(), Error> {
+        self.key(key_id)?;
+        self.current = key_id;
+        Ok(())
+    }
+
+    /// Id of the key sealing the messages
+    pub fn key_id(&self) -> u8 {
+        self.current
+    }
+
+    /// Whether the datagram was sealed by a cipher
+    pub fn is_sealed(datagram: &[u8]) -> bool {
+        datagram.first() == Some(&SEALED_FLAG)
+    }
+
+    /// Encrypts an encoded message
+    pub fn seal(&mut self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        if message.len() + SEAL_OVERHEAD > MAX_SEALED_LENGTH {
+            return Err(Error::InvalidMessageLength);
+        }
+        if self.counter == u64::MAX {
+            self.prefix = random_bytes();
+            self.counter = 0;
+        }
+        self.counter += 1;
+
+        let header = [SEALED_FLAG, self.current];
+        let mut nonce = [0; 24];
+        nonce[..16].copy_from_slice(&self.prefix);
+        nonce[16..].copy_from_slice(&self.counter.to_be_bytes());
+        let sealed = self
+            .key(self.current)?
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: message, aad: &header })
+            .map_err(|_| Error::InvalidMessageLength)?;
+
+        let mut datagram = Vec::with_capacity(message.len() + SEAL_OVERHEAD);
+        datagram.extend_from_slice(&header);
+        datagram.extend_from_slice(&nonce);
+        datagram.extend_from_slice(&sealed);
+        Ok(datagram)
+    }
+
+    /// Decrypts a sealed datagram, rejecting the forged ones and the duplicates of the recent datagrams
+    pub fn open(&mut self, datagram: &[u8]) -> Result<Vec<u8>, Error> {
+        if !Self::is_sealed(datagram) {
+            return Err(Error::NotSealed);
+        }
+        if datagram.len() < SEAL_OVERHEAD || datagram.len() > MAX_SEALED_LENGTH {
+            return Err(Error::InvalidMessageLength);
+        }
+        let key_id = datagram[1];
+        let nonce = &datagram[2..26];
+        let message = self
+            .key(key_id)?
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: &datagram[26..], aad: &datagram[..2] })
+            .map_err(|_| Error::Unauthenticated)?;
+
+        // Only authenticated datagrams move the windows, so forged ones cannot push the genuine ones out
+        let prefix: [u8; 16] = nonce[..16].try_into().unwrap();
+        let counter = u64::from_be_bytes(nonce[16..].try_into().unwrap());
+        match self.windows.iter().position(|window| window.key_id == key_id && window.prefix == prefix) {
+            Some(index) => {
+                let mut window = self.windows.remove(index).unwrap();
+                let accepted = window.accept(counter);
+                self.windows.push_back(window);
+                if !accepted {
+                    return Err(Error::Replayed);
+                }
+            },
+            None => {
+                if self.windows.len() == Self::MAX_SENDERS {
+                    self.windows.pop_front();
+                }
+                self.windows.push_back(Window { key_id, prefix, last: counter, seen: 1 });
+            },
+        }
+        Ok(message)
+    }
+
+    fn key(&self, key_id: u8) -> Result<&XChaCha20Poly1305, Error> {
+        self.keys.iter().find(|&&(id, _)| id == key_id).map(|(_, key)| key).ok_or(Error::UnknownKey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> Key {
+        Key::from([byte; 32])
+    }
+
+    #[test]
+    fn test_key() {
+        let hex = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let expected: [u8; 32] = std::array::from_fn(|index| index as u8);
+        assert_eq!(hex.parse::<Key>(), Ok(Key::from(expected)));
+        assert_eq!(hex[1..].parse::<Key>(), Err(Error::InvalidKey));
+        assert_eq!(hex.replace('0', "g").parse::<Key>(), Err(Error::InvalidKey));
+        assert_eq!(format!("{:?}", key(1)), "Key(..)");
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let mut client = Cipher::new(1, &key(7));
+        let mut server = Cipher::new(1, &key(7));
+        let sealed = client.seal(&[1, 2, 3]).unwrap();
+        assert_eq!(sealed.len(), 3 + SEAL_OVERHEAD);
+        assert_eq!(sealed[..2], [SEALED_FLAG, 1]);
+        assert_eq!(server.open(&sealed), Ok(vec![1, 2, 3]));
+
+        // The same message is sealed with another nonce
+        assert_ne!(client.seal(&[1, 2, 3]).unwrap(), sealed);
+        // Each cipher draws its own nonce prefix
+        assert_ne!(Cipher::new(1, &key(7)).seal(&[1, 2, 3]).unwrap()[2..18], sealed[2..18]);
+
+        let mut other = Cipher::new(1, &key(8));
+        assert_eq!(other.open(&sealed), Err(Error::Unauthenticated));
+        assert_eq!(server.open(&[1, 2, 3]), Err(Error::NotSealed));
+        assert_eq!(server.open(&sealed[..10]), Err(Error::InvalidMessageLength));
+    }
+
+    #[test]
+    fn test_tampering() {
+        let mut client = Cipher::new(1, &key(7));
+        let mut server = Cipher::new(1, &key(7));
+        server.add_key(2, &key(7));
+        let sealed = client.seal(&[1, 2, 3]).unwrap();
+        for index in 1..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 1;
+            assert!(server.open(&tampered).is_err(), "byte {index}");
+        }
+        // The key id is authenticated, even when both ids hold the same key
+        let mut moved = sealed.clone();
+        moved[1] = 2;
+        assert_eq!(server.open(&moved), Err(Error::Unauthenticated));
+        assert_eq!(server.open(&sealed), Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut client = Cipher::new(1, &key(7));
+        let mut server = Cipher::new(1, &key(7));
+        let first = client.seal(&[1]).unwrap();
+        let second = client.seal(&[2]).unwrap();
+        let third = client.seal(&[3]).unwrap();
+
+        // Reordered datagrams are accepted once
+        assert_eq!(server.open(&third), Ok(vec![3]));
+        assert_eq!(server.open(&first), Ok(vec![1]));
+        assert_eq!(server.open(&first), Err(Error::Replayed));
+        assert_eq!(server.open(&second), Ok(vec![2]));
+        assert_eq!(server.open(&third), Err(Error::Replayed));
+
+        // Datagrams too old for the window are rejected
+        let old = client.seal(&[4]).unwrap();
+        for _ in 0..64 {
+            client.seal(&[0]).unwrap();
+        }
+        assert_eq!(server.open(&client.seal(&[5]).unwrap()), Ok(vec![5]));
+        assert_eq!(server.open(&old), Err(Error::Replayed));
+
+        // Another sender has its own counters
+        let mut other = Cipher::new(1, &key(7));
+        assert_eq!(server.open(&other.seal(&[6]).unwrap()), Ok(vec![6]));
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let mut client = Cipher::new(1, &key(1));
+        let mut server = Cipher::new(1, &key(1));
+        let old = client.seal(&[1]).unwrap();
+
+        server.add_key(2, &key(2));
+        client.add_key(2, &key(2));
+        assert_eq!(client.use_key(3), Err(Error::UnknownKey));
+        client.use_key(2).unwrap();
+        let new = client.seal(&[2]).unwrap();
+        assert_eq!(new[1], 2);
+        assert_eq!(server.open(&old), Ok(vec![1]));
+        assert_eq!(server.open(&new), Ok(vec![2]));
+
+        server.remove_key(1);
+        let late = Cipher::new(1, &key(1)).seal(&[3]).unwrap();
+        assert_eq!(server.open(&late), Err(Error::UnknownKey));
+        client.remove_key(2);
+        assert_eq!(client.seal(&[4]), Err(Error::UnknownKey));
+    }
+}
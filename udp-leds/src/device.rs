@@ -0,0 +1,115 @@
+use std::fmt;
+
+use crate::{constants::DEVICE_MASK, error::Error, random::random_bytes};
+
+/// Number identifying a client to the server, between 0 and 63 as it is sent in 6 bits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u8", into = "u8"))]
+pub struct DeviceId(u8);
+
+impl DeviceId {
+    /// Number of devices a server can tell apart
+    pub const COUNT: u8 = DEVICE_MASK + 1;
+
+    pub fn new(id: u8) -> Result<Self, Error> {
+        if id > DEVICE_MASK {
+            return Err(Error::InvalidDevice);
+        }
+        Ok(DeviceId(id))
+    }
+
+    /// Keeps the 6 bits of the wire format
+    pub(crate) fn from_bits(bits: u8) -> Self {
+        DeviceId(bits & DEVICE_MASK)
+    }
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for DeviceId {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        DeviceId::new(value)
+    }
+}
+
+impl From<DeviceId> for u8 {
+    fn from(value: DeviceId) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Random number a client keeps across restarts to get its device id back from the servers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClientToken(u64);
+
+impl ClientToken {
+    /// Draws a new token from the random generator of the operating system
+    pub fn random() -> Self {
+        ClientToken(u64::from_be_bytes(random_bytes()))
+    }
+
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for ClientToken {
+    fn from(value: u64) -> Self {
+        ClientToken(value)
+    }
+}
+
+impl From<ClientToken> for u64 {
+    fn from(value: ClientToken) -> Self {
+        value.0
+    }
+}
+
+/// Formatted as 16 hex digits, which `FromStr` reads back
+impl fmt::Display for ClientToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl std::str::FromStr for ClientToken {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(value, 16).map(ClientToken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(DeviceId::new(63).map(DeviceId::get), Ok(63));
+        assert_eq!(DeviceId::new(64), Err(Error::InvalidDevice));
+        assert_eq!(DeviceId::from_bits(0b1100_0101).get(), 5);
+        assert_eq!(DeviceId::try_from(12).unwrap().to_string(), "12");
+    }
+
+    #[test]
+    fn test_token() {
+        let token = ClientToken::from(0xab);
+        assert_eq!(token.to_string(), "00000000000000ab");
+        assert_eq!("00000000000000ab".parse(), Ok(token));
+        assert!("token".parse::<ClientToken>().is_err());
+        assert_ne!(ClientToken::random(), ClientToken::random());
+    }
+}
@@ -0,0 +1,686 @@
+use std::f32::consts::TAU;
+use std::time::{Duration, Instant};
+
+use crate::{color::Rgb, frame::Frame};
+
+/**
+ * # Effect
+ * Animation rendered into frames of any length
+ *
+ * The effects only depend on the time they are given and on their seed, so rendering the same times in the
+ * same order always gives the same frames. Effects keeping a state, such as fire, advance it in fixed steps
+ * from one render to the next and start over when the time goes backwards
+ */
+pub trait Effect {
+    /// Renders the effect `t` after it started, the frame keeps its length
+    fn render(&mut self, t: Duration, frame: &mut Frame);
+}
+
+impl<E: Effect + ?Sized> Effect for Box<E> {
+    fn render(&mut self, t: Duration, frame: &mut Frame) {
+        (**self).render(t, frame)
+    }
+}
+
+/// Source of the time given to the effects
+pub trait Clock {
+    /// Time since the clock started
+    fn elapsed(&self) -> Duration;
+}
+
+/// Clock following the time of the system
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    /// Starts the clock now
+    pub fn new() -> Self {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Clock only moving when told to, so that effects can be rendered faster than real time or checked frame by frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VirtualClock {
+    elapsed: Duration,
+}
+
+impl VirtualClock {
+    /// Starts the clock at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the clock forward
+    pub fn advance(&mut self, step: Duration) {
+        self.elapsed += step;
+    }
+
+    /// Moves the clock to the given time
+    pub fn set(&mut self, elapsed: Duration) {
+        self.elapsed = elapsed;
+    }
+}
+
+impl Clock for VirtualClock {
+    fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+/// Effect played against a clock
+pub struct Animation<C: Clock = SystemClock> {
+    effect: Box<dyn Effect + Send>,
+    clock: C,
+}
+
+impl<C: Clock> Animation<C> {
+    pub fn new(effect: impl Effect + Send + 'static, clock: C) -> Self {
+        Animation { effect: Box::new(effect), clock }
+    }
+
+    /// Time since the animation started
+    pub fn elapsed(&self) -> Duration {
+        self.clock.elapsed()
+    }
+
+    pub fn clock_mut(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    /// Renders the effect at the current time of the clock
+    pub fn render(&mut self, frame: &mut Frame) {
+        self.effect.render(self.clock.elapsed(), frame);
+    }
+}
+
+/// Number of periods elapsed at `t`, 0 for a zero period
+fn cycles(t: Duration, period: Duration) -> f32 {
+    if period.is_zero() {
+        return 0.0;
+    }
+    t.as_secs_f32() / period.as_secs_f32()
+}
+
+/// Xorshift generator, the random effects repeat for a given seed
+#[derive(Debug, Clone)]
+struct Random(u32);
+
+impl Random {
+    fn new(seed: u32) -> Self {
+        // Xorshift never leaves zero
+        Random(seed.max(1))
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    /// Random value from 0 to `bound`, `bound` excluded
+    fn below(&mut self, bound: u32) -> u32 {
+        match bound {
+            0 => 0,
+            bound => self.next_u32() % bound,
+        }
+    }
+
+    /// Random value from 0 to 1, 1 excluded
+    fn unit(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// State of the effects advancing in fixed steps
+#[derive(Debug, Clone)]
+struct Steps {
+    random: Random,
+    seed: u32,
+    done: u64,
+    last: Option<Duration>,
+}
+
+impl Steps {
+    /// Steps run at once at most, when rendering after a long pause
+    const MAX_STEPS: u64 = 256;
+
+    fn new(seed: u32) -> Self {
+        Steps { random: Random::new(seed), seed, done: 0, last: None }
+    }
+
+    /// Number of steps to run to reach `t`, true when the state has to start over
+    fn advance(&mut self, t: Duration, rate: f32) -> (u64, bool) {
+        let restart = self.last.map_or(true, |last| t < last);
+        if restart {
+            self.random = Random::new(self.seed);
+            self.done = 0;
+        }
+        self.last = Some(t);
+        let target = (t.as_secs_f32() * rate.max(0.0)) as u64;
+        let steps = target.saturating_sub(self.done);
+        self.done = target;
+        (steps.min(Self::MAX_STEPS), restart)
+    }
+}
+
+/// Rainbow scrolling along the strip
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rainbow {
+    /// Time for a color to go around the color wheel
+    pub period: Duration,
+    /// Number of rainbows along the strip
+    pub waves: f32,
+    /// Brightness from 0 to 1
+    pub brightness: f32,
+}
+
+impl Default for Rainbow {
+    fn default() -> Self {
+        Rainbow { period: Duration::from_secs(10), waves: 1.0, brightness: 1.0 }
+    }
+}
+
+impl Effect for Rainbow {
+    fn render(&mut self, t: Duration, frame: &mut Frame) {
+        let shift = cycles(t, self.period);
+        let len = frame.len();
+        for (index, pixel) in frame.iter_mut().enumerate() {
+            *pixel = Rgb::from_hue(index as f32 * self.waves / len as f32 + shift).scale(self.brightness);
+        }
+    }
+}
+
+/// Groups of lit LEDs running along the strip
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chase {
+    pub color: Rgb,
+    pub background: Rgb,
+    /// Number of lit LEDs of each group
+    pub length: usize,
+    /// Number of LEDs between two groups
+    pub gap: usize,
+    /// LEDs per second
+    pub speed: f32,
+}
+
+impl Default for Chase {
+    fn default() -> Self {
+        Chase { color: Rgb::WHITE, background: Rgb::BLACK, length: 3, gap: 5, speed: 10.0 }
+    }
+}
+
+impl Effect for Chase {
+    fn render(&mut self, t: Duration, frame: &mut Frame) {
+        let cycle = self.length + self.gap;
+        if cycle == 0 {
+            frame.fill(self.background);
+            return;
+        }
+        let offset = (t.as_secs_f32() * self.speed) as usize % cycle;
+        for (index, pixel) in frame.iter_mut().enumerate() {
+            let lit = (index + cycle - offset) % cycle < self.length;
+            *pixel = if lit { self.color } else { self.background };
+        }
+    }
+}
+
+/// Color slowly pulsing on the whole strip
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breathe {
+    pub color: Rgb,
+    /// Time from a pulse to the next one
+    pub period: Duration,
+    /// Lowest brightness, from 0 to 1
+    pub min: f32,
+}
+
+impl Default for Breathe {
+    fn default() -> Self {
+        Breathe { color: Rgb::WHITE, period: Duration::from_secs(4), min: 0.0 }
+    }
+}
+
+impl Effect for Breathe {
+    fn render(&mut self, t: Duration, frame: &mut Frame) {
+        let level = 0.5 - 0.5 * (cycles(t, self.period) * TAU).cos();
+        frame.fill(self.color.scale(self.min + (1.0 - self.min) * level));
+    }
+}
+
+/// LEDs lighting up at random and fading out
+#[derive(Debug, Clone)]
+pub struct Twinkle {
+    pub color: Rgb,
+    pub background: Rgb,
+    /// Sparkles started per LED and per second
+    pub rate: f32,
+    /// Time for a sparkle to fade out
+    pub fade: Duration,
+    levels: Vec<f32>,
+    steps: Steps,
+}
+
+impl Twinkle {
+    /// Steps per second
+    const RATE: f32 = 60.0;
+
+    pub fn new(color: Rgb, rate: f32, fade: Duration, seed: u32) -> Self {
+        Twinkle { color, background: Rgb::BLACK, rate, fade, levels: Vec::new(), steps: Steps::new(seed) }
+    }
+}
+
+impl Default for Twinkle {
+    fn default() -> Self {
+        Self::new(Rgb::WHITE, 0.5, Duration::from_secs(1), 1)
+    }
+}
+
+impl Effect for Twinkle {
+    fn render(&mut self, t: Duration, frame: &mut Frame) {
+        let (steps, restart) = self.steps.advance(t, Self::RATE);
+        if restart {
+            self.levels.clear();
+        }
+        self.levels.resize(frame.len(), 0.0);
+        let decay = match self.fade.as_secs_f32() {
+            0.0 => 1.0,
+            fade => 1.0 / (fade * Self::RATE),
+        };
+        for _ in 0..steps {
+            for level in &mut self.levels {
+                *level = (*level - decay).max(0.0);
+                if self.steps.random.unit() < self.rate / Self::RATE {
+                    *level = 1.0;
+                }
+            }
+        }
+        for (pixel, level) in frame.iter_mut().zip(&self.levels) {
+            *pixel = self.background.lerp(self.color, *level);
+        }
+    }
+}
+
+/// Flames rising from the first LED of the strip
+#[derive(Debug, Clone)]
+pub struct Fire {
+    /// How fast the flames cool down, higher values give shorter flames
+    pub cooling: u8,
+    /// Chances out of 255 of a new spark at each step, higher values give a livelier fire
+    pub sparking: u8,
+    /// Steps per second
+    pub rate: f32,
+    heat: Vec<u8>,
+    steps: Steps,
+}
+
+impl Fire {
+    pub fn new(cooling: u8, sparking: u8, seed: u32) -> Self {
+        Fire { cooling, sparking, rate: 60.0, heat: Vec::new(), steps: Steps::new(seed) }
+    }
+
+    fn step(&mut self) {
+        let len = self.heat.len();
+        let random = &mut self.steps.random;
+        let cooldown = self.cooling as u32 * 10 / len as u32 + 2;
+        for heat in &mut self.heat {
+            *heat = heat.saturating_sub(random.below(cooldown) as u8);
+        }
+        for index in (2..len).rev() {
+            self.heat[index] = ((self.heat[index - 1] as u16 + 2 * self.heat[index - 2] as u16) / 3) as u8;
+        }
+        if random.below(255) < self.sparking as u32 {
+            let index = (random.below(7) as usize).min(len - 1);
+            self.heat[index] = self.heat[index].saturating_add(160 + random.below(96) as u8);
+        }
+    }
+
+    /// Black to red to yellow to white
+    fn heat_color(heat: u8) -> Rgb {
+        let scaled = (heat as u16 * 191 / 255) as u8;
+        let ramp = (scaled & 0x3f) << 2;
+        match scaled {
+            0x80.. => Rgb::new(255, 255, ramp),
+            0x40.. => Rgb::new(255, ramp, 0),
+            _ => Rgb::new(ramp, 0, 0),
+        }
+    }
+}
+
+impl Default for Fire {
+    fn default() -> Self {
+        Self::new(55, 120, 1)
+    }
+}
+
+impl Effect for Fire {
+    fn render(&mut self, t: Duration, frame: &mut Frame) {
+        let (steps, restart) = self.steps.advance(t, self.rate);
+        if restart {
+            self.heat.clear();
+        }
+        self.heat.resize(frame.len(), 0);
+        if !self.heat.is_empty() {
+            for _ in 0..steps {
+                self.step();
+            }
+        }
+        for (pixel, heat) in frame.iter_mut().zip(&self.heat) {
+            *pixel = Self::heat_color(*heat);
+        }
+    }
+}
+
+/// Bright head crossing the strip, leaving a trail which fades out unevenly
+#[derive(Debug, Clone)]
+pub struct Meteor {
+    pub color: Rgb,
+    /// Number of LEDs of the head
+    pub size: usize,
+    /// Brightness kept by the trail when it fades, from 0 to 1
+    pub decay: f32,
+    /// LEDs per second
+    pub speed: f32,
+    levels: Vec<f32>,
+    steps: Steps,
+}
+
+impl Meteor {
+    pub fn new(color: Rgb, size: usize, speed: f32, seed: u32) -> Self {
+        Meteor { color, size, decay: 0.75, speed, levels: Vec::new(), steps: Steps::new(seed) }
+    }
+}
+
+impl Default for Meteor {
+    fn default() -> Self {
+        Self::new(Rgb::WHITE, 3, 30.0, 1)
+    }
+}
+
+impl Effect for Meteor {
+    fn render(&mut self, t: Duration, frame: &mut Frame) {
+        let (steps, restart) = self.steps.advance(t, self.speed);
+        if restart {
+            self.levels.clear();
+        }
+        let len = frame.len();
+        self.levels.resize(len, 0.0);
+        // The head leaves the strip before coming back, so the trail fades out
+        let cycle = (len * 2).max(1) as u64;
+        for step in self.steps.done - steps..self.steps.done {
+            for level in &mut self.levels {
+                if self.steps.random.below(2) == 0 {
+                    *level *= self.decay;
+                }
+            }
+            let head = (step % cycle) as usize;
+            // A head without any LED lights nothing
+            for index in (head + 1).saturating_sub(self.size)..=head {
+                if let Some(level) = self.levels.get_mut(index) {
+                    *level = 1.0;
+                }
+            }
+        }
+        for (pixel, level) in frame.iter_mut().zip(&self.levels) {
+            *pixel = self.color.scale(*level);
+        }
+    }
+}
+
+/// Smoothly shifting colors, made of overlapping waves
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plasma {
+    /// Time for the waves to go back to their start
+    pub period: Duration,
+    /// Number of waves along the strip
+    pub scale: f32,
+    /// Brightness from 0 to 1
+    pub brightness: f32,
+}
+
+impl Default for Plasma {
+    fn default() -> Self {
+        Plasma { period: Duration::from_secs(8), scale: 2.0, brightness: 1.0 }
+    }
+}
+
+impl Effect for Plasma {
+    fn render(&mut self, t: Duration, frame: &mut Frame) {
+        let phase = cycles(t, self.period);
+        let len = frame.len();
+        for (index, pixel) in frame.iter_mut().enumerate() {
+            let x = index as f32 * self.scale / len as f32;
+            let value = (TAU * (x + phase)).sin() + (TAU * (x * 0.5 - phase)).sin();
+            *pixel = Rgb::from_hue(value * 0.25 + phase).scale(self.brightness);
+        }
+    }
+}
+
+/// Bright dot with a fading tail, bouncing between the ends of the strip
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comet {
+    pub color: Rgb,
+    /// Number of LEDs of the tail, head included
+    pub length: usize,
+    /// LEDs per second
+    pub speed: f32,
+    /// Whether the comet comes back rather than leaving the strip
+    pub bounce: bool,
+}
+
+impl Default for Comet {
+    fn default() -> Self {
+        Comet { color: Rgb::WHITE, length: 8, speed: 20.0, bounce: true }
+    }
+}
+
+impl Effect for Comet {
+    fn render(&mut self, t: Duration, frame: &mut Frame) {
+        frame.fill(Rgb::BLACK);
+        let len = frame.len();
+        if len == 0 || self.length == 0 {
+            return;
+        }
+        let travelled = (t.as_secs_f32() * self.speed) as usize;
+        // The tail follows the path of the head, folding back at the ends of the strip when bouncing
+        let position = |travelled: usize| match self.bounce && len > 1 {
+            true => {
+                let lap = travelled % ((len - 1) * 2);
+                lap.min((len - 1) * 2 - lap)
+            },
+            false => travelled % (len + self.length),
+        };
+        for step in (0..self.length.min(travelled + 1)).rev() {
+            if let Some(pixel) = frame.as_mut_slice().get_mut(position(travelled - step)) {
+                *pixel = self.color.scale(1.0 - step as f32 / self.length as f32);
+            }
+        }
+    }
+}
+
+/// Colors blended along the strip, still or scrolling
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    /// Colors spread evenly along the strip
+    pub colors: Vec<Rgb>,
+    /// Time for the gradient to scroll by a whole strip, zero for a still gradient
+    pub period: Duration,
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Gradient { colors: vec![Rgb::RED, Rgb::BLUE], period: Duration::ZERO }
+    }
+}
+
+impl Effect for Gradient {
+    fn render(&mut self, t: Duration, frame: &mut Frame) {
+        let count = self.colors.len();
+        match count {
+            0 => return frame.fill(Rgb::BLACK),
+            1 => return frame.fill(self.colors[0]),
+            _ => {},
+        }
+        let len = frame.len();
+        let shift = cycles(t, self.period);
+        for (index, pixel) in frame.iter_mut().enumerate() {
+            // A scrolling gradient wraps around from its last color to its first one
+            let (position, stops) = if self.period.is_zero() {
+                (index as f32 / (len - 1).max(1) as f32 * (count - 1) as f32, count - 1)
+            } else {
+                ((index as f32 / len as f32 - shift).rem_euclid(1.0) * count as f32, count)
+            };
+            let from = (position as usize).min(stops - 1);
+            *pixel = self.colors[from].lerp(self.colors[(from + 1) % count], position - from as f32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREY: Rgb = Rgb::new(128, 128, 128);
+
+    /// Frames rendered every 100 ms from the start of the effect
+    fn golden(effect: impl Effect + Send + 'static, len: usize, count: usize) -> Vec<Vec<Rgb>> {
+        let mut animation = Animation::new(effect, VirtualClock::new());
+        let mut frame = Frame::new(len);
+        (0..count)
+            .map(|_| {
+                animation.render(&mut frame);
+                animation.clock_mut().advance(Duration::from_millis(100));
+                frame.as_slice().to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_virtual_clock() {
+        let mut clock = VirtualClock::new();
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+        clock.advance(Duration::from_millis(250));
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(clock.elapsed(), Duration::from_millis(500));
+        clock.set(Duration::from_secs(2));
+        assert_eq!(clock.elapsed(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_rainbow() {
+        let frames = golden(Rainbow { period: Duration::from_secs(1), ..Default::default() }, 3, 2);
+        assert_eq!(frames[0], vec![Rgb::new(255, 0, 0), Rgb::new(0, 255, 0), Rgb::new(0, 0, 255)]);
+        assert_eq!(frames[1][0], Rgb::new(255, 153, 0));
+    }
+
+    #[test]
+    fn test_chase() {
+        let (o, x) = (Rgb::BLACK, Rgb::WHITE);
+        let frames = golden(Chase { length: 2, gap: 2, ..Default::default() }, 6, 3);
+        assert_eq!(frames[0], vec![x, x, o, o, x, x]);
+        assert_eq!(frames[1], vec![o, x, x, o, o, x]);
+        assert_eq!(frames[2], vec![o, o, x, x, o, o]);
+    }
+
+    #[test]
+    fn test_breathe() {
+        let breathe = Breathe { color: Rgb::new(200, 100, 0), period: Duration::from_millis(400), min: 0.5 };
+        let frames = golden(breathe, 2, 3);
+        assert_eq!(frames[0], vec![Rgb::new(100, 50, 0); 2]);
+        assert_eq!(frames[1], vec![Rgb::new(150, 75, 0); 2]);
+        assert_eq!(frames[2], vec![Rgb::new(200, 100, 0); 2]);
+    }
+
+    #[test]
+    fn test_twinkle() {
+        let frames = golden(Twinkle::new(Rgb::WHITE, 2.0, Duration::from_millis(200), 7), 8, 10);
+        assert_eq!(frames, golden(Twinkle::new(Rgb::WHITE, 2.0, Duration::from_millis(200), 7), 8, 10));
+        assert_eq!(frames[0], [Rgb::BLACK; 8]);
+        assert!(frames.iter().any(|frame| frame.iter().any(|&pixel| pixel != Rgb::BLACK)));
+        assert_ne!(frames, golden(Twinkle::new(Rgb::WHITE, 2.0, Duration::from_millis(200), 8), 8, 10));
+        let level = |level: u8| Rgb::new(level, level, level);
+        assert_eq!(frames[3], [level(234), level(0), level(85), level(170), level(0), level(0), level(213), level(234)]);
+    }
+
+    #[test]
+    fn test_fire() {
+        let frames = golden(Fire::default(), 6, 5);
+        assert_eq!(frames, golden(Fire::default(), 6, 5));
+        assert_eq!(frames[0], [Rgb::BLACK; 6]);
+        let red = |level: u8| Rgb::new(level, 0, 0);
+        assert_eq!(frames[4], [Rgb::new(255, 255, 136), red(44), red(12), red(28), red(64), red(232)]);
+        assert_eq!(Fire::heat_color(0), Rgb::BLACK);
+        assert_eq!(Fire::heat_color(255), Rgb::new(255, 255, 252));
+    }
+
+    #[test]
+    fn test_meteor() {
+        let frames = golden(Meteor::new(Rgb::WHITE, 2, 10.0, 3), 4, 6);
+        assert_eq!(frames[0], [Rgb::BLACK; 4]);
+        assert_eq!(frames[1], vec![Rgb::WHITE, Rgb::BLACK, Rgb::BLACK, Rgb::BLACK]);
+        assert_eq!(frames[2][..2], [Rgb::WHITE, Rgb::WHITE]);
+        assert_eq!(frames[4][2..], [Rgb::WHITE, Rgb::WHITE]);
+        assert_eq!(frames, golden(Meteor::new(Rgb::WHITE, 2, 10.0, 3), 4, 6));
+        assert!(golden(Meteor::new(Rgb::WHITE, 0, 10.0, 3), 4, 6).iter().flatten().all(|pixel| *pixel == Rgb::BLACK));
+    }
+
+    #[test]
+    fn test_plasma() {
+        let frames = golden(Plasma::default(), 4, 2);
+        assert_eq!(frames[0][0], Rgb::new(255, 0, 0));
+        assert_ne!(frames[0], frames[1]);
+        assert_eq!(frames, golden(Plasma::default(), 4, 2));
+    }
+
+    #[test]
+    fn test_comet() {
+        let comet = Comet { length: 2, speed: 10.0, color: Rgb::new(200, 200, 200), ..Default::default() };
+        let frames = golden(comet, 3, 5);
+        let (o, x, h) = (Rgb::BLACK, Rgb::new(200, 200, 200), Rgb::new(100, 100, 100));
+        assert_eq!(frames[0], vec![x, o, o]);
+        assert_eq!(frames[1], vec![h, x, o]);
+        assert_eq!(frames[2], vec![o, h, x]);
+        assert_eq!(frames[3], vec![o, x, h]);
+        assert_eq!(frames[4], vec![x, h, o]);
+    }
+
+    #[test]
+    fn test_gradient() {
+        let mut gradient = Gradient { colors: vec![Rgb::BLACK, Rgb::WHITE], ..Default::default() };
+        assert_eq!(golden(gradient.clone(), 3, 1)[0], vec![Rgb::BLACK, GREY, Rgb::WHITE]);
+
+        gradient.period = Duration::from_millis(400);
+        let frames = golden(gradient, 4, 2);
+        assert_eq!(frames[0], vec![Rgb::BLACK, GREY, Rgb::WHITE, GREY]);
+        assert_eq!(frames[1], vec![GREY, Rgb::BLACK, GREY, Rgb::WHITE]);
+    }
+
+    #[test]
+    fn test_restart() {
+        let mut fire = Fire::default();
+        let mut frame = Frame::new(6);
+        fire.render(Duration::from_secs(1), &mut frame);
+        let first = frame.clone();
+        fire.render(Duration::from_secs(2), &mut frame);
+        fire.render(Duration::ZERO, &mut frame);
+        fire.render(Duration::from_secs(1), &mut frame);
+        assert_eq!(frame, first);
+    }
+}
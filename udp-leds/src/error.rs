@@ -7,4 +7,63 @@ pub enum Error {
     InvalidMessageLength,
     #[error("Malformed message : invalid flag")]
     InvalidFlag,
-}
\ No newline at end of file
+    #[error("Malformed DNS packet")]
+    MalformedDns,
+    #[error("Malformed capture file")]
+    InvalidCapture,
+    #[error("Malformed sequence file")]
+    InvalidSequence,
+    #[error("Malformed FSEQ file")]
+    InvalidFseq,
+    #[error("IO error : {0}")]
+    Io(std::io::ErrorKind),
+    #[error("Timed out waiting for the server")]
+    Timeout,
+    #[error("No server with this name or id answered")]
+    ServerNotFound,
+    #[error("The client is not connected to a server")]
+    NotConnected,
+    #[error("Device ids go from 0 to 63")]
+    InvalidDevice,
+    #[error("The client has not acquired a device")]
+    NoDevice,
+    #[error("Only the client of the active device can change the settings of the server")]
+    NotActive,
+    #[error("The server does not support this message")]
+    Unsupported,
+    #[error("The clock of the server is not synchronized")]
+    NotSynchronized,
+    #[error("Invalid configuration")]
+    InvalidConfig,
+    #[error("Colors are written as #rrggbb, rrggbb, r,g,b or by their name")]
+    InvalidColor,
+    #[error("Invalid segment")]
+    InvalidSegment,
+    #[error("A segment is still used by another device")]
+    SegmentInUse,
+    #[error("Invalid layout")]
+    InvalidLayout,
+    #[error("Firmware update failed : {0}")]
+    UpdateFailed(String),
+    #[error("Keys are 32 bytes written as 64 hexadecimal digits")]
+    InvalidKey,
+    #[error("No key with this id")]
+    UnknownKey,
+    #[error("The datagram is encrypted")]
+    Sealed,
+    #[error("The datagram is not encrypted")]
+    NotSealed,
+    #[error("The datagram could not be authenticated")]
+    Unauthenticated,
+    #[error("The datagram was already received")]
+    Replayed,
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => Error::Timeout,
+            kind => Error::Io(kind),
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::ops::{Index, IndexMut, Range};
+use std::slice::SliceIndex;
+
+use crate::{color::Rgb, constants::MAX_LED_COUNT, error::Error};
+
+/**
+ * # Frame
+ * A full update of the LED strip
+ *
+ * The frame holds up to `MAX_LED_COUNT` pixels, only the first `len` pixels are part of the frame
+ * It can be indexed and iterated like a slice of `Rgb` and converted to and from the wire format,
+ * a list of 24bits RGB values
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    len: usize,
+    pixels: [Rgb; MAX_LED_COUNT],
+}
+
+impl Frame {
+    /// Creates a new black frame of `len` pixels
+    pub fn new(len: usize) -> Self {
+        assert!(len <= MAX_LED_COUNT, "Invalid frame length: {}", len);
+        Frame {
+            len,
+            pixels: [Rgb::BLACK; MAX_LED_COUNT],
+        }
+    }
+
+    /// Reads a frame from the wire format, an incomplete trailing pixel is ignored
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let len = bytes.len() / 3;
+        if len > MAX_LED_COUNT {
+            return Err(Error::InvalidMessageLength);
+        }
+        let mut frame = Frame::new(len);
+        for (pixel, bytes) in frame.pixels.iter_mut().zip(bytes.chunks_exact(3)) {
+            *pixel = Rgb::new(bytes[0], bytes[1], bytes[2]);
+        }
+        Ok(frame)
+    }
+
+    /// Number of pixels in the frame
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Changes the number of pixels, the new pixels are black
+    pub fn resize(&mut self, len: usize) {
+        assert!(len <= MAX_LED_COUNT, "Invalid frame length: {}", len);
+        if len < self.len {
+            self.pixels[len..self.len].fill(Rgb::BLACK);
+        }
+        self.len = len;
+    }
+
+    /// Sets the color of a single pixel
+    pub fn set(&mut self, index: usize, color: impl Into<Rgb>) {
+        self[index] = color.into();
+    }
+
+    /// Returns the color of a single pixel
+    pub fn get(&self, index: usize) -> Rgb {
+        self[index]
+    }
+
+    /// Sets every pixel of the frame to the same color
+    pub fn fill(&mut self, color: impl Into<Rgb>) {
+        self.as_mut_slice().fill(color.into());
+    }
+
+    /// Sets a range of pixels to the same color
+    pub fn fill_range(&mut self, range: Range<usize>, color: impl Into<Rgb>) {
+        self[range].fill(color.into());
+    }
+
+    /// Copies the pixels of `other` starting at pixel `start`, the pixels past the end of the frame are dropped
+    pub fn copy_at(&mut self, start: usize, other: &[Rgb]) {
+        let start = start.min(self.len);
+        let count = other.len().min(self.len - start);
+        self[start..start + count].copy_from_slice(&other[..count]);
+    }
+
+    /// A new frame made of a range of pixels
+    pub fn slice(&self, range: Range<usize>) -> Frame {
+        self[range].iter().copied().collect()
+    }
+
+    pub fn as_slice(&self) -> &[Rgb] {
+        &self.pixels[..self.len]
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [Rgb] {
+        &mut self.pixels[..self.len]
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Rgb> {
+        self.as_slice().iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Rgb> {
+        self.as_mut_slice().iter_mut()
+    }
+
+    /// The pixels in the wire format, 3 bytes per pixel
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.iter().flat_map(|&pixel| <[u8; 3]>::from(pixel)).collect()
+    }
+
+    /// Writes the pixels in the wire format, `out` must hold at least `3 * len` bytes
+    pub fn write_bytes(&self, out: &mut [u8]) {
+        for (bytes, &pixel) in out.chunks_exact_mut(3).zip(self.iter()) {
+            bytes.copy_from_slice(&<[u8; 3]>::from(pixel));
+        }
+    }
+}
+
+impl<I: SliceIndex<[Rgb]>> Index<I> for Frame {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl<I: SliceIndex<[Rgb]>> IndexMut<I> for Frame {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.as_mut_slice()[index]
+    }
+}
+
+impl<'a> IntoIterator for &'a Frame {
+    type Item = &'a Rgb;
+    type IntoIter = std::slice::Iter<'a, Rgb>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Frame {
+    type Item = &'a mut Rgb;
+    type IntoIter = std::slice::IterMut<'a, Rgb>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// Collects at most `MAX_LED_COUNT` pixels, the next ones are dropped
+impl FromIterator<Rgb> for Frame {
+    fn from_iter<T: IntoIterator<Item = Rgb>>(iter: T) -> Self {
+        let mut frame = Frame::new(0);
+        for (pixel, color) in frame.pixels.iter_mut().zip(iter) {
+            *pixel = color;
+            frame.len += 1;
+        }
+        frame
+    }
+}
+
+impl From<&[Rgb]> for Frame {
+    fn from(value: &[Rgb]) -> Self {
+        value.iter().copied().collect()
+    }
+}
+
+impl TryFrom<&[u8]> for Frame {
+    type Error = Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Frame::from_bytes(value)
+    }
+}
+
+/// A frame is stored as its pixel bytes, a hex string in human readable formats
+#[cfg(feature = "serde")]
+impl serde::Serialize for Frame {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::hex::vec::serialize(&self.to_bytes(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Frame {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = crate::hex::vec::deserialize(deserializer)?;
+        if bytes.len() % 3 != 0 || bytes.len() > MAX_LED_COUNT * 3 {
+            return Err(serde::de::Error::invalid_length(bytes.len(), &"a multiple of 3 bytes up to the maximum LED count"));
+        }
+        Frame::from_bytes(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_get() {
+        let mut frame = Frame::new(4);
+        frame.set(2, (1, 2, 3));
+        assert_eq!(frame.get(2), Rgb::new(1, 2, 3));
+        assert_eq!(frame[1], Rgb::BLACK);
+        frame[1] = Rgb::RED;
+        assert_eq!(frame.get(1), Rgb::RED);
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut frame = Frame::new(2);
+        frame.fill((4, 5, 6));
+        assert_eq!(frame.to_bytes(), vec![4, 5, 6, 4, 5, 6]);
+
+        let mut frame = Frame::new(5);
+        frame.fill_range(1..3, Rgb::WHITE);
+        assert_eq!(frame.iter().filter(|&&pixel| pixel == Rgb::WHITE).count(), 2);
+        frame.copy_at(4, &[Rgb::RED, Rgb::RED]);
+        assert_eq!(frame[3..], [Rgb::BLACK, Rgb::RED]);
+        assert_eq!(frame.slice(2..4).as_slice(), &[Rgb::WHITE, Rgb::BLACK]);
+    }
+
+    #[test]
+    fn test_wire_format() {
+        let frame = Frame::from_bytes(&[1, 2, 3, 4, 5, 6, 7]).unwrap();
+        assert_eq!(frame.len(), 2);
+        assert_eq!(frame[1], Rgb::new(4, 5, 6));
+        assert_eq!(frame.to_bytes(), vec![1, 2, 3, 4, 5, 6]);
+        assert!(Frame::from_bytes(&[0; MAX_LED_COUNT * 3 + 3]).is_err());
+
+        let mut out = [9; 7];
+        frame.write_bytes(&mut out);
+        assert_eq!(out, [1, 2, 3, 4, 5, 6, 9]);
+    }
+
+    #[test]
+    fn test_resize_and_collect() {
+        let mut frame: Frame = std::iter::repeat_n(Rgb::GREEN, MAX_LED_COUNT + 10).collect();
+        assert_eq!(frame.len(), MAX_LED_COUNT);
+        frame.resize(1);
+        frame.resize(3);
+        assert_eq!(frame.as_slice(), &[Rgb::GREEN, Rgb::BLACK, Rgb::BLACK]);
+        for pixel in &mut frame {
+            *pixel = pixel.lerp(Rgb::BLUE, 1.0);
+        }
+        assert!(frame.iter().all(|&pixel| pixel == Rgb::BLUE));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_out_of_bounds() {
+        let mut frame = Frame::new(2);
+        frame.set(2, (1, 1, 1));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let mut frame = Frame::new(2);
+        frame.set(1, (0xff, 0x10, 0));
+        let json = serde_json::to_string(&frame).unwrap();
+        assert_eq!(json, r#""000000ff1000""#);
+        assert_eq!(serde_json::from_str::<Frame>(&json).unwrap(), frame);
+        assert!(serde_json::from_str::<Frame>(r#""0000""#).is_err());
+    }
+}
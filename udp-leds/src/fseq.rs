@@ -0,0 +1,393 @@
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::{constants::MAX_LED_COUNT, error::Error, frame::Frame};
+
+/**
+ * # FSEQ files
+ * Reader for the sequences exported by xLights and played by FPP
+ *
+ * Both versions of the format are supported:
+ * - v1: uncompressed, every channel of every frame is stored
+ * - v2: optionally compressed with zstd or zlib in blocks of frames, optionally sparse
+ *   (only some ranges of channels are stored)
+ *
+ * Channels are numbered from 0 here, xLights numbers them from 1
+ */
+#[derive(Debug)]
+pub struct Fseq {
+    data: Vec<u8>,
+    major: u8,
+    minor: u8,
+    channel_count: u32,
+    frame_count: u32,
+    step_ms: u8,
+    compression: Compression,
+    blocks: Vec<Block>,
+    sparse: Vec<(u32, u32)>,
+    frame_size: usize,
+    data_offset: usize,
+    cache: Option<(usize, Vec<u8>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Compression {
+    None,
+    Zstd,
+    Zlib,
+}
+
+/// A compressed block of frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Block {
+    first_frame: u32,
+    offset: usize,
+    len: usize,
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Result<u16, Error> {
+    let bytes = data.get(pos..pos + 2).ok_or(Error::InvalidFseq)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u24_at(data: &[u8], pos: usize) -> Result<u32, Error> {
+    let bytes = data.get(pos..pos + 3).ok_or(Error::InvalidFseq)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Result<u32, Error> {
+    let bytes = data.get(pos..pos + 4).ok_or(Error::InvalidFseq)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+impl Fseq {
+    /// Reads a FSEQ file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::parse(std::fs::read(path)?)
+    }
+
+    /// Parses the content of a FSEQ file
+    pub fn parse(data: Vec<u8>) -> Result<Self, Error> {
+        if data.len() < 20 || !(&data[..4] == b"PSEQ" || &data[..4] == b"FSEQ") {
+            return Err(Error::InvalidFseq);
+        }
+        let data_offset = u16_at(&data, 4)? as usize;
+        let minor = data[6];
+        let major = data[7];
+        let channel_count = u32_at(&data, 10)?;
+        let frame_count = u32_at(&data, 14)?;
+        let step_ms = data[18];
+
+        let mut fseq = Fseq {
+            major,
+            minor,
+            channel_count,
+            frame_count,
+            step_ms,
+            compression: Compression::None,
+            blocks: Vec::new(),
+            sparse: Vec::new(),
+            frame_size: channel_count as usize,
+            data_offset,
+            cache: None,
+            data: Vec::new(),
+        };
+
+        match major {
+            1 => {},
+            2 => {
+                let header = data.get(20..32).ok_or(Error::InvalidFseq)?;
+                fseq.compression = match header[0] & 0x0f {
+                    0 => Compression::None,
+                    1 => Compression::Zstd,
+                    2 => Compression::Zlib,
+                    _ => return Err(Error::InvalidFseq),
+                };
+                let block_count = ((header[0] as usize & 0xf0) << 4) | header[1] as usize;
+                let sparse_count = header[2] as usize;
+
+                let mut offset = data_offset;
+                for i in 0..block_count {
+                    let first_frame = u32_at(&data, 32 + i * 8)?;
+                    let len = u32_at(&data, 36 + i * 8)? as usize;
+                    let start = offset;
+                    // The skipped blocks still take their room in the file
+                    offset = offset.checked_add(len).ok_or(Error::InvalidFseq)?;
+                    if len == 0 || first_frame >= frame_count {
+                        continue;
+                    }
+                    fseq.blocks.push(Block { first_frame, offset: start, len });
+                }
+                if fseq.compression != Compression::None && fseq.blocks.is_empty() {
+                    return Err(Error::InvalidFseq);
+                }
+
+                let sparse_start = 32 + block_count * 8;
+                for i in 0..sparse_count {
+                    let start = u24_at(&data, sparse_start + i * 6)?;
+                    let count = u24_at(&data, sparse_start + i * 6 + 3)?;
+                    fseq.sparse.push((start, count));
+                }
+                if !fseq.sparse.is_empty() {
+                    fseq.frame_size = fseq
+                        .sparse
+                        .iter()
+                        .try_fold(0usize, |size, (_, count)| size.checked_add(*count as usize))
+                        .ok_or(Error::InvalidFseq)?;
+                }
+            },
+            _ => return Err(Error::InvalidFseq),
+        }
+
+        if fseq.compression == Compression::None {
+            let needed = fseq
+                .frame_size
+                .checked_mul(frame_count as usize)
+                .and_then(|size| size.checked_add(data_offset))
+                .ok_or(Error::InvalidFseq)?;
+            if data.len() < needed {
+                return Err(Error::InvalidFseq);
+            }
+        } else if fseq.blocks.iter().any(|block| block.offset.checked_add(block.len).filter(|end| *end <= data.len()).is_none()) {
+            return Err(Error::InvalidFseq);
+        }
+
+        fseq.data = data;
+        Ok(fseq)
+    }
+
+    /// Version of the file as (major, minor)
+    pub fn version(&self) -> (u8, u8) {
+        (self.major, self.minor)
+    }
+
+    /// Number of channels of a frame, a RGB pixel uses three channels
+    pub fn channel_count(&self) -> u32 {
+        self.channel_count
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Time each frame stays on the strip
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_millis(self.step_ms.max(1) as u64)
+    }
+
+    /// Returns `count` channels starting at channel `start` of a frame
+    ///
+    /// Channels outside the file or not stored by a sparse file are black
+    pub fn read_channels(&mut self, frame: u32, start: u32, count: u32) -> Result<Vec<u8>, Error> {
+        if frame >= self.frame_count {
+            return Err(Error::InvalidFseq);
+        }
+        let indexes: Vec<Option<usize>> = (start..start.saturating_add(count)).map(|channel| self.stored_index(channel)).collect();
+        let stored = self.stored_frame(frame)?;
+        Ok(indexes.into_iter().map(|index| index.map_or(0, |index| stored[index])).collect())
+    }
+
+    /// Maps `led_count` RGB pixels starting at channel `start` of a frame onto a strip
+    pub fn read_frame(&mut self, frame: u32, start: u32, led_count: usize) -> Result<Frame, Error> {
+        let led_count = led_count.min(MAX_LED_COUNT);
+        let channels = self.read_channels(frame, start, led_count as u32 * 3)?;
+        let mut strip = Frame::new(led_count);
+        for (index, pixel) in channels.chunks_exact(3).enumerate() {
+            strip.set(index, (pixel[0], pixel[1], pixel[2]));
+        }
+        Ok(strip)
+    }
+
+    /// Position of an absolute channel in a stored frame
+    fn stored_index(&self, channel: u32) -> Option<usize> {
+        if channel >= self.channel_count {
+            return None;
+        }
+        if self.sparse.is_empty() {
+            return Some(channel as usize);
+        }
+        let mut offset = 0;
+        for &(start, count) in &self.sparse {
+            if channel >= start && channel < start + count {
+                return Some(offset + (channel - start) as usize);
+            }
+            offset += count as usize;
+        }
+        None
+    }
+
+    /// The stored bytes of a frame, decompressing its block if needed
+    fn stored_frame(&mut self, frame: u32) -> Result<&[u8], Error> {
+        let size = self.frame_size;
+        if self.compression == Compression::None {
+            let start = self.data_offset + size * frame as usize;
+            return Ok(&self.data[start..start + size]);
+        }
+
+        let index = self
+            .blocks
+            .iter()
+            .rposition(|block| block.first_frame <= frame)
+            .ok_or(Error::InvalidFseq)?;
+        if self.cache.as_ref().map(|(cached, _)| *cached) != Some(index) {
+            let block = self.blocks[index];
+            let compressed = &self.data[block.offset..block.offset + block.len];
+            // A block holds the frames up to the next one, reading one byte more tells an over-long block
+            let next_frame = self.blocks.get(index + 1).map_or(self.frame_count, |next| next.first_frame);
+            let expected = next_frame
+                .checked_sub(block.first_frame)
+                .and_then(|frames| (frames as usize).checked_mul(size))
+                .ok_or(Error::InvalidFseq)?;
+            let limit = expected as u64 + 1;
+            let mut decompressed = Vec::new();
+            match self.compression {
+                Compression::Zstd => {
+                    let decoder = ruzstd::decoding::StreamingDecoder::new(compressed).map_err(|_| Error::InvalidFseq)?;
+                    decoder.take(limit).read_to_end(&mut decompressed).map_err(|_| Error::InvalidFseq)?;
+                },
+                Compression::Zlib => {
+                    let decoder = flate2::read::ZlibDecoder::new(compressed);
+                    decoder.take(limit).read_to_end(&mut decompressed).map_err(|_| Error::InvalidFseq)?;
+                },
+                Compression::None => unreachable!(),
+            }
+            if decompressed.len() != expected {
+                return Err(Error::InvalidFseq);
+            }
+            self.cache = Some((index, decompressed));
+        }
+
+        let (_, decompressed) = self.cache.as_ref().unwrap();
+        let start = (frame - self.blocks[index].first_frame) as usize * size;
+        decompressed.get(start..start + size).ok_or(Error::InvalidFseq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const CHANNELS: u32 = 12;
+    const FRAMES: u32 = 4;
+
+    /// Channel `c` of frame `f` is worth `f * 16 + c`
+    fn channel_data(frames: std::ops::Range<u32>, channels: &[u32]) -> Vec<u8> {
+        frames
+            .flat_map(|frame| channels.iter().map(move |channel| (frame * 16 + channel) as u8))
+            .collect()
+    }
+
+    fn v1() -> Vec<u8> {
+        let mut data = b"PSEQ".to_vec();
+        data.extend_from_slice(&28u16.to_le_bytes());
+        data.extend_from_slice(&[0, 1]);
+        data.extend_from_slice(&28u16.to_le_bytes());
+        data.extend_from_slice(&CHANNELS.to_le_bytes());
+        data.extend_from_slice(&FRAMES.to_le_bytes());
+        data.extend_from_slice(&[50, 0, 0, 0, 0, 0, 1, 0, 0, 0]);
+        data.extend(channel_data(0..FRAMES, &(0..CHANNELS).collect::<Vec<_>>()));
+        data
+    }
+
+    fn v2(compression: u8, blocks: &[(u32, Vec<u8>)], sparse: &[(u32, u32)]) -> Vec<u8> {
+        let data_offset = 32 + blocks.len() * 8 + sparse.len() * 6;
+        let mut data = b"PSEQ".to_vec();
+        data.extend_from_slice(&(data_offset as u16).to_le_bytes());
+        data.extend_from_slice(&[0, 2]);
+        data.extend_from_slice(&(data_offset as u16).to_le_bytes());
+        data.extend_from_slice(&CHANNELS.to_le_bytes());
+        data.extend_from_slice(&FRAMES.to_le_bytes());
+        data.extend_from_slice(&[25, 0, compression, blocks.len() as u8, sparse.len() as u8, 0]);
+        data.extend_from_slice(&[0; 8]);
+        for (first_frame, block) in blocks {
+            data.extend_from_slice(&first_frame.to_le_bytes());
+            data.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        }
+        for (start, count) in sparse {
+            data.extend_from_slice(&start.to_le_bytes()[..3]);
+            data.extend_from_slice(&count.to_le_bytes()[..3]);
+        }
+        for (_, block) in blocks {
+            data.extend_from_slice(block);
+        }
+        data
+    }
+
+    #[test]
+    fn test_v1() {
+        let mut fseq = Fseq::parse(v1()).unwrap();
+        assert_eq!(fseq.version(), (1, 0));
+        assert_eq!(fseq.frame_duration(), Duration::from_millis(50));
+        assert_eq!(fseq.read_channels(2, 10, 4).unwrap(), vec![42, 43, 0, 0]);
+
+        let frame = fseq.read_frame(1, 3, 2).unwrap();
+        assert_eq!(frame.to_bytes(), vec![19, 20, 21, 22, 23, 24]);
+    }
+
+    #[test]
+    fn test_v2_zstd_blocks() {
+        let all: Vec<u32> = (0..CHANNELS).collect();
+        let compress = |frames| ruzstd::encoding::compress_to_vec(&channel_data(frames, &all)[..], ruzstd::encoding::CompressionLevel::Fastest);
+        // The block past the last frame is skipped along with its bytes
+        let blocks = vec![(0, compress(0..3)), (FRAMES, vec![1, 2, 3]), (3, compress(3..4)), (0, Vec::new())];
+        let mut fseq = Fseq::parse(v2(1, &blocks, &[])).unwrap();
+        assert_eq!(fseq.frame_count(), FRAMES);
+        for frame in [3, 0, 2] {
+            assert_eq!(fseq.read_channels(frame, 0, CHANNELS).unwrap(), channel_data(frame..frame + 1, &all));
+        }
+        assert_eq!(fseq.read_channels(4, 0, 1), Err(Error::InvalidFseq));
+    }
+
+    #[test]
+    fn test_v2_zlib_sparse() {
+        let stored = [2, 3, 4, 9, 10];
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&channel_data(0..FRAMES, &stored)).unwrap();
+        let blocks = vec![(0, encoder.finish().unwrap())];
+        let mut fseq = Fseq::parse(v2(2, &blocks, &[(2, 3), (9, 2)])).unwrap();
+        assert_eq!(fseq.read_channels(1, 0, 12).unwrap(), vec![0, 0, 18, 19, 20, 0, 0, 0, 0, 25, 26, 0]);
+    }
+
+    #[test]
+    fn test_v2_uncompressed() {
+        let all: Vec<u32> = (0..CHANNELS).collect();
+        let mut data = v2(0, &[], &[]);
+        data.extend(channel_data(0..FRAMES, &all));
+        let mut fseq = Fseq::parse(data).unwrap();
+        assert_eq!(fseq.read_channels(3, 11, 1).unwrap(), vec![59]);
+    }
+
+    #[test]
+    fn test_invalid_fseq() {
+        let mut truncated = v1();
+        truncated.pop();
+        assert_eq!(Fseq::parse(truncated).err(), Some(Error::InvalidFseq));
+        assert_eq!(Fseq::parse(b"NOPE".to_vec()).err(), Some(Error::InvalidFseq));
+        assert_eq!(Fseq::parse(v2(1, &[], &[])).err(), Some(Error::InvalidFseq));
+
+        // The blocks have to hold exactly their frames, however much they expand
+        let all: Vec<u32> = (0..CHANNELS).collect();
+        let zlib = |data: &[u8]| {
+            let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        let mut bomb = channel_data(0..FRAMES, &all);
+        bomb.resize(1 << 20, 0);
+        let mut fseq = Fseq::parse(v2(2, &[(0, zlib(&bomb))], &[])).unwrap();
+        assert_eq!(fseq.read_channels(0, 0, 1), Err(Error::InvalidFseq));
+        let mut fseq = Fseq::parse(v2(2, &[(0, zlib(&channel_data(0..FRAMES - 1, &all)))], &[])).unwrap();
+        assert_eq!(fseq.read_channels(0, 0, 1), Err(Error::InvalidFseq));
+
+        // Sizes too large for the memory are refused, not computed
+        let mut huge = v2(0, &[], &[(0, 0xff_ffff); 255]);
+        huge[14..18].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(Fseq::parse(huge).err(), Some(Error::InvalidFseq));
+        let mut huge = v1();
+        huge[10..18].copy_from_slice(&[0xff; 8]);
+        assert_eq!(Fseq::parse(huge).err(), Some(Error::InvalidFseq));
+    }
+}
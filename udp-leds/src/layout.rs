@@ -0,0 +1,289 @@
+use crate::{constants::MAX_LED_COUNT, error::Error};
+
+/// Corner of the screen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Corner {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomRight,
+    BottomLeft,
+}
+
+impl TryFrom<u8> for Corner {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Corner::TopLeft),
+            1 => Ok(Corner::TopRight),
+            2 => Ok(Corner::BottomRight),
+            3 => Ok(Corner::BottomLeft),
+            _ => Err(Error::InvalidLayout),
+        }
+    }
+}
+
+/// Way the strip runs around the screen, seen from the front
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    #[default]
+    Clockwise,
+    CounterClockwise,
+}
+
+impl TryFrom<u8> for Direction {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Direction::Clockwise),
+            1 => Ok(Direction::CounterClockwise),
+            _ => Err(Error::InvalidLayout),
+        }
+    }
+}
+
+/// Strip running around a screen, starting at a corner
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rectangle {
+    /// Number of LEDs of each side
+    pub top: u16,
+    pub right: u16,
+    pub bottom: u16,
+    pub left: u16,
+    /// Corner where the strip starts
+    pub start: Corner,
+    pub direction: Direction,
+    /// LEDs hidden at the end of each side, behind the corners of the screen
+    pub corner_gap: u8,
+    /// Width of the gap in the middle of the bottom side, for a stand, counted in LEDs of the bottom side
+    pub bottom_gap: u16,
+}
+
+/// LED placed on a 256 by 256 grid covering the screen, (0, 0) being the top left corner
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Point {
+    pub x: u8,
+    pub y: u8,
+}
+
+/**
+ * # Layout
+ * Physical arrangement of the LEDs around the screen, stored by the controller so that the clients
+ * can sample the screen for each LED without being told where the LEDs are
+ *
+ * ## Encoding
+ * The layout starts with its kind, the 16 bits values are big endian
+ * [0] for an unknown layout
+ * [1, top, right, bottom, left, start, direction, corner_gap, bottom_gap] for a rectangle
+ * [2, count, (x, y)...] for arbitrary points
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Layout {
+    #[default]
+    Unknown,
+    Rectangle(Rectangle),
+    /// Position of each LED, in the order of the strip
+    Points(Vec<Point>),
+}
+
+impl Layout {
+    const RECTANGLE_LEN: usize = 14;
+
+    /// Number of LEDs of the strip covered by the layout, hidden ones included
+    pub fn led_count(&self) -> usize {
+        match self {
+            Layout::Unknown => 0,
+            Layout::Rectangle(rectangle) => {
+                let sides = [rectangle.top, rectangle.right, rectangle.bottom, rectangle.left];
+                sides.iter().map(|&count| count as usize + rectangle.corner_gap as usize).sum()
+            },
+            Layout::Points(points) => points.len(),
+        }
+    }
+
+    /// Checks the layout fits on a strip of `led_count` LEDs
+    pub fn validate(&self, led_count: usize) -> Result<(), Error> {
+        if self.led_count() > led_count.min(MAX_LED_COUNT) {
+            return Err(Error::InvalidLayout);
+        }
+        Ok(())
+    }
+
+    /// Position of each LED on the screen, from (0, 0) at the top left corner to (1, 1) at the bottom right one
+    ///
+    /// The LEDs of a side are spread evenly along it, the hidden LEDs have no position
+    pub fn positions(&self) -> Vec<Option<(f32, f32)>> {
+        match self {
+            Layout::Unknown => Vec::new(),
+            Layout::Rectangle(rectangle) => rectangle.positions(),
+            Layout::Points(points) => points
+                .iter()
+                .map(|point| Some((point.x as f32 / 255.0, point.y as f32 / 255.0)))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn encoded_len(&self) -> usize {
+        match self {
+            Layout::Unknown => 1,
+            Layout::Rectangle(_) => Self::RECTANGLE_LEN,
+            Layout::Points(points) => 3 + points.len() * 2,
+        }
+    }
+
+    pub(crate) fn encode(&self, out: &mut [u8]) {
+        match self {
+            Layout::Unknown => out[0] = 0,
+            Layout::Rectangle(rectangle) => {
+                out[0] = 1;
+                let sides = [rectangle.top, rectangle.right, rectangle.bottom, rectangle.left];
+                for (encoded, count) in out[1..9].chunks_exact_mut(2).zip(sides) {
+                    encoded.copy_from_slice(&count.to_be_bytes());
+                }
+                out[9] = rectangle.start as u8;
+                out[10] = rectangle.direction as u8;
+                out[11] = rectangle.corner_gap;
+                out[12..14].copy_from_slice(&rectangle.bottom_gap.to_be_bytes());
+            },
+            Layout::Points(points) => {
+                out[0] = 2;
+                out[1..3].copy_from_slice(&(points.len() as u16).to_be_bytes());
+                for (encoded, point) in out[3..].chunks_exact_mut(2).zip(points) {
+                    encoded.copy_from_slice(&[point.x, point.y]);
+                }
+            },
+        }
+    }
+
+    pub(crate) fn decode(value: &[u8]) -> Result<Self, Error> {
+        let u16_at = |index: usize| u16::from_be_bytes([value[index], value[index + 1]]);
+        match value.first() {
+            None => Err(Error::InvalidMessageLength),
+            Some(0) if value.len() == 1 => Ok(Layout::Unknown),
+            Some(1) if value.len() == Self::RECTANGLE_LEN => Ok(Layout::Rectangle(Rectangle {
+                top: u16_at(1),
+                right: u16_at(3),
+                bottom: u16_at(5),
+                left: u16_at(7),
+                start: Corner::try_from(value[9])?,
+                direction: Direction::try_from(value[10])?,
+                corner_gap: value[11],
+                bottom_gap: u16_at(12),
+            })),
+            Some(2) if value.len() >= 3 && value.len() == 3 + u16_at(1) as usize * 2 => Ok(Layout::Points(
+                value[3..].chunks_exact(2).map(|point| Point { x: point[0], y: point[1] }).collect(),
+            )),
+            Some(0..=2) => Err(Error::InvalidMessageLength),
+            Some(_) => Err(Error::InvalidLayout),
+        }
+    }
+
+    /// Encodes the layout on its own, as stored by the controllers
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; self.encoded_len()];
+        self.encode(&mut bytes);
+        bytes
+    }
+
+    /// Decodes a layout stored by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::decode(bytes)
+    }
+}
+
+impl Rectangle {
+    fn positions(&self) -> Vec<Option<(f32, f32)>> {
+        // Sides in clockwise order, each one running clockwise from the corner it starts at
+        let sides = [self.top, self.right, self.bottom, self.left];
+        let first = self.start as usize;
+        let order: Vec<(usize, bool)> = match self.direction {
+            Direction::Clockwise => (0..4).map(|step| ((first + step) % 4, false)).collect(),
+            Direction::CounterClockwise => (0..4).map(|step| ((first + 7 - step) % 4, true)).collect(),
+        };
+
+        let mut positions = Vec::with_capacity(Layout::Rectangle(*self).led_count());
+        for (side, reversed) in order {
+            let count = sides[side] as usize;
+            let gap = if side == 2 { self.bottom_gap as usize } else { 0 };
+            let slots = count + gap;
+            for index in 0..count {
+                let index = if reversed { count - 1 - index } else { index };
+                // The gap is kept in the middle of the side
+                let slot = if index < count / 2 { index } else { index + gap };
+                let along = (slot as f32 + 0.5) / slots as f32;
+                positions.push(Some(match side {
+                    0 => (along, 0.0),
+                    1 => (1.0, along),
+                    2 => (1.0 - along, 1.0),
+                    _ => (0.0, 1.0 - along),
+                }));
+            }
+            positions.extend(std::iter::repeat(None).take(self.corner_gap as usize));
+        }
+        positions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rectangle() -> Rectangle {
+        Rectangle { top: 2, right: 1, bottom: 2, left: 1, ..Default::default() }
+    }
+
+    #[test]
+    fn test_encoding() {
+        let layout = Layout::Rectangle(Rectangle { start: Corner::BottomLeft, direction: Direction::CounterClockwise, corner_gap: 1, bottom_gap: 3, ..rectangle() });
+        let bytes = layout.to_bytes();
+        assert_eq!(bytes, [1, 0, 2, 0, 1, 0, 2, 0, 1, 3, 1, 1, 0, 3]);
+        assert_eq!(Layout::from_bytes(&bytes), Ok(layout));
+        assert_eq!(Layout::from_bytes(&bytes[..13]), Err(Error::InvalidMessageLength));
+
+        let layout = Layout::Points(vec![Point { x: 1, y: 2 }, Point { x: 3, y: 4 }]);
+        let bytes = layout.to_bytes();
+        assert_eq!(bytes, [2, 0, 2, 1, 2, 3, 4]);
+        assert_eq!(Layout::from_bytes(&bytes), Ok(layout));
+        assert_eq!(Layout::from_bytes(&[0]), Ok(Layout::Unknown));
+        assert_eq!(Layout::from_bytes(&[3]), Err(Error::InvalidLayout));
+        assert_eq!(Layout::from_bytes(&[1, 0, 2, 0, 1, 0, 2, 0, 1, 4, 0, 0, 0, 0]), Err(Error::InvalidLayout));
+    }
+
+    #[test]
+    fn test_rectangle_positions() {
+        let positions = Layout::Rectangle(rectangle()).positions();
+        assert_eq!(
+            positions,
+            [Some((0.25, 0.0)), Some((0.75, 0.0)), Some((1.0, 0.5)), Some((0.75, 1.0)), Some((0.25, 1.0)), Some((0.0, 0.5))]
+        );
+
+        let layout = Layout::Rectangle(Rectangle { start: Corner::BottomLeft, direction: Direction::CounterClockwise, corner_gap: 1, ..rectangle() });
+        assert_eq!(layout.led_count(), 10);
+        let positions = layout.positions();
+        assert_eq!(positions[..3], [Some((0.25, 1.0)), Some((0.75, 1.0)), None]);
+        assert_eq!(positions[3], Some((1.0, 0.5)));
+        assert_eq!(positions[5..7], [Some((0.75, 0.0)), Some((0.25, 0.0))]);
+        assert_eq!(positions[8], Some((0.0, 0.5)));
+    }
+
+    #[test]
+    fn test_bottom_gap() {
+        let layout = Layout::Rectangle(Rectangle { top: 0, right: 0, left: 0, bottom: 2, bottom_gap: 2, ..Default::default() });
+        assert_eq!(layout.positions(), [Some((0.875, 1.0)), Some((0.125, 1.0))]);
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(Layout::Rectangle(rectangle()).validate(6), Ok(()));
+        assert_eq!(Layout::Rectangle(rectangle()).validate(5), Err(Error::InvalidLayout));
+        assert_eq!(Layout::Points(vec![Point::default(); 3]).positions(), [Some((0.0, 0.0)); 3]);
+        assert_eq!(Layout::Unknown.validate(0), Ok(()));
+    }
+}
@@ -2,3 +2,33 @@ pub mod constants;
 pub mod client;
 pub mod server;
 pub mod error;
+pub mod frame;
+pub mod color;
+pub mod device;
+pub mod mdns;
+pub mod capture;
+pub mod sequence;
+pub mod sync;
+pub mod config;
+pub mod ota;
+pub mod segment;
+pub mod layout;
+pub mod effects;
+#[cfg(feature = "fseq")]
+pub mod fseq;
+mod varint;
+mod random;
+#[cfg(feature = "serde")]
+mod hex;
+#[cfg(feature = "crypto")]
+pub mod crypto;
+#[cfg(feature = "tokio")]
+pub mod async_client;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+pub use client::Client;
+pub use color::Rgb;
+pub use device::{ClientToken, DeviceId};
+pub use frame::Frame;
+pub use server::{LedSink, Server};
@@ -0,0 +1,641 @@
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::{client::ServerInfo, constants::MAX_LED_COUNT, error::Error, server::Advertisement};
+
+/**
+ * # mDNS / DNS-SD
+ * Advertisement and discovery of the LED servers on the local network without relying on broadcasts
+ *
+ * The servers are advertised as `_udpleds._udp.local` services
+ * Each service comes with the following records:
+ * - PTR `_udpleds._udp.local` -> `<name>._udpleds._udp.local`
+ * - SRV `<name>._udpleds._udp.local` -> `<host>.local:<port>`
+ * - TXT `<name>._udpleds._udp.local` -> `leds=<led count>`, `version=<version>`, `name=<name>`, `id=<unique id>`
+ * - A `<host>.local` -> address of the server (only if known)
+ *
+ * Only the subset of DNS needed for this is implemented, the `Responder` answers the queries
+ * mentioning one of its names and the `Browser` sends a PTR query and collects the answers
+ */
+pub const SERVICE_TYPE: &str = "_udpleds._udp.local";
+pub const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_PORT: u16 = 5353;
+
+const SERVICES_META_QUERY: &str = "_services._dns-sd._udp.local";
+const TTL: u32 = 120;
+const MAX_PACKET_LENGTH: usize = 1500;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CLASS_MASK: u16 = 0x7fff;
+const UNICAST_RESPONSE: u16 = 0x8000;
+const CACHE_FLUSH: u16 = 0x8000;
+const FLAGS_RESPONSE: u16 = 0x8400;
+
+/// A LED server advertised over mDNS
+#[derive(Debug, Clone, PartialEq)]
+pub struct Service {
+    /// Instance name of the server, dots are not allowed
+    pub name: String,
+    /// Host name of the server, without the `.local` suffix
+    pub host: String,
+    /// Address of the server, `None` if unknown
+    pub addr: Option<Ipv4Addr>,
+    pub port: u16,
+    pub led_count: u16,
+    pub version: String,
+    /// Stable unique id of the server, see `Advertisement::id`
+    pub id: [u8; 6],
+}
+
+impl Service {
+    /// Creates a new service advertising the LED server of the given name
+    pub fn new(name: &str, id: [u8; 6], addr: Option<Ipv4Addr>, port: u16, led_count: u16) -> Self {
+        let name = name.replace('.', "-");
+        Service {
+            host: name.replace(' ', "-").to_lowercase(),
+            name,
+            addr,
+            port,
+            led_count,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            id,
+        }
+    }
+
+    /// Address of the LED server, `None` if the address of the host is unknown
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        self.addr.map(|addr| SocketAddr::from((addr, self.port)))
+    }
+
+    /// The server described by the service, `None` if the address of the host is unknown
+    pub fn server_info(&self) -> Option<ServerInfo> {
+        Some(ServerInfo {
+            addr: self.socket_addr()?,
+            advertisement: Advertisement {
+                led_count: self.led_count,
+                max_fps: 0,
+                id: self.id,
+                name: self.name.clone(),
+            },
+        })
+    }
+
+    fn instance_name(&self) -> String {
+        format!("{}.{}", self.name, SERVICE_TYPE)
+    }
+
+    fn host_name(&self) -> String {
+        format!("{}.local", self.host)
+    }
+
+    fn txt(&self) -> Vec<String> {
+        vec![
+            format!("leds={}", self.led_count),
+            format!("version={}", self.version),
+            format!("name={}", self.name),
+            format!("id={}", self.id.iter().map(|byte| format!("{byte:02x}")).collect::<String>()),
+        ]
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        [SERVICE_TYPE.to_string(), SERVICES_META_QUERY.to_string(), self.instance_name(), self.host_name()]
+            .iter()
+            .any(|own| own.eq_ignore_ascii_case(name))
+    }
+}
+
+/**
+ * # Responder
+ * Answers the mDNS queries for a single service
+ */
+#[derive(Debug)]
+pub struct Responder {
+    socket: UdpSocket,
+    service: Service,
+}
+
+impl Responder {
+    /// Creates a responder listening on the mDNS multicast group
+    pub fn new(service: Service) -> Result<Self, Error> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, MDNS_PORT))?;
+        socket.join_multicast_v4(&MDNS_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+        socket.set_multicast_loop_v4(true)?;
+        Ok(Responder { socket, service })
+    }
+
+    /// Creates a responder listening on the given address, mostly useful for tests
+    pub fn bind(addr: impl ToSocketAddrs, service: Service) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(addr)?;
+        Ok(Responder { socket, service })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    pub fn service(&self) -> &Service {
+        &self.service
+    }
+
+    /// Builds the response to a query, `None` if the query is not about this service
+    pub fn answer(&self, query: &[u8]) -> Option<Vec<u8>> {
+        let (id, questions) = parse_questions(query).ok()?;
+        let relevant = questions.iter().any(|(name, kind, _)| {
+            matches!(*kind, TYPE_PTR | TYPE_SRV | TYPE_TXT | TYPE_A | TYPE_ANY) && self.service.matches(name)
+        });
+        relevant.then(|| response_packet(id, &self.service))
+    }
+
+    /// Sends an unsolicited announcement of the service to the multicast group
+    pub fn announce(&self) -> Result<(), Error> {
+        self.socket.send_to(&response_packet(0, &self.service), (MDNS_ADDR, MDNS_PORT))?;
+        Ok(())
+    }
+
+    /// Waits for the next query and answers it
+    ///
+    /// Queries coming from another port than 5353 or asking for an unicast response are answered directly,
+    /// the others are answered on the multicast group
+    pub fn handle_next(&self) -> Result<(), Error> {
+        let mut buf = [0; MAX_PACKET_LENGTH];
+        let (size, addr) = self.socket.recv_from(&mut buf)?;
+        let Some(response) = self.answer(&buf[..size]) else {
+            return Ok(());
+        };
+        let unicast = addr.port() != MDNS_PORT || wants_unicast(&buf[..size]);
+        if unicast {
+            self.socket.send_to(&response, addr)?;
+        } else {
+            self.socket.send_to(&response, (MDNS_ADDR, MDNS_PORT))?;
+        }
+        Ok(())
+    }
+
+    /// Answers the queries forever
+    pub fn run(&self) -> Result<(), Error> {
+        loop {
+            match self.handle_next() {
+                Ok(()) | Err(Error::Timeout) => continue,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+/**
+ * # Browser
+ * Looks for the LED servers advertised on the network
+ */
+#[derive(Debug)]
+pub struct Browser {
+    socket: UdpSocket,
+    target: SocketAddr,
+}
+
+impl Browser {
+    /// Creates a browser querying the mDNS multicast group
+    pub fn new() -> Result<Self, Error> {
+        Self::bind((Ipv4Addr::UNSPECIFIED, 0))
+    }
+
+    /// Creates a browser bound to the given address
+    pub fn bind(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(addr)?;
+        Ok(Browser {
+            socket,
+            target: SocketAddr::from((MDNS_ADDR, MDNS_PORT)),
+        })
+    }
+
+    /// Sets the address the queries are sent to
+    pub fn set_query_address(&mut self, addr: SocketAddr) {
+        self.target = addr;
+    }
+
+    /// Queries the LED servers and collects every answer received before the timeout
+    pub fn browse(&self, timeout: Duration) -> Result<Vec<Service>, Error> {
+        self.socket.send_to(&query_packet(), self.target)?;
+
+        let mut services: Vec<Service> = Vec::new();
+        let mut buf = [0; MAX_PACKET_LENGTH];
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(services);
+            }
+            self.socket.set_read_timeout(Some(deadline - now))?;
+            let (size, addr) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(error) => match Error::from(error) {
+                    Error::Timeout => return Ok(services),
+                    error => return Err(error),
+                },
+            };
+            let Ok(records) = parse_records(&buf[..size]) else {
+                continue;
+            };
+            let source = match addr.ip() {
+                std::net::IpAddr::V4(ip) => Some(ip),
+                std::net::IpAddr::V6(_) => None,
+            };
+            for service in services_from_records(&records, source) {
+                if !services.contains(&service) {
+                    services.push(service);
+                }
+            }
+        }
+    }
+}
+
+/// Name, type and class of a question
+type Question = (String, u16, u16);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Record {
+    Ptr { name: String, target: String },
+    Srv { name: String, port: u16, target: String },
+    Txt { name: String, entries: Vec<String> },
+    A { name: String, addr: Ipv4Addr },
+    Other,
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn header(id: u16, flags: u16, questions: u16, answers: u16) -> Self {
+        let mut writer = Writer(Vec::with_capacity(MAX_PACKET_LENGTH));
+        for value in [id, flags, questions, answers, 0, 0] {
+            writer.u16(value);
+        }
+        writer
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn name(&mut self, name: &str) {
+        for label in name.split('.').filter(|label| !label.is_empty()) {
+            // Labels are cut to 63 bytes without splitting a character
+            let mut len = label.len().min(63);
+            while !label.is_char_boundary(len) {
+                len -= 1;
+            }
+            let label = &label.as_bytes()[..len];
+            self.0.push(label.len() as u8);
+            self.0.extend_from_slice(label);
+        }
+        self.0.push(0);
+    }
+
+    fn record(&mut self, name: &str, kind: u16, class: u16, rdata: &[u8]) {
+        self.name(name);
+        self.u16(kind);
+        self.u16(class);
+        self.u32(TTL);
+        self.u16(rdata.len() as u16);
+        self.0.extend_from_slice(rdata);
+    }
+}
+
+struct Reader<'a> {
+    packet: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], Error> {
+        let bytes = self.packet.get(self.pos..self.pos + count).ok_or(Error::MalformedDns)?;
+        self.pos += count;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a possibly compressed name
+    fn name(&mut self) -> Result<String, Error> {
+        let mut labels: Vec<String> = Vec::new();
+        let mut pos = self.pos;
+        let mut jumps = 0;
+        let mut end = None;
+        loop {
+            let len = *self.packet.get(pos).ok_or(Error::MalformedDns)? as usize;
+            match len {
+                0 => {
+                    pos += 1;
+                    break;
+                },
+                len if len & 0xc0 == 0xc0 => {
+                    let low = *self.packet.get(pos + 1).ok_or(Error::MalformedDns)? as usize;
+                    end.get_or_insert(pos + 2);
+                    pos = (len & 0x3f) << 8 | low;
+                    jumps += 1;
+                    if jumps > 16 {
+                        return Err(Error::MalformedDns);
+                    }
+                },
+                len if len < 64 => {
+                    let label = self.packet.get(pos + 1..pos + 1 + len).ok_or(Error::MalformedDns)?;
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    pos += 1 + len;
+                },
+                _ => return Err(Error::MalformedDns),
+            }
+        }
+        self.pos = end.unwrap_or(pos);
+        Ok(labels.join("."))
+    }
+}
+
+fn query_packet() -> Vec<u8> {
+    let mut writer = Writer::header(0, 0, 1, 0);
+    writer.name(SERVICE_TYPE);
+    writer.u16(TYPE_PTR);
+    writer.u16(CLASS_IN | UNICAST_RESPONSE);
+    writer.0
+}
+
+fn response_packet(id: u16, service: &Service) -> Vec<u8> {
+    let instance = service.instance_name();
+    let host = service.host_name();
+    let records = if service.addr.is_some() { 4 } else { 3 };
+    let mut writer = Writer::header(id, FLAGS_RESPONSE, 0, records);
+
+    let mut rdata = Writer(Vec::new());
+    rdata.name(&instance);
+    writer.record(SERVICE_TYPE, TYPE_PTR, CLASS_IN, &rdata.0);
+
+    let mut rdata = Writer(Vec::new());
+    rdata.u16(0);
+    rdata.u16(0);
+    rdata.u16(service.port);
+    rdata.name(&host);
+    writer.record(&instance, TYPE_SRV, CLASS_IN | CACHE_FLUSH, &rdata.0);
+
+    let mut rdata = Vec::new();
+    for entry in service.txt() {
+        let entry = &entry.as_bytes()[..entry.len().min(255)];
+        rdata.push(entry.len() as u8);
+        rdata.extend_from_slice(entry);
+    }
+    writer.record(&instance, TYPE_TXT, CLASS_IN | CACHE_FLUSH, &rdata);
+
+    if let Some(addr) = service.addr {
+        writer.record(&host, TYPE_A, CLASS_IN | CACHE_FLUSH, &addr.octets());
+    }
+    writer.0
+}
+
+/// Returns the id and the questions of a query
+fn parse_questions(packet: &[u8]) -> Result<(u16, Vec<Question>), Error> {
+    let mut reader = Reader { packet, pos: 0 };
+    let id = reader.u16()?;
+    let flags = reader.u16()?;
+    if flags & 0x8000 != 0 {
+        return Ok((id, Vec::new()));
+    }
+    let count = reader.u16()?;
+    reader.bytes(6)?;
+    let mut questions = Vec::new();
+    for _ in 0..count {
+        let name = reader.name()?;
+        let kind = reader.u16()?;
+        let class = reader.u16()?;
+        questions.push((name, kind, class));
+    }
+    Ok((id, questions))
+}
+
+fn wants_unicast(packet: &[u8]) -> bool {
+    parse_questions(packet)
+        .map(|(_, questions)| questions.iter().any(|(_, _, class)| class & UNICAST_RESPONSE != 0))
+        .unwrap_or(false)
+}
+
+/// Returns every resource record of a response
+fn parse_records(packet: &[u8]) -> Result<Vec<Record>, Error> {
+    let mut reader = Reader { packet, pos: 0 };
+    reader.u16()?;
+    let flags = reader.u16()?;
+    if flags & 0x8000 == 0 {
+        return Ok(Vec::new());
+    }
+    let questions = reader.u16()?;
+    let count = reader.u16()? as usize + reader.u16()? as usize + reader.u16()? as usize;
+    for _ in 0..questions {
+        reader.name()?;
+        reader.bytes(4)?;
+    }
+
+    // The counts come from the packet, they are not trusted for an allocation
+    let mut records = Vec::new();
+    for _ in 0..count {
+        let name = reader.name()?;
+        let kind = reader.u16()?;
+        let class = reader.u16()? & CLASS_MASK;
+        reader.u32()?;
+        let len = reader.u16()? as usize;
+        let end = reader.pos + len;
+        if class != CLASS_IN {
+            reader.bytes(len)?;
+            records.push(Record::Other);
+            continue;
+        }
+        let record = match kind {
+            TYPE_PTR => Record::Ptr { name, target: reader.name()? },
+            TYPE_SRV => {
+                reader.bytes(4)?;
+                let port = reader.u16()?;
+                Record::Srv { name, port, target: reader.name()? }
+            },
+            TYPE_TXT => {
+                let mut entries = Vec::new();
+                while reader.pos < end {
+                    let len = reader.bytes(1)?[0] as usize;
+                    entries.push(String::from_utf8_lossy(reader.bytes(len)?).into_owned());
+                }
+                Record::Txt { name, entries }
+            },
+            TYPE_A if len == 4 => {
+                let octets = reader.bytes(4)?;
+                Record::A { name, addr: Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]) }
+            },
+            _ => Record::Other,
+        };
+        if reader.pos > end {
+            return Err(Error::MalformedDns);
+        }
+        reader.pos = end;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Assembles the services described by the records of a response
+///
+/// The source address of the response is used when the response has no A record for the host
+fn services_from_records(records: &[Record], source: Option<Ipv4Addr>) -> Vec<Service> {
+    let suffix = format!(".{}", SERVICE_TYPE);
+    let mut services = Vec::new();
+    for record in records {
+        let Record::Ptr { name, target: instance } = record else {
+            continue;
+        };
+        if !name.eq_ignore_ascii_case(SERVICE_TYPE) {
+            continue;
+        }
+        let Some(label) = instance.strip_suffix(&suffix) else {
+            continue;
+        };
+        let Some((port, host)) = records.iter().find_map(|record| match record {
+            Record::Srv { name, port, target } if name.eq_ignore_ascii_case(instance) => Some((*port, target)),
+            _ => None,
+        }) else {
+            continue;
+        };
+        let addr = records
+            .iter()
+            .find_map(|record| match record {
+                Record::A { name, addr } if name.eq_ignore_ascii_case(host) => Some(*addr),
+                _ => None,
+            })
+            .or(source);
+        let entries = records
+            .iter()
+            .find_map(|record| match record {
+                Record::Txt { name, entries } if name.eq_ignore_ascii_case(instance) => Some(entries.as_slice()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let txt = |key: &str| {
+            entries
+                .iter()
+                .find_map(|entry| entry.strip_prefix(key)?.strip_prefix('=').map(str::to_string))
+        };
+
+        services.push(Service {
+            name: txt("name").unwrap_or_else(|| label.to_string()),
+            host: host.trim_end_matches(".local").to_string(),
+            addr,
+            port,
+            led_count: txt("leds")
+                .and_then(|leds| leds.parse().ok())
+                .map(|leds: u16| leds.min(MAX_LED_COUNT as u16))
+                .unwrap_or(0),
+            version: txt("version").unwrap_or_default(),
+            id: txt("id").and_then(|id| parse_id(&id)).unwrap_or_default(),
+        });
+    }
+    services
+}
+
+fn parse_id(id: &str) -> Option<[u8; 6]> {
+    let mut bytes = [0; 6];
+    if id.len() != 12 || !id.is_ascii() {
+        return None;
+    }
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&id[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service() -> Service {
+        Service::new("Living room", [1, 2, 3, 4, 5, 6], Some(Ipv4Addr::new(127, 0, 0, 1)), crate::constants::PORT, 64)
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let service = service();
+        let records = parse_records(&response_packet(0, &service)).unwrap();
+        assert_eq!(services_from_records(&records, None), vec![service]);
+    }
+
+    #[test]
+    fn test_answers_only_relevant_queries() {
+        let responder = Responder::bind("127.0.0.1:0", service()).unwrap();
+        assert!(responder.answer(&query_packet()).is_some());
+
+        let mut other = Writer::header(0, 0, 1, 0);
+        other.name("_http._tcp.local");
+        other.u16(TYPE_PTR);
+        other.u16(CLASS_IN);
+        assert!(responder.answer(&other.0).is_none());
+        assert!(responder.answer(&response_packet(0, &service())).is_none());
+    }
+
+    #[test]
+    fn test_compressed_names() {
+        let mut packet = Writer::header(0, FLAGS_RESPONSE, 0, 1);
+        packet.name(SERVICE_TYPE);
+        packet.u16(TYPE_PTR);
+        packet.u16(CLASS_IN);
+        packet.u32(TTL);
+        packet.u16(7);
+        packet.0.extend_from_slice(&[4, b't', b'e', b's', b't', 0xc0, 12]);
+        let records = parse_records(&packet.0).unwrap();
+        assert_eq!(records, vec![Record::Ptr {
+            name: SERVICE_TYPE.to_string(),
+            target: format!("test.{}", SERVICE_TYPE),
+        }]);
+    }
+
+    #[test]
+    fn test_instance_names() {
+        let records = |instance: &str| {
+            vec![
+                Record::Ptr { name: SERVICE_TYPE.to_string(), target: instance.to_string() },
+                Record::Srv { name: instance.to_string(), port: 1234, target: "desk.local".to_string() },
+            ]
+        };
+        let services = services_from_records(&records(&format!("Desk é.{}", SERVICE_TYPE)), None);
+        assert_eq!(services[0].name, "Desk é");
+        // The instance does not end with the service type, a cut inside the character used to panic
+        assert_eq!(services_from_records(&records("aéééééééééééééééééééééé"), None), vec![]);
+
+        // Long labels are cut between two characters
+        let mut writer = Writer(Vec::new());
+        writer.name(&format!("{}é.local", "a".repeat(62)));
+        let mut reader = Reader { packet: &writer.0, pos: 0 };
+        assert_eq!(reader.name(), Ok(format!("{}.local", "a".repeat(62))));
+    }
+
+    #[test]
+    fn test_browse_loopback() {
+        let responder = Responder::bind("127.0.0.1:0", Service::new("Desk", [0xab; 6], None, 1234, 10)).unwrap();
+        let mut browser = Browser::bind("127.0.0.1:0").unwrap();
+        browser.set_query_address(responder.local_addr().unwrap());
+
+        let handle = std::thread::spawn(move || responder.handle_next());
+        let services = browser.browse(Duration::from_millis(200)).unwrap();
+        handle.join().unwrap().unwrap();
+
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].name, "Desk");
+        assert_eq!(services[0].led_count, 10);
+        assert_eq!(services[0].id, [0xab; 6]);
+        assert_eq!(services[0].socket_addr(), Some(SocketAddr::from(([127, 0, 0, 1], 1234))));
+    }
+}
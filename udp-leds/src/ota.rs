@@ -0,0 +1,325 @@
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+use crate::{constants::MAX_MESSAGE_LENGTH, error::Error};
+
+/// Largest firmware chunk fitting in a message
+pub const MAX_CHUNK_LENGTH: usize = MAX_MESSAGE_LENGTH - 6;
+
+/// SHA-256 hash of a firmware image
+pub fn hash(image: &[u8]) -> [u8; 32] {
+    Sha256::digest(image).into()
+}
+
+/// Where the server is in a firmware update
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OtaState {
+    /// The server cannot be updated over the network
+    Unsupported,
+    #[default]
+    Idle,
+    Receiving,
+    /// The whole image was received and matches its hash
+    Verified,
+    /// The image will be booted on the next restart
+    Committed,
+    /// The image could not be written or did not match its hash, the update has to begin again
+    Failed,
+}
+
+impl TryFrom<u8> for OtaState {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(OtaState::Unsupported),
+            1 => Ok(OtaState::Idle),
+            2 => Ok(OtaState::Receiving),
+            3 => Ok(OtaState::Verified),
+            4 => Ok(OtaState::Committed),
+            5 => Ok(OtaState::Failed),
+            _ => Err(Error::InvalidFlag),
+        }
+    }
+}
+
+/// Progress of a firmware update, sent by the server in answer to every update message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OtaStatus {
+    pub state: OtaState,
+    /// Number of bytes of the image received so far, where the next chunk has to start
+    pub offset: u32,
+}
+
+impl OtaStatus {
+    pub(crate) const ENCODED_LEN: usize = 5;
+
+    pub(crate) fn encode(&self, out: &mut [u8]) {
+        out[0] = self.state as u8;
+        out[1..5].copy_from_slice(&self.offset.to_be_bytes());
+    }
+
+    pub(crate) fn decode(value: &[u8]) -> Result<Self, Error> {
+        if value.len() != Self::ENCODED_LEN {
+            return Err(Error::InvalidMessageLength);
+        }
+        Ok(OtaStatus {
+            state: OtaState::try_from(value[0])?,
+            offset: u32::from_be_bytes(value[1..5].try_into().unwrap()),
+        })
+    }
+}
+
+/**
+ * # Updater
+ * Storage of the firmware images, implemented by the platforms that can be updated over the network
+ *
+ * The server checks the size, the order of the chunks and the hash of the image, the updater only writes it
+ */
+pub trait Updater {
+    /// Prepares to receive an image of `size` bytes, the image being written if any is dropped
+    fn begin(&mut self, size: u32) -> Result<(), Error>;
+
+    /// Appends the next bytes of the image
+    fn write(&mut self, data: &[u8]) -> Result<(), Error>;
+
+    /// Makes the written image the one booted on the next restart
+    fn commit(&mut self) -> Result<(), Error>;
+
+    /// Drops the image being written
+    fn abort(&mut self);
+}
+
+/// Firmware update in progress on a server
+pub(crate) struct Ota {
+    updater: Option<Box<dyn Updater + Send>>,
+    state: OtaState,
+    size: u32,
+    hash: [u8; 32],
+    hasher: Sha256,
+    received: u32,
+}
+
+impl fmt::Debug for Ota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ota")
+            .field("state", &self.state)
+            .field("size", &self.size)
+            .field("received", &self.received)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Ota {
+    fn default() -> Self {
+        Ota {
+            updater: None,
+            state: OtaState::Unsupported,
+            size: 0,
+            hash: [0; 32],
+            hasher: Sha256::new(),
+            received: 0,
+        }
+    }
+}
+
+impl Ota {
+    pub(crate) fn set_updater(&mut self, updater: Box<dyn Updater + Send>) {
+        if let (OtaState::Receiving, Some(previous)) = (self.state, &mut self.updater) {
+            previous.abort();
+        }
+        self.updater = Some(updater);
+        self.state = OtaState::Idle;
+        self.received = 0;
+    }
+
+    pub(crate) fn status(&self) -> OtaStatus {
+        OtaStatus { state: self.state, offset: self.received }
+    }
+
+    /// Starts receiving an image, or resumes receiving it if it is the one being received
+    pub(crate) fn begin(&mut self, size: u32, hash: [u8; 32]) -> Result<(), Error> {
+        let Some(updater) = &mut self.updater else {
+            return Ok(());
+        };
+        if self.state == OtaState::Receiving && self.size == size && self.hash == hash {
+            return Ok(());
+        }
+        if self.state == OtaState::Receiving {
+            updater.abort();
+        }
+        self.size = size;
+        self.hash = hash;
+        self.hasher = Sha256::new();
+        self.received = 0;
+        self.state = OtaState::Receiving;
+        updater.begin(size).map_err(|error| {
+            self.state = OtaState::Failed;
+            error
+        })
+    }
+
+    /// Writes the chunk if it is the next one, the other chunks are ignored and the status tells where to resume
+    pub(crate) fn chunk(&mut self, offset: u32, data: &[u8]) -> Result<(), Error> {
+        let Some(updater) = &mut self.updater else {
+            return Ok(());
+        };
+        if self.state != OtaState::Receiving || offset != self.received {
+            return Ok(());
+        }
+        if self.received as u64 + data.len() as u64 > self.size as u64 {
+            updater.abort();
+            self.state = OtaState::Failed;
+            return Err(Error::UpdateFailed("the image is longer than announced".to_string()));
+        }
+        if let Err(error) = updater.write(data) {
+            updater.abort();
+            self.state = OtaState::Failed;
+            return Err(error);
+        }
+        self.hasher.update(data);
+        self.received += data.len() as u32;
+        Ok(())
+    }
+
+    /// Checks the whole image was received and matches its hash
+    pub(crate) fn verify(&mut self) -> Result<(), Error> {
+        let Some(updater) = &mut self.updater else {
+            return Ok(());
+        };
+        if self.state != OtaState::Receiving || self.received != self.size {
+            return Ok(());
+        }
+        if <[u8; 32]>::from(self.hasher.clone().finalize()) != self.hash {
+            updater.abort();
+            self.state = OtaState::Failed;
+            return Err(Error::UpdateFailed("the image does not match its hash".to_string()));
+        }
+        self.state = OtaState::Verified;
+        Ok(())
+    }
+
+    /// Makes the verified image the one to boot
+    pub(crate) fn commit(&mut self) -> Result<(), Error> {
+        let Some(updater) = &mut self.updater else {
+            return Ok(());
+        };
+        if self.state != OtaState::Verified {
+            return Ok(());
+        }
+        match updater.commit() {
+            Ok(()) => {
+                self.state = OtaState::Committed;
+                Ok(())
+            },
+            Err(error) => {
+                self.state = OtaState::Failed;
+                Err(error)
+            },
+        }
+    }
+
+    /// Whether the server can restart on a new image
+    pub(crate) fn is_committed(&self) -> bool {
+        self.state == OtaState::Committed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default)]
+    struct Flash {
+        image: Vec<u8>,
+        committed: bool,
+    }
+
+    impl Updater for Arc<Mutex<Flash>> {
+        fn begin(&mut self, _size: u32) -> Result<(), Error> {
+            *self.lock().unwrap() = Flash::default();
+            Ok(())
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+            self.lock().unwrap().image.extend_from_slice(data);
+            Ok(())
+        }
+
+        fn commit(&mut self) -> Result<(), Error> {
+            self.lock().unwrap().committed = true;
+            Ok(())
+        }
+
+        fn abort(&mut self) {
+            self.lock().unwrap().image.clear();
+        }
+    }
+
+    fn ota() -> (Ota, Arc<Mutex<Flash>>) {
+        let flash = Arc::new(Mutex::new(Flash::default()));
+        let mut ota = Ota::default();
+        ota.set_updater(Box::new(flash.clone()));
+        (ota, flash)
+    }
+
+    #[test]
+    fn test_status_encoding() {
+        let status = OtaStatus { state: OtaState::Receiving, offset: 0x0102_0304 };
+        let mut bytes = [0; OtaStatus::ENCODED_LEN];
+        status.encode(&mut bytes);
+        assert_eq!(bytes, [2, 1, 2, 3, 4]);
+        assert_eq!(OtaStatus::decode(&bytes), Ok(status));
+        assert_eq!(OtaStatus::decode(&[6, 0, 0, 0, 0]), Err(Error::InvalidFlag));
+    }
+
+    #[test]
+    fn test_update() {
+        assert_eq!(Ota::default().status().state, OtaState::Unsupported);
+        let image: Vec<u8> = (0..=255).collect();
+        let (mut ota, flash) = ota();
+        ota.begin(256, hash(&image)).unwrap();
+        ota.chunk(0, &image[..100]).unwrap();
+
+        // Repeated and early chunks are ignored
+        ota.chunk(0, &image[..100]).unwrap();
+        ota.chunk(200, &image[200..]).unwrap();
+        assert_eq!(ota.status(), OtaStatus { state: OtaState::Receiving, offset: 100 });
+
+        // Beginning the same image again resumes it
+        ota.begin(256, hash(&image)).unwrap();
+        assert_eq!(ota.status().offset, 100);
+        ota.verify().unwrap();
+        assert_eq!(ota.status().state, OtaState::Receiving);
+        ota.chunk(100, &image[100..]).unwrap();
+        ota.commit().unwrap();
+        assert!(!ota.is_committed());
+        ota.verify().unwrap();
+        ota.commit().unwrap();
+        assert!(ota.is_committed());
+        assert_eq!(ota.status(), OtaStatus { state: OtaState::Committed, offset: 256 });
+        assert_eq!(flash.lock().unwrap().image, image);
+        assert!(flash.lock().unwrap().committed);
+    }
+
+    #[test]
+    fn test_corrupted_update() {
+        let image = [1, 2, 3, 4];
+        let (mut ota, flash) = ota();
+        ota.begin(4, hash(&image)).unwrap();
+        ota.chunk(0, &[1, 2, 3, 5]).unwrap();
+        assert!(matches!(ota.verify(), Err(Error::UpdateFailed(_))));
+        assert_eq!(ota.status().state, OtaState::Failed);
+        assert!(flash.lock().unwrap().image.is_empty());
+
+        // Another image starts over
+        ota.begin(4, hash(&image)).unwrap();
+        assert_eq!(ota.status(), OtaStatus { state: OtaState::Receiving, offset: 0 });
+        assert!(matches!(ota.chunk(0, &[0; 5]), Err(Error::UpdateFailed(_))));
+        assert_eq!(ota.status().state, OtaState::Failed);
+    }
+}
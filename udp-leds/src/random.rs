@@ -0,0 +1,16 @@
+/// Bytes drawn from the random generator of the operating system, suitable for keys and nonces
+pub(crate) fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).expect("The operating system has no random generator");
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_bytes() {
+        assert_ne!(random_bytes::<16>(), random_bytes::<16>());
+    }
+}
@@ -0,0 +1,177 @@
+use crate::{constants::MAX_LED_COUNT, error::Error};
+
+/**
+ * # Segment
+ * Named zone of a strip, such as the top or the left side of a screen, which a client can own
+ * while other clients own the other zones
+ *
+ * The pixels sent to a segment are shown from its first LED on, or from its last LED on when it is reversed
+ *
+ * ## Encoding
+ * The segment table starts with the number of segments, the positions are 16 bits big endian values
+ * and the first bit of the flags tells whether the segment is reversed
+ * [count, (start, len, flags, name_len, name...)...]
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Segment {
+    /// Name of the segment, at most 16 bytes of UTF-8
+    pub name: String,
+    /// First LED of the segment
+    pub start: u16,
+    /// Number of LEDs of the segment
+    pub len: u16,
+    /// Whether the pixels run from the last LED of the segment to the first one
+    pub reversed: bool,
+}
+
+impl Segment {
+    pub const MAX_NAME_LENGTH: usize = 16;
+    /// Number of segments a server holds
+    pub const MAX_COUNT: usize = 16;
+    const HEADER_LEN: usize = 6;
+    const REVERSED: u8 = 0b0000_0001;
+
+    /// Creates a new segment running from its first LED to its last one
+    pub fn new(name: impl Into<String>, start: u16, len: u16) -> Self {
+        Segment { name: name.into(), start, len, reversed: false }
+    }
+
+    /// The LED showing the pixel `index` of the segment
+    pub fn led(&self, index: usize) -> Option<usize> {
+        if index >= self.len as usize {
+            return None;
+        }
+        match self.reversed {
+            false => Some(self.start as usize + index),
+            true => Some(self.start as usize + self.len as usize - 1 - index),
+        }
+    }
+
+    /// Checks the segments fit on a strip of `led_count` LEDs and can be told apart by their name
+    ///
+    /// Segments may overlap, the last frame sent to either of them is shown on the shared LEDs
+    pub fn validate_table(segments: &[Segment], led_count: usize) -> Result<(), Error> {
+        if segments.len() > Self::MAX_COUNT {
+            return Err(Error::InvalidSegment);
+        }
+        for (index, segment) in segments.iter().enumerate() {
+            if segment.name.is_empty() || segment.name.len() > Self::MAX_NAME_LENGTH {
+                return Err(Error::InvalidSegment);
+            }
+            if segment.len == 0 || segment.start as usize + segment.len as usize > led_count.min(MAX_LED_COUNT) {
+                return Err(Error::InvalidSegment);
+            }
+            if segments[..index].iter().any(|other| other.name == segment.name) {
+                return Err(Error::InvalidSegment);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn table_len(segments: &[Segment]) -> usize {
+        1 + segments.iter().map(|segment| Self::HEADER_LEN + segment.encoded_name().len()).sum::<usize>()
+    }
+
+    pub(crate) fn encode_table(segments: &[Segment], out: &mut [u8]) {
+        out[0] = segments.len() as u8;
+        let mut at = 1;
+        for segment in segments {
+            let name = segment.encoded_name();
+            out[at..at + 2].copy_from_slice(&segment.start.to_be_bytes());
+            out[at + 2..at + 4].copy_from_slice(&segment.len.to_be_bytes());
+            out[at + 4] = if segment.reversed { Self::REVERSED } else { 0 };
+            out[at + 5] = name.len() as u8;
+            out[at + Self::HEADER_LEN..at + Self::HEADER_LEN + name.len()].copy_from_slice(name);
+            at += Self::HEADER_LEN + name.len();
+        }
+    }
+
+    pub(crate) fn decode_table(value: &[u8]) -> Result<Vec<Segment>, Error> {
+        let (&count, mut rest) = value.split_first().ok_or(Error::InvalidMessageLength)?;
+        if count as usize > Self::MAX_COUNT {
+            return Err(Error::InvalidSegment);
+        }
+        let mut segments = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            if rest.len() < Self::HEADER_LEN || rest.len() < Self::HEADER_LEN + rest[5] as usize {
+                return Err(Error::InvalidMessageLength);
+            }
+            let name_len = rest[5] as usize;
+            segments.push(Segment {
+                name: String::from_utf8_lossy(&rest[Self::HEADER_LEN..Self::HEADER_LEN + name_len]).into_owned(),
+                start: u16::from_be_bytes([rest[0], rest[1]]),
+                len: u16::from_be_bytes([rest[2], rest[3]]),
+                reversed: rest[4] & Self::REVERSED != 0,
+            });
+            rest = &rest[Self::HEADER_LEN + name_len..];
+        }
+        if !rest.is_empty() {
+            return Err(Error::InvalidMessageLength);
+        }
+        Ok(segments)
+    }
+
+    /// Encodes a segment table on its own, as stored by the controllers
+    pub fn table_to_bytes(segments: &[Segment]) -> Vec<u8> {
+        let mut bytes = vec![0; Self::table_len(segments)];
+        Self::encode_table(segments, &mut bytes);
+        bytes
+    }
+
+    /// Decodes a segment table stored by `table_to_bytes`
+    pub fn table_from_bytes(bytes: &[u8]) -> Result<Vec<Segment>, Error> {
+        Self::decode_table(bytes)
+    }
+
+    fn encoded_name(&self) -> &[u8] {
+        let mut len = self.name.len().min(Self::MAX_NAME_LENGTH);
+        while !self.name.is_char_boundary(len) {
+            len -= 1;
+        }
+        &self.name.as_bytes()[..len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Vec<Segment> {
+        vec![Segment::new("top", 0, 10), Segment { reversed: true, ..Segment::new("left", 10, 4) }]
+    }
+
+    #[test]
+    fn test_led() {
+        let [top, left] = <[Segment; 2]>::try_from(table()).unwrap();
+        assert_eq!(top.led(0), Some(0));
+        assert_eq!(top.led(9), Some(9));
+        assert_eq!(top.led(10), None);
+        assert_eq!(left.led(0), Some(13));
+        assert_eq!(left.led(3), Some(10));
+    }
+
+    #[test]
+    fn test_encoding() {
+        let bytes = Segment::table_to_bytes(&table());
+        assert_eq!(bytes[..10], [2, 0, 0, 0, 10, 0, 3, b't', b'o', b'p']);
+        assert_eq!(bytes[10..16], [0, 10, 0, 4, 1, 4]);
+        assert_eq!(Segment::table_from_bytes(&bytes), Ok(table()));
+        assert_eq!(Segment::table_from_bytes(&bytes[..bytes.len() - 1]), Err(Error::InvalidMessageLength));
+        assert_eq!(Segment::table_from_bytes(&[0]), Ok(Vec::new()));
+        assert_eq!(Segment::table_from_bytes(&[]), Err(Error::InvalidMessageLength));
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(Segment::validate_table(&table(), 14), Ok(()));
+        assert_eq!(Segment::validate_table(&table(), 13), Err(Error::InvalidSegment));
+        let mut duplicated = table();
+        duplicated[1].name = "top".to_string();
+        assert_eq!(Segment::validate_table(&duplicated, 14), Err(Error::InvalidSegment));
+        assert_eq!(Segment::validate_table(&[Segment::new("empty", 0, 0)], 14), Err(Error::InvalidSegment));
+        assert_eq!(Segment::validate_table(&[Segment::new("", 0, 1)], 14), Err(Error::InvalidSegment));
+        let many: Vec<Segment> = (0..17).map(|index| Segment::new(index.to_string(), index, 1)).collect();
+        assert_eq!(Segment::validate_table(&many, 100), Err(Error::InvalidSegment));
+    }
+}
@@ -0,0 +1,317 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use crate::{color::Rgb, constants::MAX_LED_COUNT, error::Error, frame::Frame, varint};
+
+/**
+ * # Sequence files
+ * Animations authored offline and played back on a strip
+ *
+ * ## File format
+ * The file starts with a header:
+ * [b'U', b'L', b'S', b'Q', version, led_count_hi, led_count_lo, fps, pixel_format]
+ * The pixel format tells in which order the color components are stored (0: RGB, 1: GRB, 2: BGR)
+ *
+ * Then comes one block per frame:
+ * - the length of the block (LEB128 varint)
+ * - the frame XORed with the previous one (the first frame is XORed with a black frame) and run-length encoded
+ *   as a list of (number of unchanged bytes, number of changed bytes, changed bytes) with varint counts
+ *
+ * Only the bytes changing from a frame to the next one are stored, so static parts of an animation cost nothing
+ */
+const MAGIC: &[u8; 4] = b"ULSQ";
+const VERSION: u8 = 1;
+
+/// Order of the color components of the stored pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PixelFormat {
+    #[default]
+    Rgb,
+    Grb,
+    Bgr,
+}
+
+impl PixelFormat {
+    /// Position of the red, green and blue components in a stored pixel
+    fn order(self) -> [usize; 3] {
+        match self {
+            PixelFormat::Rgb => [0, 1, 2],
+            PixelFormat::Grb => [1, 0, 2],
+            PixelFormat::Bgr => [2, 1, 0],
+        }
+    }
+}
+
+impl TryFrom<u8> for PixelFormat {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PixelFormat::Rgb),
+            1 => Ok(PixelFormat::Grb),
+            2 => Ok(PixelFormat::Bgr),
+            _ => Err(Error::InvalidSequence),
+        }
+    }
+}
+
+impl From<PixelFormat> for u8 {
+    fn from(value: PixelFormat) -> Self {
+        match value {
+            PixelFormat::Rgb => 0,
+            PixelFormat::Grb => 1,
+            PixelFormat::Bgr => 2,
+        }
+    }
+}
+
+/// Header of a sequence file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SequenceHeader {
+    pub led_count: u16,
+    pub fps: u8,
+    pub format: PixelFormat,
+}
+
+impl SequenceHeader {
+    /// Time each frame stays on the strip
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs(1) / self.fps.max(1) as u32
+    }
+}
+
+/// Writes the frames of an animation to a sequence file
+#[derive(Debug)]
+pub struct SequenceWriter<W: Write> {
+    inner: W,
+    header: SequenceHeader,
+    previous: Vec<u8>,
+}
+
+impl<W: Write> SequenceWriter<W> {
+    /// Writes the header of the sequence
+    pub fn new(mut inner: W, header: SequenceHeader) -> Result<Self, Error> {
+        if header.led_count as usize > MAX_LED_COUNT || header.fps == 0 {
+            return Err(Error::InvalidSequence);
+        }
+        inner.write_all(MAGIC)?;
+        inner.write_all(&[VERSION])?;
+        inner.write_all(&header.led_count.to_be_bytes())?;
+        inner.write_all(&[header.fps, header.format.into()])?;
+        Ok(SequenceWriter {
+            inner,
+            header,
+            previous: vec![0; header.led_count as usize * 3],
+        })
+    }
+
+    pub fn header(&self) -> &SequenceHeader {
+        &self.header
+    }
+
+    /// Appends a frame, the frame is cut or padded with black pixels to the LED count of the sequence
+    pub fn write_frame(&mut self, frame: &Frame) -> Result<(), Error> {
+        let order = self.header.format.order();
+        let mut current = vec![0; self.previous.len()];
+        for (index, stored) in current.chunks_exact_mut(3).enumerate().take(frame.len()) {
+            let Rgb { r, g, b } = frame.get(index);
+            for (component, value) in order.iter().zip([r, g, b]) {
+                stored[*component] = value;
+            }
+        }
+
+        let mut block = Vec::new();
+        let mut pos = 0;
+        while pos < current.len() {
+            let unchanged = current[pos..].iter().zip(&self.previous[pos..]).take_while(|(a, b)| a == b).count();
+            let start = pos + unchanged;
+            let changed = current[start..].iter().zip(&self.previous[start..]).take_while(|(a, b)| a != b).count();
+            varint::write(&mut block, unchanged as u64);
+            varint::write(&mut block, changed as u64);
+            block.extend(current[start..start + changed].iter().zip(&self.previous[start..]).map(|(a, b)| a ^ b));
+            pos = start + changed;
+        }
+
+        let mut length = Vec::new();
+        varint::write(&mut length, block.len() as u64);
+        self.inner.write_all(&length)?;
+        self.inner.write_all(&block)?;
+        self.previous = current;
+        Ok(())
+    }
+
+    /// Flushes the frames and returns the underlying writer
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Reads the frames of a sequence file
+#[derive(Debug)]
+pub struct SequenceReader<R: Read> {
+    inner: R,
+    header: SequenceHeader,
+    previous: Vec<u8>,
+}
+
+impl<R: Read> SequenceReader<R> {
+    /// Reads the header of the sequence
+    pub fn new(mut inner: R) -> Result<Self, Error> {
+        let mut header = [0; 9];
+        inner.read_exact(&mut header).map_err(|_| Error::InvalidSequence)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(Error::InvalidSequence);
+        }
+        let header = SequenceHeader {
+            led_count: u16::from_be_bytes([header[5], header[6]]),
+            fps: header[7],
+            format: PixelFormat::try_from(header[8])?,
+        };
+        if header.led_count as usize > MAX_LED_COUNT || header.fps == 0 {
+            return Err(Error::InvalidSequence);
+        }
+        Ok(SequenceReader {
+            inner,
+            previous: vec![0; header.led_count as usize * 3],
+            header,
+        })
+    }
+
+    pub fn header(&self) -> &SequenceHeader {
+        &self.header
+    }
+
+    /// Reads the next frame, `None` at the end of the sequence
+    pub fn read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        let mut first = [0; 1];
+        if self.inner.read(&mut first)? == 0 {
+            return Ok(None);
+        }
+        let len = varint::read(&mut self.inner, Some(first[0])).ok_or(Error::InvalidSequence)?;
+        // Each run covers at least a byte of the frame and stores it with two counts of at most 2 bytes
+        if len > self.previous.len() as u64 * 5 {
+            return Err(Error::InvalidSequence);
+        }
+        let mut block = vec![0; len as usize];
+        self.inner.read_exact(&mut block).map_err(|_| Error::InvalidSequence)?;
+
+        let mut block = &block[..];
+        let mut pos: usize = 0;
+        while !block.is_empty() {
+            let unchanged = varint::read(&mut block, None).ok_or(Error::InvalidSequence)? as usize;
+            let changed = varint::read(&mut block, None).ok_or(Error::InvalidSequence)? as usize;
+            let start = pos.checked_add(unchanged).ok_or(Error::InvalidSequence)?;
+            let end = start.checked_add(changed).ok_or(Error::InvalidSequence)?;
+            if end > self.previous.len() || changed > block.len() {
+                return Err(Error::InvalidSequence);
+            }
+            for (stored, delta) in self.previous[start..end].iter_mut().zip(&block[..changed]) {
+                *stored ^= delta;
+            }
+            block = &block[changed..];
+            pos = end;
+        }
+
+        let order = self.header.format.order();
+        let mut frame = Frame::new(self.header.led_count as usize);
+        for (index, stored) in self.previous.chunks_exact(3).enumerate() {
+            frame.set(index, (stored[order[0]], stored[order[1]], stored[order[2]]));
+        }
+        Ok(Some(frame))
+    }
+}
+
+impl<R: Read> Iterator for SequenceReader<R> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames() -> Vec<Frame> {
+        (0..10)
+            .map(|step| {
+                let mut frame = Frame::new(30);
+                frame.fill((10, 20, 30));
+                frame.set(step, (255, 128, 0));
+                frame
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for format in [PixelFormat::Rgb, PixelFormat::Grb, PixelFormat::Bgr] {
+            let header = SequenceHeader { led_count: 30, fps: 25, format };
+            let mut writer = SequenceWriter::new(Vec::new(), header).unwrap();
+            for frame in frames() {
+                writer.write_frame(&frame).unwrap();
+            }
+            let bytes = writer.finish().unwrap();
+
+            let reader = SequenceReader::new(&bytes[..]).unwrap();
+            assert_eq!(*reader.header(), header);
+            let read: Vec<Frame> = reader.map(Result::unwrap).collect();
+            assert_eq!(read, frames());
+        }
+    }
+
+    #[test]
+    fn test_frames_are_compressed() {
+        let header = SequenceHeader { led_count: 30, fps: 25, format: PixelFormat::Rgb };
+        let mut writer = SequenceWriter::new(Vec::new(), header).unwrap();
+        for frame in frames() {
+            writer.write_frame(&frame).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        // The first frame is stored as a whole, the next ones only store the two moving pixels
+        assert!(bytes.len() < 9 + 95 + 9 * 12, "{} bytes", bytes.len());
+    }
+
+    #[test]
+    fn test_frame_duration() {
+        let header = SequenceHeader { led_count: 1, fps: 40, format: PixelFormat::Rgb };
+        assert_eq!(header.frame_duration(), Duration::from_millis(25));
+    }
+
+    #[test]
+    fn test_invalid_sequence() {
+        assert_eq!(SequenceReader::new(&b"ULSQ\x01\x00"[..]).err(), Some(Error::InvalidSequence));
+        assert_eq!(SequenceReader::new(&b"ULSQ\x01\x00\x01\x19\x07"[..]).err(), Some(Error::InvalidSequence));
+        let mut reader = SequenceReader::new(&b"ULSQ\x01\x00\x01\x19\x00\x03\x00\x04\x01"[..]).unwrap();
+        assert_eq!(reader.read_frame(), Err(Error::InvalidSequence));
+
+        // Blocks longer than any frame and counts overflowing are refused
+        let mut reader = SequenceReader::new(&b"ULSQ\x01\x00\x01\x19\x00\xff\xff\xff\xff\x0f"[..]).unwrap();
+        assert_eq!(reader.read_frame(), Err(Error::InvalidSequence));
+        let mut file = b"ULSQ\x01\x00\x04\x19\x00\x14".to_vec();
+        for _ in 0..2 {
+            // 2^63 unchanged then 2^63 changed bytes
+            file.extend([0x80; 9]);
+            file.push(0x01);
+        }
+        let mut reader = SequenceReader::new(&file[..]).unwrap();
+        assert_eq!(reader.read_frame(), Err(Error::InvalidSequence));
+    }
+
+    #[test]
+    fn test_scattered_changes() {
+        // Every other byte changing makes the longest blocks
+        let header = SequenceHeader { led_count: MAX_LED_COUNT as u16, fps: 25, format: PixelFormat::Rgb };
+        let mut frame = Frame::new(MAX_LED_COUNT);
+        for index in 0..MAX_LED_COUNT {
+            frame.set(index, if index % 2 == 0 { (1, 0, 1) } else { (0, 1, 0) });
+        }
+        let mut writer = SequenceWriter::new(Vec::new(), header).unwrap();
+        writer.write_frame(&frame).unwrap();
+        let bytes = writer.finish().unwrap();
+        let mut reader = SequenceReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.read_frame(), Ok(Some(frame)));
+    }
+}
@@ -1,3 +1,9 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::{client::ClientMessages, color::Rgb, config::Config, device::{ClientToken, DeviceId}, effects::{Breathe, Effect, Rainbow}, frame::Frame, ota::{Ota, OtaStatus, Updater}, segment::Segment, layout::Layout};
+
 /**
  * # Server Messages
  * Defines the messages that the server can send to the client.
@@ -10,17 +16,195 @@
  * 
  * ## Hello
  * The server sends a hello message to the client to confirm that it is the server
- * [SERVER_FLAG, 0b1100_0000]
+ * The message advertises the number of LEDs (16 bits, big endian) and the maximum frame rate of the server
+ * followed by a stable unique id (usually the MAC address) and the name of the server (at most 32 bytes of UTF-8)
+ * A zero means that the value is unknown, older servers only send the first two or five bytes
+ * [SERVER_FLAG, 0b1100_0000, led_count_hi, led_count_lo, max_fps, id1, ..., id6, name_len, name...]
+ *
+ * ## Welcome
+ * The server answers a claim with its hello followed by the device id assigned to the client
+ * [SERVER_FLAG, 0b1100_0000, led_count_hi, led_count_lo, max_fps, id1, ..., id6, name_len, name..., device]
+ *
+ * ## Status
+ * The server answers a status request with its health, the counters and the uptime are 32 bits big endian values,
+ * the frame rate is sent in hundredths of frames per second (16 bits) and 0xff stands for no active device
+ * [SERVER_FLAG, 0b1100_0011, uptime_s, received, applied, dropped, fps, active, free_heap, rssi, error_len, error...]
+ *
+ * ## TimeResponse
+ * The server answers a time request with the time of the request (t1), the time of its clock when it received it (t2)
+ * and when it answered (t3), in microseconds
+ * [SERVER_FLAG, 0b1100_0100, t1, t2, t3 (64 bits big endian)]
+ *
+ * ## Config
+ * The server answers the config messages with its config
+ * [SERVER_FLAG, 0b1100_0110, config...]
+ *
+ * ## OtaStatus
+ * The server answers the firmware update messages with the state of the update and the number of bytes
+ * of the image received so far (32 bits big endian), 0 for a server that cannot be updated
+ * [SERVER_FLAG, 0b1100_1000, state, offset]
+ *
+ * ## Segments
+ * The server answers the segment table messages with its segment table
+ * [SERVER_FLAG, 0b1100_1101, segments...]
+ *
+ * ## Layout
+ * The server answers the layout messages with the layout of its LEDs
+ * [SERVER_FLAG, 0b1101_0001, layout...]
  */
 #[derive(Debug , PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ServerMessages {
-    Hello
+    Hello(Advertisement),
+    Welcome(Advertisement, DeviceId),
+    Status(Status),
+    TimeResponse(u64, u64, u64),
+    Config(Config),
+    OtaStatus(OtaStatus),
+    Segments(Vec<Segment>),
+    Layout(Layout),
+}
+
+/// What a server advertises about itself in its hello message
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Advertisement {
+    /// Number of LEDs driven by the server, 0 if unknown
+    pub led_count: u16,
+    /// Maximum number of frames per second the server can display, 0 if unlimited
+    pub max_fps: u8,
+    /// Stable unique id of the server, all zeros if unknown
+    pub id: [u8; 6],
+    /// Human readable name of the server, empty if unknown
+    pub name: String,
+}
+
+impl Advertisement {
+    /// Maximum length of the name in bytes
+    pub const MAX_NAME_LENGTH: usize = 32;
+
+    /// The unique id formatted as a MAC address
+    pub fn id_string(&self) -> String {
+        self.id.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<_>>().join(":")
+    }
+
+    /// Checks if the server is the one designated by the given name or id
+    pub fn matches(&self, name_or_id: &str) -> bool {
+        (!self.name.is_empty() && self.name.eq_ignore_ascii_case(name_or_id))
+            || (self.id != [0; 6] && self.id_string().eq_ignore_ascii_case(name_or_id))
+    }
+
+    fn encoded_name(&self) -> &[u8] {
+        let mut len = self.name.len().min(Self::MAX_NAME_LENGTH);
+        while !self.name.is_char_boundary(len) {
+            len -= 1;
+        }
+        &self.name.as_bytes()[..len]
+    }
+}
+
+/// Health of a server, as reported in its status messages
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Status {
+    /// Time since the server started, to the second
+    pub uptime: Duration,
+    /// Number of frames received from the clients
+    pub frames_received: u32,
+    /// Number of frames shown on the LEDs
+    pub frames_applied: u32,
+    /// Number of frames never shown: frames from a device other than the active one, segment pixels from a
+    /// device not owning the segment, and timed frames that were late, skipped, cancelled or over the buffer
+    pub frames_dropped: u32,
+    /// Frames shown per second over the last second
+    pub fps: f32,
+    /// The device currently allowed to update the LEDs
+    pub active: Option<DeviceId>,
+    /// Free memory in bytes, 0 if unknown
+    pub free_heap: u32,
+    /// Strength of the Wi-Fi signal in dBm, 0 if unknown
+    pub rssi: i8,
+    /// Last error met by the server, empty if none
+    pub last_error: String,
+}
+
+impl Status {
+    /// Maximum length of the error in bytes
+    pub const MAX_ERROR_LENGTH: usize = 64;
+    const ENCODED_LEN: usize = 25;
+
+    fn encoded_error(&self) -> &[u8] {
+        let mut len = self.last_error.len().min(Self::MAX_ERROR_LENGTH);
+        while !self.last_error.is_char_boundary(len) {
+            len -= 1;
+        }
+        &self.last_error.as_bytes()[..len]
+    }
+
+    fn decode(value: &[u8]) -> Result<Self, crate::error::Error> {
+        if value.len() < Self::ENCODED_LEN || value.len() != Self::ENCODED_LEN + value[Self::ENCODED_LEN - 1] as usize {
+            return Err(crate::error::Error::InvalidMessageLength);
+        }
+        let u32_at = |index: usize| u32::from_be_bytes(value[index..index + 4].try_into().unwrap());
+        Ok(Status {
+            uptime: Duration::from_secs(u32_at(0) as u64),
+            frames_received: u32_at(4),
+            frames_applied: u32_at(8),
+            frames_dropped: u32_at(12),
+            fps: u16::from_be_bytes([value[16], value[17]]) as f32 / 100.0,
+            active: match value[18] {
+                0xff => None,
+                device => Some(DeviceId::new(device)?),
+            },
+            free_heap: u32_at(19),
+            rssi: value[23] as i8,
+            last_error: String::from_utf8_lossy(&value[Self::ENCODED_LEN..]).into_owned(),
+        })
+    }
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0..4].copy_from_slice(&(self.uptime.as_secs().min(u32::MAX as u64) as u32).to_be_bytes());
+        out[4..8].copy_from_slice(&self.frames_received.to_be_bytes());
+        out[8..12].copy_from_slice(&self.frames_applied.to_be_bytes());
+        out[12..16].copy_from_slice(&self.frames_dropped.to_be_bytes());
+        out[16..18].copy_from_slice(&((self.fps * 100.0).round() as u16).to_be_bytes());
+        out[18] = self.active.map_or(0xff, DeviceId::get);
+        out[19..23].copy_from_slice(&self.free_heap.to_be_bytes());
+        out[23] = self.rssi as u8;
+        let error = self.encoded_error();
+        out[24] = error.len() as u8;
+        out[Self::ENCODED_LEN..Self::ENCODED_LEN + error.len()].copy_from_slice(error);
+    }
 }
 
 impl ServerMessages {
     /// Creates a new hello message
     pub fn hello() -> Self {
-        ServerMessages::Hello
+        ServerMessages::Hello(Advertisement::default())
+    }
+
+    /// Number of meaningful bytes in the encoded message
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            ServerMessages::Hello(advertisement) => 12 + advertisement.encoded_name().len(),
+            ServerMessages::Welcome(advertisement, _) => 13 + advertisement.encoded_name().len(),
+            ServerMessages::Status(status) => 2 + Status::ENCODED_LEN + status.encoded_error().len(),
+            ServerMessages::TimeResponse(_, _, _) => 26,
+            ServerMessages::Config(config) => 2 + config.encoded_len(),
+            ServerMessages::OtaStatus(_) => 2 + OtaStatus::ENCODED_LEN,
+            ServerMessages::Segments(segments) => 2 + Segment::table_len(segments),
+            ServerMessages::Layout(layout) => 2 + layout.encoded_len(),
+        }
+    }
+
+    /// What the server advertises about itself, for the messages carrying its advertisement
+    pub fn advertisement(&self) -> Option<&Advertisement> {
+        match self {
+            ServerMessages::Hello(advertisement) | ServerMessages::Welcome(advertisement, _) => Some(advertisement),
+            ServerMessages::Status(_) | ServerMessages::TimeResponse(_, _, _) | ServerMessages::Config(_) | ServerMessages::OtaStatus(_)
+            | ServerMessages::Segments(_)
+            | ServerMessages::Layout(_) => None,
+        }
     }
 }
 
@@ -28,30 +212,1012 @@ impl TryFrom<&[u8]> for ServerMessages {
     type Error = crate::error::Error;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        if value.len() <= 2 {
+        if value.len() < 2 {
             return Err(crate::error::Error::InvalidMessageLength);
         }
         if value[0] != crate::constants::SERVER_FLAG {
             return Err(crate::error::Error::InvalidFlag);
         }
 
-        match value[1] & crate::constants::INSTRUCTION_MASK {
-            crate::constants::INSTRUCTION_HELLO => Ok(ServerMessages::Hello),
+        if value[1] & crate::constants::INSTRUCTION_MASK != crate::constants::INSTRUCTION_HELLO {
+            return Err(crate::error::Error::InvalidFlag);
+        }
+        match value[1] & crate::constants::OPCODE_MASK {
+            crate::constants::OPCODE_HELLO => match value.len() {
+                2 => Ok(ServerMessages::Hello(Advertisement::default())),
+                3 | 4 | 6..=11 => Err(crate::error::Error::InvalidMessageLength),
+                5 => Ok(ServerMessages::Hello(Advertisement {
+                    led_count: u16::from_be_bytes([value[2], value[3]]),
+                    max_fps: value[4],
+                    ..Default::default()
+                })),
+                _ => {
+                    let name_len = value[11] as usize;
+                    if name_len > Advertisement::MAX_NAME_LENGTH || value.len() < 12 + name_len {
+                        return Err(crate::error::Error::InvalidMessageLength);
+                    }
+                    let advertisement = Advertisement {
+                        led_count: u16::from_be_bytes([value[2], value[3]]),
+                        max_fps: value[4],
+                        id: value[5..11].try_into().unwrap(),
+                        name: String::from_utf8_lossy(&value[12..12 + name_len]).into_owned(),
+                    };
+                    // Padded datagrams are read as a plain hello
+                    if value.len() == 13 + name_len {
+                        return Ok(ServerMessages::Welcome(advertisement, DeviceId::new(value[12 + name_len])?));
+                    }
+                    Ok(ServerMessages::Hello(advertisement))
+                },
+            },
+            crate::constants::OPCODE_STATUS => Ok(ServerMessages::Status(Status::decode(&value[2..])?)),
+            crate::constants::OPCODE_TIME => {
+                if value.len() != 26 {
+                    return Err(crate::error::Error::InvalidMessageLength);
+                }
+                let u64_at = |index: usize| u64::from_be_bytes(value[index..index + 8].try_into().unwrap());
+                Ok(ServerMessages::TimeResponse(u64_at(2), u64_at(10), u64_at(18)))
+            },
+            crate::constants::OPCODE_CONFIG => Ok(ServerMessages::Config(Config::decode(&value[2..])?)),
+            crate::constants::OPCODE_OTA_BEGIN => Ok(ServerMessages::OtaStatus(OtaStatus::decode(&value[2..])?)),
+            crate::constants::OPCODE_SEGMENTS => Ok(ServerMessages::Segments(Segment::decode_table(&value[2..])?)),
+            crate::constants::OPCODE_LAYOUT => Ok(ServerMessages::Layout(Layout::decode(&value[2..])?)),
             _ => Err(crate::error::Error::InvalidFlag)
         }
     }
 }
 
-impl Into<[u8; crate::constants::MAX_MESSAGE_LENGTH]> for ServerMessages  {
-    fn into(self) -> [u8; crate::constants::MAX_MESSAGE_LENGTH] {
-        match self {
-            ServerMessages::Hello => {
-                let mut message = [0; crate::constants::MAX_MESSAGE_LENGTH];
-                message[0] = crate::constants::SERVER_FLAG;
-                message[1] = crate::constants::INSTRUCTION_HELLO;
-                message
+impl From<ServerMessages> for [u8; crate::constants::MAX_MESSAGE_LENGTH] {
+    fn from(value: ServerMessages) -> Self {
+        let mut message = [0; crate::constants::MAX_MESSAGE_LENGTH];
+        message[0] = crate::constants::SERVER_FLAG;
+        let (advertisement, device) = match value {
+            ServerMessages::Hello(advertisement) => (advertisement, None),
+            ServerMessages::Welcome(advertisement, device) => (advertisement, Some(device)),
+            ServerMessages::Status(status) => {
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_STATUS;
+                status.encode(&mut message[2..]);
+                return message;
+            },
+            ServerMessages::TimeResponse(t1, t2, t3) => {
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_TIME;
+                message[2..10].copy_from_slice(&t1.to_be_bytes());
+                message[10..18].copy_from_slice(&t2.to_be_bytes());
+                message[18..26].copy_from_slice(&t3.to_be_bytes());
+                return message;
+            },
+            ServerMessages::Config(config) => {
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_CONFIG;
+                config.encode(&mut message[2..]);
+                return message;
+            },
+            ServerMessages::OtaStatus(status) => {
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_OTA_BEGIN;
+                status.encode(&mut message[2..]);
+                return message;
+            },
+            ServerMessages::Segments(segments) => {
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_SEGMENTS;
+                Segment::encode_table(&segments, &mut message[2..]);
+                return message;
+            },
+            ServerMessages::Layout(layout) => {
+                message[1] = crate::constants::INSTRUCTION_HELLO | crate::constants::OPCODE_LAYOUT;
+                layout.encode(&mut message[2..]);
+                return message;
+            },
+        };
+        message[1] = crate::constants::INSTRUCTION_HELLO;
+        message[2..4].copy_from_slice(&advertisement.led_count.to_be_bytes());
+        message[4] = advertisement.max_fps;
+        message[5..11].copy_from_slice(&advertisement.id);
+        let name = advertisement.encoded_name();
+        message[11] = name.len() as u8;
+        message[12..12 + name.len()].copy_from_slice(name);
+        if let Some(device) = device {
+            message[12 + name.len()] = device.get();
+        }
+        message
+    }
+}
+
+/**
+ * # LED sink
+ * Output of the server, implemented by whatever drives the LEDs (RMT driver, emulator, ...)
+ */
+pub trait LedSink {
+    /// Replaces the pixels of the strip, the pixels are given as 24bits RGB values
+    fn set_pixels(&mut self, pixels: &[u8]);
+
+    /// Updates a single pixel of the strip
+    fn set_pixel(&mut self, index: usize, color: Rgb);
+
+    /// Applies a new config, the output pin is left to the platform as it usually needs a restart
+    fn configure(&mut self, _config: &Config) {}
+
+    /// Number of LEDs the sink can drive, the configs with more LEDs are refused
+    fn max_led_count(&self) -> usize {
+        crate::constants::MAX_LED_COUNT
+    }
+}
+
+impl LedSink for Frame {
+    fn set_pixels(&mut self, pixels: &[u8]) {
+        for (pixel, bytes) in self.iter_mut().zip(pixels.chunks_exact(3)) {
+            *pixel = Rgb::new(bytes[0], bytes[1], bytes[2]);
+        }
+    }
+
+    fn set_pixel(&mut self, index: usize, color: Rgb) {
+        if index < self.len() {
+            self.set(index, color);
+        }
+    }
+
+    fn configure(&mut self, config: &Config) {
+        self.resize(config.led_count as usize);
+    }
+}
+
+/// What the strip shows once the active device went silent
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IdleMode {
+    /// Turns the LEDs off
+    #[default]
+    Off,
+    /// Shows a single color
+    Solid(Rgb),
+    /// Slowly pulses a color
+    Breathe(Rgb),
+    /// Scrolls a rainbow along the strip
+    Rainbow,
+}
+
+impl IdleMode {
+    /// Renders the idle state `t` after it started
+    fn render(&self, t: Duration, frame: &mut Frame) {
+        match *self {
+            IdleMode::Off => frame.fill(Rgb::BLACK),
+            IdleMode::Solid(color) => frame.fill(color),
+            IdleMode::Breathe(color) => Breathe { color, ..Default::default() }.render(t, frame),
+            IdleMode::Rainbow => Rainbow::default().render(t, frame),
+        }
+    }
+
+    /// Whether the idle state changes over time
+    fn is_animated(&self) -> bool {
+        matches!(self, IdleMode::Breathe(..) | IdleMode::Rainbow)
+    }
+}
+
+/// Device id assigned to a client
+#[derive(Debug)]
+struct Owner {
+    device: DeviceId,
+    token: ClientToken,
+    addr: SocketAddr,
+    claimed: Instant,
+}
+
+/// Frames shown per second, counted over windows of a second
+#[derive(Debug, Default)]
+struct FrameRate {
+    since: Option<Instant>,
+    count: u32,
+    fps: f32,
+}
+
+impl FrameRate {
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    fn record(&mut self, now: Instant) {
+        let since = *self.since.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(since);
+        if elapsed >= Self::WINDOW {
+            self.fps = self.count as f32 / elapsed.as_secs_f32();
+            self.since = Some(now);
+            self.count = 0;
+        }
+        self.count += 1;
+    }
+
+    fn get(&self, now: Instant) -> f32 {
+        let Some(since) = self.since else {
+            return 0.0;
+        };
+        // A window without any frame after it brings the rate down
+        let elapsed = now.saturating_duration_since(since);
+        if elapsed >= Self::WINDOW {
+            self.count as f32 / elapsed.as_secs_f32()
+        } else {
+            self.fps
+        }
+    }
+}
+
+/// Fade from the last frame of the device that went silent to the idle state
+#[derive(Debug)]
+struct Idle {
+    since: Instant,
+    from: Frame,
+    settled: bool,
+}
+
+/**
+ * # Server
+ * Portable state machine of the LED server
+ *
+ * The server is fed the raw datagrams received on the socket, it returns the response to send back
+ * to the sender if there is one and forwards the pixel updates of the active device to its `LedSink`
+ * It does not do any IO itself so it can be shared by the firmware, the emulators and the bridges
+ *
+ * ## Receiver timeout
+ * With a timeout set, the active device is released when nothing was heard from it for that long
+ * and the strip fades to its idle mode, `tick` has to be called regularly for the fade to happen
+ * The `_at` variants take the current time so the timer can be driven by a fake clock
+ *
+ * ## Device ids
+ * The server hands out the free device ids to the clients claiming one, a client claiming again with the same token
+ * gets its id back and moves it to its new address. Once every id is taken, the least recently claimed one is reused
+ * Messages using an assigned id are ignored unless they come from the address owning it, the ids nobody claimed
+ * stay open to the clients picking their own id
+ *
+ * ## Status
+ * The server counts the frames it receives and answers the status requests, the figures only the platform knows
+ * (free memory, Wi-Fi signal) are given with `set_health` and its own errors with `report_error`
+ *
+ * ## Synchronized playout
+ * The server clock counts the microseconds since the server started, the clients estimate its offset with time requests
+ * The timed frames wait in a small buffer until the clock reaches their presentation timestamp, `tick` shows them
+ * so it has to be called at least by `next_frame_in`. The frames coming too late or not fitting in the buffer are dropped
+ *
+ * ## Config
+ * The client of the active device can change the LED count, the color order, the output pin and the name of the server
+ * The server applies the config to itself and to its sink right away, the platform stores it after `take_config`
+ * and restarts when the output pin changed
+ *
+ * ## Segments
+ * The strip can be split in named segments, each one owned by its own device so several clients or effects
+ * share the strip. The frames of the active device and the idle mode leave the owned segments alone
+ * The client of the active device can replace the table once no other device is sending to a segment
+ * A segment stays owned while its device keeps sending, another device can only take it once the owner was silent
+ * for the receiver timeout. Without a timeout it stays owned until the table is replaced. `tick` releases the silent
+ * owners and the zone goes back to the active device or the idle mode. The platform stores the table
+ * after `take_segments`
+ *
+ * ## Layout
+ * The server keeps the layout of its LEDs around the screen for the clients sampling the screen,
+ * the client of the active device can change it and the platform stores it after `take_layout`
+ *
+ * ## Firmware update
+ * A platform that can be updated over the network gives its flash storage with `set_updater`, the server then
+ * receives the image chunk by chunk, checks its hash and commits it. A client asking for a restart once the image
+ * is committed makes `take_reboot` return true, the platform restarts after sending the response
+ * Only the client of the active device can update the firmware, which is not authenticated unless a cipher is set:
+ * without the `crypto` feature anyone on the network can acquire a free device and flash its own image
+ *
+ * ## Encryption
+ * With the `crypto` feature, a cipher can be set so that only the sealed messages are accepted,
+ * `respond` then seals the responses. The plain hellos are still answered in plain for the discovery
+ * The sealed messages cannot be read nor forged but a captured one can be sent again after a restart of the server
+ */
+#[derive(Debug)]
+pub struct Server<S: LedSink> {
+    sink: S,
+    advertisement: Advertisement,
+    active: Option<DeviceId>,
+    /// Client which made the active device active
+    active_addr: Option<SocketAddr>,
+    timeout: Option<Duration>,
+    idle_mode: IdleMode,
+    fade: Duration,
+    last_seen: Option<Instant>,
+    idle: Option<Idle>,
+    shown: Frame,
+    owners: Vec<Owner>,
+    pending: VecDeque<(u64, Frame)>,
+    last_pts: Option<u64>,
+    started: Instant,
+    frames_received: u32,
+    frames_applied: u32,
+    frames_dropped: u32,
+    rate: FrameRate,
+    free_heap: u32,
+    rssi: i8,
+    last_error: Option<String>,
+    color_order: crate::config::ColorOrder,
+    output_pin: u8,
+    config_changed: bool,
+    ota: Ota,
+    reboot_requested: bool,
+    segments: Vec<Segment>,
+    /// Device owning each segment with the last time it was heard from
+    segment_owners: Vec<Option<(DeviceId, Instant)>>,
+    segments_changed: bool,
+    layout: Layout,
+    layout_changed: bool,
+    #[cfg(feature = "crypto")]
+    cipher: Option<crate::crypto::Cipher>,
+}
+
+impl<S: LedSink> Server<S> {
+    /// Duration of the fade to the idle mode
+    pub const DEFAULT_FADE: Duration = Duration::from_secs(1);
+    /// Number of timed frames waiting to be shown
+    pub const MAX_PENDING_FRAMES: usize = 8;
+
+    /// Creates a new server driving the given sink, no device is active at first and there is no timeout
+    pub fn new(sink: S, advertisement: Advertisement) -> Self {
+        let mut server = Server {
+            sink,
+            advertisement,
+            active: None,
+            active_addr: None,
+            timeout: None,
+            idle_mode: IdleMode::default(),
+            fade: Self::DEFAULT_FADE,
+            last_seen: None,
+            idle: None,
+            shown: Frame::new(0),
+            owners: Vec::new(),
+            pending: VecDeque::new(),
+            last_pts: None,
+            started: Instant::now(),
+            frames_received: 0,
+            frames_applied: 0,
+            frames_dropped: 0,
+            rate: FrameRate::default(),
+            free_heap: 0,
+            rssi: 0,
+            last_error: None,
+            color_order: Default::default(),
+            output_pin: 0,
+            config_changed: false,
+            ota: Ota::default(),
+            reboot_requested: false,
+            segments: Vec::new(),
+            segment_owners: Vec::new(),
+            segments_changed: false,
+            layout: Layout::Unknown,
+            layout_changed: false,
+            #[cfg(feature = "crypto")]
+            cipher: None,
+        };
+        server.shown = Frame::new(server.led_count());
+        server
+    }
+
+    /// What the server advertises in its hello messages
+    pub fn advertisement(&self) -> &Advertisement {
+        &self.advertisement
+    }
+
+    /// The device currently allowed to update the LEDs
+    pub fn active(&self) -> Option<DeviceId> {
+        self.active
+    }
+
+    /// The address of the client the device id was assigned to
+    pub fn owner(&self, device: DeviceId) -> Option<SocketAddr> {
+        self.owners.iter().find(|owner| owner.device == device).map(|owner| owner.addr)
+    }
+
+    /// Requires the clients to seal their messages with the keys of the cipher, `None` accepts plain messages again
+    #[cfg(feature = "crypto")]
+    pub fn set_cipher(&mut self, cipher: Option<crate::crypto::Cipher>) {
+        self.cipher = cipher;
+    }
+
+    /// The cipher of the server, to rotate its keys
+    #[cfg(feature = "crypto")]
+    pub fn cipher_mut(&mut self) -> Option<&mut crate::crypto::Cipher> {
+        self.cipher.as_mut()
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Sets how long the active device can stay silent before being released, `None` to keep it forever
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Sets what the strip shows once the active device is released
+    pub fn set_idle_mode(&mut self, mode: IdleMode) {
+        self.idle_mode = mode;
+        if let Some(idle) = &mut self.idle {
+            idle.settled = false;
+        }
+    }
+
+    /// Sets the duration of the fade to the idle mode
+    pub fn set_fade(&mut self, fade: Duration) {
+        self.fade = fade;
+    }
+
+    /// Whether the active device timed out and the strip shows the idle mode
+    pub fn is_idle(&self) -> bool {
+        self.idle.is_some()
+    }
+
+    /// Number of pixels accepted from the clients
+    fn led_count(&self) -> usize {
+        match self.advertisement.led_count as usize {
+            0 => crate::constants::MAX_LED_COUNT,
+            count => count.min(crate::constants::MAX_LED_COUNT),
+        }
+    }
+
+    /// The config of the server, the LED count and the name are the advertised ones
+    pub fn config(&self) -> Config {
+        Config {
+            led_count: self.advertisement.led_count,
+            color_order: self.color_order,
+            output_pin: self.output_pin,
+            name: self.advertisement.name.clone(),
+        }
+    }
+
+    /// Applies a config, usually the one the platform stored, and passes it to the sink
+    ///
+    /// The strip cannot have more LEDs than the sink drives, and a shorter strip has to leave room for the
+    /// segments and the layout, they are not trimmed
+    pub fn set_config(&mut self, config: Config) -> Result<(), crate::error::Error> {
+        config.validate()?;
+        if config.led_count as usize > self.sink.max_led_count() {
+            return Err(crate::error::Error::InvalidConfig);
+        }
+        Segment::validate_table(&self.segments, config.led_count as usize)?;
+        self.layout.validate(config.led_count as usize)?;
+        self.advertisement.led_count = config.led_count;
+        self.advertisement.name = config.name.clone();
+        self.color_order = config.color_order;
+        self.output_pin = config.output_pin;
+        self.sink.configure(&config);
+
+        let mut shown = Frame::new(self.led_count());
+        shown.copy_at(0, self.shown.as_slice());
+        self.sink.set_pixels(&shown.to_bytes());
+        self.shown = shown;
+        if let Some(idle) = &mut self.idle {
+            idle.from.resize(self.shown.len());
+            idle.settled = false;
+        }
+        Ok(())
+    }
+
+    /// The config a client set since the last call, for the platform to store it
+    pub fn take_config(&mut self) -> Option<Config> {
+        std::mem::take(&mut self.config_changed).then(|| self.config())
+    }
+
+    /// The segments the strip is split in
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// The device owning the segment at `index` of the table
+    pub fn segment_owner(&self, index: usize) -> Option<DeviceId> {
+        self.segment_owners.get(index).copied().flatten().map(|(device, _)| device)
+    }
+
+    /// Replaces the segment table, usually with the one the platform stored, every segment is released
+    pub fn set_segments(&mut self, segments: Vec<Segment>) -> Result<(), crate::error::Error> {
+        Segment::validate_table(&segments, self.led_count())?;
+        self.segment_owners = vec![None; segments.len()];
+        self.segments = segments;
+        Ok(())
+    }
+
+    /// The segment table a client set since the last call, for the platform to store it
+    pub fn take_segments(&mut self) -> Option<Vec<Segment>> {
+        std::mem::take(&mut self.segments_changed).then(|| self.segments.clone())
+    }
+
+    /// Where the LEDs are around the screen
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Sets the layout of the LEDs, usually the one the platform stored
+    pub fn set_layout(&mut self, layout: Layout) -> Result<(), crate::error::Error> {
+        layout.validate(self.led_count())?;
+        self.layout = layout;
+        Ok(())
+    }
+
+    /// The layout a client set since the last call, for the platform to store it
+    pub fn take_layout(&mut self) -> Option<Layout> {
+        std::mem::take(&mut self.layout_changed).then(|| self.layout.clone())
+    }
+
+    /// Lets the clients update the firmware, the images being written to the updater
+    pub fn set_updater(&mut self, updater: impl Updater + Send + 'static) {
+        self.ota.set_updater(Box::new(updater));
+    }
+
+    /// Whether a client asked for a restart on the committed image since the last call
+    pub fn take_reboot(&mut self) -> bool {
+        std::mem::take(&mut self.reboot_requested)
+    }
+
+    /// Sets the figures only the platform knows, reported in the status, 0 when unknown
+    pub fn set_health(&mut self, free_heap: u32, rssi: i8) {
+        self.free_heap = free_heap;
+        self.rssi = rssi;
+    }
+
+    /// Records an error met outside of the server, reported in the status
+    pub fn report_error(&mut self, error: impl std::fmt::Display) {
+        self.last_error = Some(error.to_string());
+    }
+
+    /// The current status of the server
+    pub fn status(&self) -> Status {
+        self.status_at(Instant::now())
+    }
+
+    /// The status of the server at the given time
+    pub fn status_at(&self, now: Instant) -> Status {
+        Status {
+            uptime: now.saturating_duration_since(self.started),
+            frames_received: self.frames_received,
+            frames_applied: self.frames_applied,
+            frames_dropped: self.frames_dropped,
+            fps: self.rate.get(now),
+            active: self.active,
+            free_heap: self.free_heap,
+            rssi: self.rssi,
+            last_error: self.last_error.clone().unwrap_or_default(),
+        }
+    }
+
+    /// The clock the presentation timestamps refer to, in microseconds since the server started
+    pub fn clock_at(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.started).as_micros() as u64
+    }
+
+    /// Time left before the next timed frame has to be shown
+    pub fn next_frame_in(&self, now: Instant) -> Option<Duration> {
+        let (pts, _) = self.pending.front()?;
+        Some(Duration::from_micros(pts.saturating_sub(self.clock_at(now))))
+    }
+
+    /// Counts a frame received from a client, shown or not
+    fn count_frame(&mut self, applied: bool, now: Instant) {
+        self.frames_received = self.frames_received.wrapping_add(1);
+        self.count_outcome(applied, now);
+    }
+
+    /// Counts whether a received frame was shown
+    fn count_outcome(&mut self, applied: bool, now: Instant) {
+        if applied {
+            self.frames_applied = self.frames_applied.wrapping_add(1);
+            self.rate.record(now);
+        } else {
+            self.frames_dropped = self.frames_dropped.wrapping_add(1);
+        }
+    }
+
+    /// Whether the client at `source` may use the device id, the ids nobody claimed are open to everyone
+    fn may_use(&self, device: DeviceId, source: SocketAddr) -> bool {
+        self.owner(device).map_or(true, |owner| owner == source)
+    }
+
+    /// Whether the client at `source` made the active device active, only this client changes the settings
+    fn is_active_client(&self, source: SocketAddr) -> bool {
+        self.active.map_or(false, |device| self.may_use(device, source)) && self.active_addr == Some(source)
+    }
+
+    /// Whether the message of the client at `source` can update the LEDs
+    fn accepts(&self, device: DeviceId, source: SocketAddr) -> bool {
+        self.active == Some(device) && self.may_use(device, source)
+    }
+
+    /// Gives the client its previous device id back, or a free one
+    fn claim(&mut self, token: ClientToken, source: SocketAddr, now: Instant) -> DeviceId {
+        if let Some(owner) = self.owners.iter_mut().find(|owner| owner.token == token) {
+            owner.addr = source;
+            owner.claimed = now;
+            return owner.device;
+        }
+
+        let free = (0..DeviceId::COUNT)
+            .map(DeviceId::from_bits)
+            .filter(|&device| self.owner(device).is_none())
+            // Picking the active id would let the newcomer take over the LEDs
+            .min_by_key(|&device| self.active == Some(device));
+        let device = match free {
+            Some(device) => device,
+            None => {
+                let (index, _) = self.owners.iter().enumerate().min_by_key(|(_, owner)| owner.claimed).unwrap();
+                let device = self.owners.swap_remove(index).device;
+                if self.active == Some(device) {
+                    self.active = None;
+                    self.active_addr = None;
+                    self.cancel_pending(now);
+                    self.last_pts = None;
+                }
+                device
+            },
+        };
+        self.owners.push(Owner { device, token, addr: source, claimed: now });
+        device
+    }
+
+    /// Shows a frame, the pixels missing from the frame are turned off
+    fn show(&mut self, frame: &Frame) {
+        let mut shown = Frame::new(self.led_count());
+        shown.copy_at(0, frame.as_slice());
+        for segment in self.owned_segments() {
+            let start = (segment.start as usize).min(shown.len());
+            let end = (start + segment.len as usize).min(shown.len()).min(self.shown.len());
+            shown.copy_at(start, &self.shown[start..end.max(start)]);
+        }
+        self.sink.set_pixels(&shown.to_bytes());
+        self.shown = shown;
+    }
+
+    /// Shows the pixels of a segment, the LEDs past the end of the strip are dropped
+    fn show_segment(&mut self, index: usize, pixels: &Frame) {
+        let segment = &self.segments[index];
+        for (index, led) in (0..segment.len as usize).filter_map(|index| Some((index, segment.led(index)?))) {
+            if led < self.shown.len() {
+                self.shown.set(led, pixels.as_slice().get(index).copied().unwrap_or(Rgb::BLACK));
+            }
+        }
+        self.sink.set_pixels(&self.shown.to_bytes());
+    }
+
+    /// Whether a device last heard from at `seen` went silent for the receiver timeout
+    fn is_stale(&self, seen: Instant, now: Instant) -> bool {
+        self.timeout.map_or(false, |timeout| now.saturating_duration_since(seen) >= timeout)
+    }
+
+    /// The segments owned by a device
+    fn owned_segments(&self) -> impl Iterator<Item = &Segment> {
+        self.segments.iter().zip(&self.segment_owners).filter(|(_, owner)| owner.is_some()).map(|(segment, _)| segment)
+    }
+
+    /// Forgets the timed frames waiting to be shown
+    fn cancel_pending(&mut self, now: Instant) {
+        while self.pending.pop_front().is_some() {
+            self.count_outcome(false, now);
+        }
+    }
+
+    /// Buffers a timed frame of the active device
+    fn schedule(&mut self, pts: u64, frame: Frame, now: Instant) {
+        // Showing a frame older than the one on the LEDs would go back in time
+        if self.last_pts.map_or(false, |last| pts <= last) {
+            self.count_outcome(false, now);
+            return;
+        }
+        let index = self.pending.partition_point(|(at, _)| *at <= pts);
+        self.pending.insert(index, (pts, frame));
+        if self.pending.len() > Self::MAX_PENDING_FRAMES {
+            self.pending.pop_back();
+            self.count_outcome(false, now);
+        }
+        self.play_due(now);
+    }
+
+    /// Shows the last timed frame whose time has come, the earlier ones are skipped
+    fn play_due(&mut self, now: Instant) {
+        let clock = self.clock_at(now);
+        let mut due = None;
+        while self.pending.front().map_or(false, |(pts, _)| *pts <= clock) {
+            if due.replace(self.pending.pop_front().unwrap()).is_some() {
+                self.count_outcome(false, now);
             }
         }
+        if let Some((pts, frame)) = due {
+            self.last_pts = Some(pts);
+            self.show(&frame);
+            self.count_outcome(true, now);
+        }
+    }
+
+    /// Handles a datagram received from the client at `source`, returns the response to send back to it
+    pub fn handle(&mut self, datagram: &[u8], source: SocketAddr) -> Result<Option<ServerMessages>, crate::error::Error> {
+        self.handle_at(datagram, source, Instant::now())
+    }
+
+    /// Handles a datagram received from the client at `source` at the given time
+    pub fn handle_at(&mut self, datagram: &[u8], source: SocketAddr, now: Instant) -> Result<Option<ServerMessages>, crate::error::Error> {
+        let (message, _) = self.receive(datagram)?;
+        self.apply_at(message, source, now)
+    }
+
+    /// Handles a datagram received from the client at `source`, returns the encoded response to send back to it
+    ///
+    /// The response is sealed when the datagram was, so this is the method to use with a cipher
+    pub fn respond(&mut self, datagram: &[u8], source: SocketAddr) -> Result<Option<Vec<u8>>, crate::error::Error> {
+        self.respond_at(datagram, source, Instant::now())
+    }
+
+    /// Handles a datagram received from the client at `source` at the given time and encodes the response
+    pub fn respond_at(&mut self, datagram: &[u8], source: SocketAddr, now: Instant) -> Result<Option<Vec<u8>>, crate::error::Error> {
+        let (message, sealed) = self.receive(datagram)?;
+        match self.apply_at(message, source, now)? {
+            Some(response) => self.reply(response, sealed).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Decodes a datagram, tells whether it was sealed
+    ///
+    /// With a cipher, only the plain hellos are accepted besides the sealed messages, so the server can be discovered
+    pub(crate) fn receive(&mut self, datagram: &[u8]) -> Result<(ClientMessages, bool), crate::error::Error> {
+        let result = self.decode(datagram);
+        if let Err(error) = &result {
+            self.last_error = Some(error.to_string());
+        }
+        result
+    }
+
+    fn decode(&mut self, datagram: &[u8]) -> Result<(ClientMessages, bool), crate::error::Error> {
+        #[cfg(feature = "crypto")]
+        if let Some(cipher) = &mut self.cipher {
+            if crate::crypto::Cipher::is_sealed(datagram) {
+                let message = cipher.open(datagram)?;
+                return Ok((ClientMessages::try_from(&message[..])?, true));
+            }
+            return match ClientMessages::try_from(datagram)? {
+                ClientMessages::Hello => Ok((ClientMessages::Hello, false)),
+                _ => Err(crate::error::Error::NotSealed),
+            };
+        }
+        Ok((ClientMessages::try_from(datagram)?, false))
+    }
+
+    /// Encodes a response, sealed if the request was
+    pub(crate) fn reply(&mut self, response: ServerMessages, sealed: bool) -> Result<Vec<u8>, crate::error::Error> {
+        let len = response.encoded_len();
+        let bytes: [u8; crate::constants::MAX_MESSAGE_LENGTH] = response.into();
+        #[cfg(feature = "crypto")]
+        if let (true, Some(cipher)) = (sealed, &mut self.cipher) {
+            return cipher.seal(&bytes[..len]);
+        }
+        #[cfg(not(feature = "crypto"))]
+        let _ = sealed;
+        Ok(bytes[..len].to_vec())
+    }
+
+    /// Applies a decoded message received from the client at `source` at the given time
+    pub(crate) fn apply_at(&mut self, message: ClientMessages, source: SocketAddr, now: Instant) -> Result<Option<ServerMessages>, crate::error::Error> {
+        match message {
+            ClientMessages::Hello => {
+                return Ok(Some(ServerMessages::Hello(self.advertisement.clone())));
+            },
+            ClientMessages::Claim(token) => {
+                let device = self.claim(token, source, now);
+                return Ok(Some(ServerMessages::Welcome(self.advertisement.clone(), device)));
+            },
+            ClientMessages::SetActive(device) => {
+                if !self.may_use(device, source) {
+                    return Ok(None);
+                }
+                if self.active != Some(device) {
+                    self.cancel_pending(now);
+                    self.last_pts = None;
+                }
+                self.active = Some(device);
+                self.active_addr = Some(source);
+                self.last_seen = Some(now);
+                self.idle = None;
+            },
+            ClientMessages::SendPixels(device, frame) => {
+                let applied = self.accepts(device, source);
+                self.count_frame(applied, now);
+                if applied {
+                    // A frame without timestamp replaces the timed frames
+                    self.cancel_pending(now);
+                    self.show(&frame);
+                    self.last_seen = Some(now);
+                }
+            },
+            ClientMessages::SetPixel(device, index, color) => {
+                let owned = self.owned_segments().any(|segment| (segment.start..segment.start + segment.len).contains(&(index as u16)));
+                if self.accepts(device, source) && (index as usize) < self.led_count() && !owned {
+                    self.sink.set_pixel(index as usize, color);
+                    self.shown.set(index as usize, color);
+                    self.last_seen = Some(now);
+                }
+            },
+            ClientMessages::Heartbeat(device) => {
+                if self.accepts(device, source) {
+                    self.last_seen = Some(now);
+                }
+            },
+            ClientMessages::SendGroupPixels(device, slices, pixels) => {
+                let mut slices = slices.iter().filter(|slice| slice.server == self.advertisement.id).peekable();
+                // The frames of the group without any slice for this server are not counted
+                if slices.peek().is_none() {
+                    return Ok(None);
+                }
+                let applied = self.accepts(device, source);
+                if applied {
+                    let mut frame = self.shown.clone();
+                    for slice in slices {
+                        let source = slice.source as usize;
+                        frame.copy_at(slice.target as usize, &pixels[source..source + slice.len as usize]);
+                    }
+                    self.show(&frame);
+                    self.last_seen = Some(now);
+                }
+                self.count_frame(applied, now);
+            },
+            ClientMessages::GetStatus => {
+                return Ok(Some(ServerMessages::Status(self.status_at(now))));
+            },
+            ClientMessages::TimeRequest(t1) => {
+                // The answer leaves right away, so it is stamped with the time of reception
+                let clock = self.clock_at(now);
+                return Ok(Some(ServerMessages::TimeResponse(t1, clock, clock)));
+            },
+            ClientMessages::GetConfig => {
+                return Ok(Some(ServerMessages::Config(self.config())));
+            },
+            ClientMessages::SetConfig(config) => {
+                let changed = match self.is_active_client(source) {
+                    true => self.set_config(config),
+                    false => Err(crate::error::Error::NotActive),
+                };
+                match changed {
+                    Ok(()) => self.config_changed = true,
+                    Err(error) => self.last_error = Some(error.to_string()),
+                }
+                return Ok(Some(ServerMessages::Config(self.config())));
+            },
+            ClientMessages::OtaBegin(..) | ClientMessages::OtaChunk(..) | ClientMessages::OtaVerify | ClientMessages::OtaCommit
+            | ClientMessages::OtaReboot if !self.is_active_client(source) => {
+                self.last_error = Some(crate::error::Error::NotActive.to_string());
+                return Ok(Some(ServerMessages::OtaStatus(self.ota.status())));
+            },
+            ClientMessages::OtaBegin(size, hash) => return Ok(Some(self.update(|ota| ota.begin(size, hash), now))),
+            ClientMessages::OtaChunk(offset, data) => return Ok(Some(self.update(|ota| ota.chunk(offset, &data), now))),
+            ClientMessages::OtaVerify => return Ok(Some(self.update(Ota::verify, now))),
+            ClientMessages::OtaCommit => return Ok(Some(self.update(Ota::commit, now))),
+            ClientMessages::OtaReboot => {
+                self.reboot_requested |= self.ota.is_committed();
+                return Ok(Some(ServerMessages::OtaStatus(self.ota.status())));
+            },
+            ClientMessages::GetSegments => {
+                return Ok(Some(ServerMessages::Segments(self.segments.clone())));
+            },
+            ClientMessages::SetSegments(segments) => {
+                // Replacing the table releases every segment, so it waits until the other devices went silent
+                let in_use = self.segment_owners.iter().flatten().any(|(owner, seen)| Some(*owner) != self.active && !self.is_stale(*seen, now));
+                let changed = match (self.is_active_client(source), in_use) {
+                    (false, _) => Err(crate::error::Error::NotActive),
+                    (true, true) => Err(crate::error::Error::SegmentInUse),
+                    (true, false) => self.set_segments(segments),
+                };
+                match changed {
+                    Ok(()) => self.segments_changed = true,
+                    Err(error) => self.last_error = Some(error.to_string()),
+                }
+                return Ok(Some(ServerMessages::Segments(self.segments.clone())));
+            },
+            ClientMessages::SetSegmentActive(device, index) => {
+                let index = index as usize;
+                if !self.may_use(device, source) || index >= self.segments.len() {
+                    return Ok(None);
+                }
+                // The owner keeps its segment until it goes silent
+                let held = self.segment_owners[index].map_or(false, |(owner, seen)| owner != device && !self.is_stale(seen, now));
+                if !held {
+                    self.segment_owners[index] = Some((device, now));
+                }
+            },
+            ClientMessages::SendSegmentPixels(device, index, pixels) => {
+                let index = index as usize;
+                let applied = self.segment_owner(index) == Some(device) && self.may_use(device, source);
+                self.count_frame(applied, now);
+                if applied {
+                    self.segment_owners[index] = Some((device, now));
+                    self.show_segment(index, &pixels);
+                }
+            },
+            ClientMessages::GetLayout => {
+                return Ok(Some(ServerMessages::Layout(self.layout.clone())));
+            },
+            ClientMessages::SetLayout(layout) => {
+                let changed = match self.is_active_client(source) {
+                    true => self.set_layout(layout),
+                    false => Err(crate::error::Error::NotActive),
+                };
+                match changed {
+                    Ok(()) => self.layout_changed = true,
+                    Err(error) => self.last_error = Some(error.to_string()),
+                }
+                return Ok(Some(ServerMessages::Layout(self.layout.clone())));
+            },
+            ClientMessages::SendTimedPixels(device, pts, frame) => {
+                if self.accepts(device, source) {
+                    self.frames_received = self.frames_received.wrapping_add(1);
+                    self.last_seen = Some(now);
+                    self.schedule(pts, frame, now);
+                } else {
+                    self.count_frame(false, now);
+                }
+            },
+        }
+        Ok(None)
+    }
+
+    /// Releases the segments whose owner went silent, the idle mode then covers them unless a device is active
+    fn release_stale_segments(&mut self, now: Instant) {
+        let mut released = false;
+        for index in 0..self.segment_owners.len() {
+            if self.segment_owners[index].map_or(false, |(_, seen)| self.is_stale(seen, now)) {
+                self.segment_owners[index] = None;
+                released = true;
+            }
+        }
+        if !released || self.active.is_some() {
+            return;
+        }
+        match &mut self.idle {
+            Some(idle) => idle.settled = false,
+            None => self.idle = Some(Idle { since: now, from: self.shown.clone(), settled: false }),
+        }
+    }
+
+    /// Runs a step of the firmware update and answers with its status
+    fn update(&mut self, step: impl FnOnce(&mut Ota) -> Result<(), crate::error::Error>, now: Instant) -> ServerMessages {
+        // The update keeps the device active, so the receiver timeout does not cut it
+        self.last_seen = Some(now);
+        if let Err(error) = step(&mut self.ota) {
+            self.last_error = Some(error.to_string());
+        }
+        ServerMessages::OtaStatus(self.ota.status())
+    }
+
+    /// Shows the timed frames, checks the receiver timeout and animates the idle mode
+    pub fn tick(&mut self) {
+        self.tick_at(Instant::now())
+    }
+
+    /// Shows the timed frames, checks the receiver timeout and animates the idle mode at the given time
+    pub fn tick_at(&mut self, now: Instant) {
+        self.play_due(now);
+        if let (Some(timeout), Some(_), Some(last_seen)) = (self.timeout, self.active, self.last_seen) {
+            if now.saturating_duration_since(last_seen) >= timeout {
+                self.active = None;
+                self.active_addr = None;
+                self.last_seen = None;
+                self.cancel_pending(now);
+                self.last_pts = None;
+                self.idle = Some(Idle { since: now, from: self.shown.clone(), settled: false });
+            }
+        }
+        self.release_stale_segments(now);
+
+        let Some(idle) = &mut self.idle else {
+            return;
+        };
+        if idle.settled {
+            return;
+        }
+        let t = now.saturating_duration_since(idle.since);
+        let mut frame = Frame::new(self.shown.len());
+        self.idle_mode.render(t, &mut frame);
+        let progress = if self.fade.is_zero() { 1.0 } else { (t.as_secs_f32() / self.fade.as_secs_f32()).min(1.0) };
+        if progress < 1.0 {
+            for (pixel, from) in frame.iter_mut().zip(idle.from.iter()) {
+                *pixel = from.lerp(*pixel, progress);
+            }
+        } else if !self.idle_mode.is_animated() {
+            idle.settled = true;
+        }
+        self.show(&frame);
     }
 }
 
@@ -59,18 +1225,359 @@ impl Into<[u8; crate::constants::MAX_MESSAGE_LENGTH]> for ServerMessages  {
 mod tests {
     use super::*;
 
+    fn device(id: u8) -> DeviceId {
+        DeviceId::new(id).unwrap()
+    }
+
+    fn client(port: u16) -> SocketAddr {
+        SocketAddr::from(([192, 168, 1, 10], port))
+    }
+
     #[test]
     fn test_hello() {
-        let message: [u8; 770] = ServerMessages::Hello.into();
+        let message: [u8; 770] = ServerMessages::hello().into();
         assert!(message[0] == crate::constants::SERVER_FLAG);
         assert!(message[1] == crate::constants::INSTRUCTION_HELLO);
         let parsed = ServerMessages::try_from(&message[..]).unwrap();
-        assert_eq!(parsed, ServerMessages::Hello);
+        assert_eq!(parsed, ServerMessages::hello());
+    }
+
+    #[test]
+    fn test_hello_advertisement() {
+        let advertisement = Advertisement {
+            led_count: 300,
+            max_fps: 60,
+            id: [0xde, 0xad, 0xbe, 0xef, 0x00, 0x01],
+            name: "Living room".to_string(),
+        };
+        let message = ServerMessages::Hello(advertisement.clone());
+        let len = message.encoded_len();
+        let bytes: [u8; 770] = message.into();
+        let parsed = ServerMessages::try_from(&bytes[..len]).unwrap();
+        assert_eq!(parsed, ServerMessages::Hello(advertisement));
+        assert!(ServerMessages::try_from(&bytes[..len - 1]).is_err());
+    }
+
+    #[test]
+    fn test_advertisement_matches() {
+        let advertisement = Advertisement {
+            id: [0xde, 0xad, 0xbe, 0xef, 0x00, 0x01],
+            name: "Living room".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(advertisement.id_string(), "de:ad:be:ef:00:01");
+        assert!(advertisement.matches("living room"));
+        assert!(advertisement.matches("DE:AD:BE:EF:00:01"));
+        assert!(!advertisement.matches("Kitchen"));
+        assert!(!Advertisement::default().matches(""));
+    }
+
+    #[test]
+    fn test_name_truncated() {
+        let advertisement = Advertisement { name: "é".repeat(20), ..Default::default() };
+        let message = ServerMessages::Hello(advertisement);
+        let len = message.encoded_len();
+        let bytes: [u8; 770] = message.into();
+        let parsed = ServerMessages::try_from(&bytes[..len]).unwrap();
+        assert_eq!(parsed.advertisement().unwrap().name, "é".repeat(16));
+    }
+
+    #[test]
+    fn test_welcome() {
+        let advertisement = Advertisement { led_count: 8, name: "desk".to_string(), ..Default::default() };
+        let message = ServerMessages::Welcome(advertisement.clone(), device(42));
+        let len = message.encoded_len();
+        let bytes: [u8; 770] = message.into();
+        assert_eq!(bytes[len - 1], 42);
+        assert_eq!(ServerMessages::try_from(&bytes[..len]), Ok(ServerMessages::Welcome(advertisement.clone(), device(42))));
+        // Older clients read it as a hello
+        assert_eq!(ServerMessages::try_from(&bytes[..len - 1]), Ok(ServerMessages::Hello(advertisement)));
+
+        let mut bytes = bytes;
+        bytes[len - 1] = 64;
+        assert_eq!(ServerMessages::try_from(&bytes[..len]), Err(crate::error::Error::InvalidDevice));
+    }
+
+    #[test]
+    fn test_config_message() {
+        let config = Config { led_count: 300, color_order: crate::config::ColorOrder::Bgr, output_pin: 4, name: "tv".to_string() };
+        let message = ServerMessages::Config(config.clone());
+        let len = message.encoded_len();
+        let bytes: [u8; 770] = message.into();
+        assert_eq!(bytes[1], 0b1100_0110);
+        assert_eq!(ServerMessages::try_from(&bytes[..len]), Ok(ServerMessages::Config(config)));
+    }
+
+    #[test]
+    fn test_server_config() {
+        let mut server = server();
+        let config = Config { led_count: 6, color_order: crate::config::ColorOrder::Grb, output_pin: 32, name: "tv".to_string() };
+        let response = server.handle(&datagram(ClientMessages::GetConfig), client(1)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Config(Config { led_count: 4, ..Default::default() })));
+        assert_eq!(server.take_config(), None);
+
+        server.handle(&datagram(ClientMessages::SetActive(device(1))), client(1)).unwrap();
+        let response = server.handle(&datagram(ClientMessages::SetConfig(config.clone())), client(1)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Config(config.clone())));
+        assert_eq!(server.take_config(), Some(config.clone()));
+        assert_eq!(server.take_config(), None);
+        assert_eq!(server.advertisement().name, "tv");
+        assert_eq!(server.sink().len(), 6);
+
+        // The longer strip takes full frames
+        let mut frame = Frame::new(6);
+        frame.fill(Rgb::RED);
+        server.handle(&datagram(ClientMessages::SendPixels(device(1), frame.clone())), client(1)).unwrap();
+        assert_eq!(server.sink(), &frame);
+
+        // An invalid config is refused and the current one is sent back
+        let invalid = Config { led_count: 0, ..config.clone() };
+        let response = server.handle(&datagram(ClientMessages::SetConfig(invalid)), client(1)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Config(config.clone())));
+        assert_eq!(server.take_config(), None);
+        assert_eq!(server.status().last_error, "Invalid configuration");
+
+        // Only the client of the active device changes the config
+        let renamed = Config { name: "desk".to_string(), ..config.clone() };
+        let response = server.handle(&datagram(ClientMessages::SetConfig(renamed)), client(2)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Config(config.clone())));
+        assert_eq!(server.status().last_error, crate::error::Error::NotActive.to_string());
+
+        // The strip cannot get shorter than its segments and its layout
+        server.set_segments(vec![Segment::new("all", 0, 6)]).unwrap();
+        let shorter = Config { led_count: 5, ..config.clone() };
+        assert_eq!(server.set_config(shorter.clone()), Err(crate::error::Error::InvalidSegment));
+        server.set_segments(Vec::new()).unwrap();
+        server.set_layout(Layout::Points(vec![Default::default(); 6])).unwrap();
+        assert_eq!(server.set_config(shorter), Err(crate::error::Error::InvalidLayout));
+        assert_eq!(server.config(), config);
+    }
+
+    #[test]
+    fn test_server_max_led_count() {
+        struct Short(Frame);
+
+        impl LedSink for Short {
+            fn set_pixels(&mut self, pixels: &[u8]) {
+                self.0.set_pixels(pixels);
+            }
+
+            fn set_pixel(&mut self, index: usize, color: Rgb) {
+                self.0.set_pixel(index, color);
+            }
+
+            fn max_led_count(&self) -> usize {
+                8
+            }
+        }
+
+        let mut server = Server::new(Short(Frame::new(4)), Advertisement { led_count: 4, ..Default::default() });
+        server.handle(&datagram(ClientMessages::SetActive(device(1))), client(1)).unwrap();
+        let config = Config { led_count: 8, color_order: crate::config::ColorOrder::Grb, output_pin: 32, name: "tv".to_string() };
+        let long = Config { led_count: 9, ..config.clone() };
+        let response = server.handle(&datagram(ClientMessages::SetConfig(long)), client(1)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Config(Config { led_count: 4, ..Default::default() })));
+        assert_eq!(server.advertisement().led_count, 4);
+        assert_eq!(server.status().last_error, "Invalid configuration");
+
+        server.set_config(config).unwrap();
+        assert_eq!(server.advertisement().led_count, 8);
+    }
+
+    #[test]
+    fn test_server_segments() {
+        let mut server = server();
+        let segments = vec![Segment::new("left", 0, 2), Segment { reversed: true, ..Segment::new("right", 2, 2) }];
+        server.handle(&datagram(ClientMessages::SetActive(device(1))), client(1)).unwrap();
+        let response = server.handle(&datagram(ClientMessages::SetSegments(vec![Segment::new("long", 0, 5)])), client(1)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Segments(Vec::new())));
+        assert_eq!(server.take_segments(), None);
+        let response = server.handle(&datagram(ClientMessages::SetSegments(segments.clone())), client(1)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Segments(segments.clone())));
+        assert_eq!(server.take_segments(), Some(segments.clone()));
+
+        // Each client updates its own segment
+        server.handle(&datagram(ClientMessages::SetSegmentActive(device(1), 0)), client(1)).unwrap();
+        server.handle(&datagram(ClientMessages::SetSegmentActive(device(2), 1)), client(2)).unwrap();
+        assert_eq!(server.segment_owner(1), Some(device(2)));
+        let pixels = Frame::from_bytes(&[1, 1, 1, 2, 2, 2]).unwrap();
+        server.handle(&datagram(ClientMessages::SendSegmentPixels(device(1), 0, pixels.clone())), client(1)).unwrap();
+        server.handle(&datagram(ClientMessages::SendSegmentPixels(device(2), 1, pixels.clone())), client(2)).unwrap();
+        server.handle(&datagram(ClientMessages::SendSegmentPixels(device(1), 1, Frame::new(2))), client(1)).unwrap();
+        assert_eq!(server.sink().to_bytes(), [1, 1, 1, 2, 2, 2, 2, 2, 2, 1, 1, 1]);
+        assert_eq!(server.status().frames_dropped, 1);
+
+        // Only the client of the active device replaces the table, once the other devices left their segments
+        let left = vec![Segment::new("left", 0, 2)];
+        let response = server.handle(&datagram(ClientMessages::SetSegments(left.clone())), client(2)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Segments(segments.clone())));
+        assert_eq!(server.status().last_error, crate::error::Error::NotActive.to_string());
+        let response = server.handle(&datagram(ClientMessages::SetSegments(left.clone())), client(1)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Segments(segments.clone())));
+        assert_eq!(server.status().last_error, crate::error::Error::SegmentInUse.to_string());
+        assert_eq!(server.segment_owner(1), Some(device(2)));
+        assert_eq!(server.take_segments(), None);
+
+        // The active device leaves the owned segments alone
+        server.set_segments(left).unwrap();
+        server.handle(&datagram(ClientMessages::SetSegmentActive(device(1), 0)), client(1)).unwrap();
+        server.handle(&datagram(ClientMessages::SetActive(device(3))), client(3)).unwrap();
+        server.handle(&datagram(ClientMessages::SendPixels(device(3), Frame::from_bytes(&[9; 12]).unwrap())), client(3)).unwrap();
+        server.handle(&datagram(ClientMessages::SetPixel(device(3), 1, Rgb::new(9, 9, 9))), client(3)).unwrap();
+        assert_eq!(server.sink().to_bytes(), [1, 1, 1, 2, 2, 2, 9, 9, 9, 9, 9, 9]);
+
+        let message = ServerMessages::Segments(segments.clone());
+        let len = message.encoded_len();
+        let bytes: [u8; 770] = message.into();
+        assert_eq!(bytes[1], 0b1100_1101);
+        assert_eq!(ServerMessages::try_from(&bytes[..len]), Ok(ServerMessages::Segments(segments)));
+    }
+
+    #[test]
+    fn test_server_layout() {
+        use crate::layout::Rectangle;
+
+        let mut server = server();
+        let response = server.handle(&datagram(ClientMessages::GetLayout), client(1)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Layout(Layout::Unknown)));
+        let layout = Layout::Rectangle(Rectangle { top: 1, right: 1, bottom: 1, left: 1, ..Default::default() });
+        server.handle(&datagram(ClientMessages::SetActive(device(1))), client(1)).unwrap();
+        let response = server.handle(&datagram(ClientMessages::SetLayout(layout.clone())), client(1)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Layout(layout.clone())));
+        assert_eq!(server.take_layout(), Some(layout.clone()));
+        assert_eq!(server.take_layout(), None);
+
+        // The layout has to fit on the strip
+        let larger = Layout::Rectangle(Rectangle { top: 2, right: 1, bottom: 1, left: 1, ..Default::default() });
+        let response = server.handle(&datagram(ClientMessages::SetLayout(larger)), client(1)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Layout(layout.clone())));
+        assert_eq!(server.take_layout(), None);
+
+        // Only the client of the active device changes the layout
+        let response = server.handle(&datagram(ClientMessages::SetLayout(Layout::Unknown)), client(2)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Layout(layout.clone())));
+        assert_eq!(server.status().last_error, crate::error::Error::NotActive.to_string());
+        assert_eq!(server.take_layout(), None);
+
+        let message = ServerMessages::Layout(layout.clone());
+        let len = message.encoded_len();
+        let bytes: [u8; 770] = message.into();
+        assert_eq!(bytes[1], 0b1101_0001);
+        assert_eq!(ServerMessages::try_from(&bytes[..len]), Ok(ServerMessages::Layout(layout)));
+    }
+
+    #[test]
+    fn test_server_update() {
+        use crate::ota::{OtaState, OtaStatus};
+
+        /// Updater keeping the image in memory
+        struct Memory(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl Updater for Memory {
+            fn begin(&mut self, _size: u32) -> Result<(), crate::error::Error> {
+                self.0.lock().unwrap().clear();
+                Ok(())
+            }
+
+            fn write(&mut self, data: &[u8]) -> Result<(), crate::error::Error> {
+                self.0.lock().unwrap().extend_from_slice(data);
+                Ok(())
+            }
+
+            fn commit(&mut self) -> Result<(), crate::error::Error> {
+                Ok(())
+            }
+
+            fn abort(&mut self) {}
+        }
+
+        let status = |state, offset| Some(ServerMessages::OtaStatus(OtaStatus { state, offset }));
+        let image = [7; 10];
+        let mut server = server();
+        server.handle(&datagram(ClientMessages::SetActive(device(1))), client(1)).unwrap();
+        let response = server.handle(&datagram(ClientMessages::ota_begin(&image)), client(1)).unwrap();
+        assert_eq!(response, status(OtaState::Unsupported, 0));
+
+        let memory = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        server.set_updater(Memory(memory.clone()));
+        server.handle(&datagram(ClientMessages::ota_begin(&image)), client(1)).unwrap();
+        let response = server.handle(&datagram(ClientMessages::OtaChunk(0, image[..6].to_vec())), client(1)).unwrap();
+        assert_eq!(response, status(OtaState::Receiving, 6));
+
+        // Only the client of the active device sends the image
+        let response = server.handle(&datagram(ClientMessages::OtaChunk(6, vec![0; 4])), client(2)).unwrap();
+        assert_eq!(response, status(OtaState::Receiving, 6));
+        assert_eq!(server.status().last_error, crate::error::Error::NotActive.to_string());
+        server.handle(&datagram(ClientMessages::ota_begin(&[0; 4])), client(2)).unwrap();
+        assert_eq!(server.handle(&datagram(ClientMessages::OtaVerify), client(2)).unwrap(), status(OtaState::Receiving, 6));
+
+        // Restarting too early is ignored
+        server.handle(&datagram(ClientMessages::OtaReboot), client(1)).unwrap();
+        assert!(!server.take_reboot());
+
+        server.handle(&datagram(ClientMessages::OtaChunk(6, image[6..].to_vec())), client(1)).unwrap();
+        let response = server.handle(&datagram(ClientMessages::OtaVerify), client(1)).unwrap();
+        assert_eq!(response, status(OtaState::Verified, 10));
+        let response = server.handle(&datagram(ClientMessages::OtaCommit), client(1)).unwrap();
+        assert_eq!(response, status(OtaState::Committed, 10));
+        server.handle(&datagram(ClientMessages::OtaReboot), client(1)).unwrap();
+        assert!(server.take_reboot());
+        assert!(!server.take_reboot());
+        assert_eq!(*memory.lock().unwrap(), image);
+
+        let message = ServerMessages::OtaStatus(OtaStatus { state: OtaState::Failed, offset: 3 });
+        let len = message.encoded_len();
+        let bytes: [u8; 770] = message.into();
+        assert_eq!(bytes[..len], [crate::constants::SERVER_FLAG, 0b1100_1000, 5, 0, 0, 0, 3]);
+    }
+
+    #[test]
+    fn test_status_message() {
+        let status = Status {
+            uptime: Duration::from_secs(3600),
+            frames_received: 1000,
+            frames_applied: 990,
+            frames_dropped: 10,
+            fps: 29.97,
+            active: Some(device(3)),
+            free_heap: 123_456,
+            rssi: -67,
+            last_error: "Malformed message".to_string(),
+        };
+        let message = ServerMessages::Status(status.clone());
+        let len = message.encoded_len();
+        let bytes: [u8; 770] = message.into();
+        assert_eq!(bytes[1], 0b1100_0011);
+        assert_eq!(ServerMessages::try_from(&bytes[..len]), Ok(ServerMessages::Status(status)));
+        assert!(ServerMessages::try_from(&bytes[..len - 1]).is_err());
+
+        let idle = ServerMessages::Status(Status::default());
+        let len = idle.encoded_len();
+        let bytes: [u8; 770] = idle.into();
+        assert_eq!(bytes[20], 0xff);
+        assert_eq!(ServerMessages::try_from(&bytes[..len]), Ok(ServerMessages::Status(Status::default())));
+    }
+
+    #[test]
+    fn test_time_response() {
+        let message = ServerMessages::TimeResponse(1, 2, u64::MAX);
+        let len = message.encoded_len();
+        let bytes: [u8; 770] = message.into();
+        assert_eq!(ServerMessages::try_from(&bytes[..len]), Ok(ServerMessages::TimeResponse(1, 2, u64::MAX)));
+        assert!(ServerMessages::try_from(&bytes[..len - 1]).is_err());
+    }
+
+    #[test]
+    fn test_legacy_hello() {
+        let message = [crate::constants::SERVER_FLAG, crate::constants::INSTRUCTION_HELLO];
+        let parsed = ServerMessages::try_from(&message[..]).unwrap();
+        assert_eq!(parsed, ServerMessages::Hello(Advertisement::default()));
+        assert!(ServerMessages::try_from(&message[..1]).is_err());
     }
 
     #[test]
     fn test_invalid_flag() {
-        let mut message: [u8; 770] = ServerMessages::Hello.into();
+        let mut message: [u8; 770] = ServerMessages::hello().into();
         message[0] = 0;
         let parsed = ServerMessages::try_from(&message[..]);
         assert!(parsed.is_err());
@@ -78,9 +1585,372 @@ mod tests {
 
     #[test]
     fn test_invalid_length() {
-        let mut message: [u8; 770] = ServerMessages::Hello.into();
+        let mut message: [u8; 770] = ServerMessages::hello().into();
         message[1] = 0;
         let parsed = ServerMessages::try_from(&message[..]);
         assert!(parsed.is_err());
     }
-}
\ No newline at end of file
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let message = ServerMessages::Hello(Advertisement { led_count: 30, name: "tv".to_string(), ..Default::default() });
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(json, r#"{"Hello":{"led_count":30,"max_fps":0,"id":[0,0,0,0,0,0],"name":"tv"}}"#);
+        assert_eq!(serde_json::from_str::<ServerMessages>(&json).unwrap(), message);
+    }
+
+    fn server() -> Server<crate::frame::Frame> {
+        let advertisement = Advertisement { led_count: 4, ..Default::default() };
+        Server::new(crate::frame::Frame::new(4), advertisement)
+    }
+
+    fn datagram(message: ClientMessages) -> Vec<u8> {
+        let len = message.encoded_len();
+        let bytes: [u8; crate::constants::MAX_MESSAGE_LENGTH] = message.into();
+        bytes[..len].to_vec()
+    }
+
+    #[test]
+    fn test_server_answers_hello() {
+        let mut server = server();
+        let response = server.handle(&datagram(ClientMessages::Hello), client(1)).unwrap();
+        assert_eq!(response, Some(ServerMessages::Hello(server.advertisement().clone())));
+    }
+
+    fn claim(server: &mut Server<Frame>, token: u64, source: SocketAddr, now: Instant) -> DeviceId {
+        match server.handle_at(&datagram(ClientMessages::Claim(token.into())), source, now).unwrap() {
+            Some(ServerMessages::Welcome(_, device)) => device,
+            response => panic!("Unexpected response {response:?}"),
+        }
+    }
+
+    #[test]
+    fn test_server_assigns_devices() {
+        let start = Instant::now();
+        let mut server = server();
+        assert_eq!(claim(&mut server, 10, client(1), start), device(0));
+        assert_eq!(claim(&mut server, 20, client(2), start), device(1));
+        assert_eq!(server.owner(device(1)), Some(client(2)));
+
+        // The first client restarts on another port and gets its id back
+        assert_eq!(claim(&mut server, 10, client(3), start), device(0));
+        assert_eq!(server.owner(device(0)), Some(client(3)));
+
+        // Only the owner can use an assigned id, the others stay open
+        server.handle(&datagram(ClientMessages::SetActive(device(0))), client(1)).unwrap();
+        assert_eq!(server.active(), None);
+        server.handle(&datagram(ClientMessages::SetActive(device(0))), client(3)).unwrap();
+        assert_eq!(server.active(), Some(device(0)));
+        server.handle(&datagram(ClientMessages::SetPixel(device(0), 0, Rgb::RED)), client(1)).unwrap();
+        assert_eq!(server.sink().get(0), Rgb::BLACK);
+        server.handle(&datagram(ClientMessages::SetActive(device(5))), client(4)).unwrap();
+        assert_eq!(server.active(), Some(device(5)));
+
+        // The active id is not handed out to newcomers
+        assert_eq!(claim(&mut server, 30, client(5), start), device(2));
+        assert_eq!(claim(&mut server, 40, client(6), start), device(3));
+        assert_eq!(claim(&mut server, 50, client(7), start), device(4));
+        assert_eq!(claim(&mut server, 60, client(8), start), device(6));
+    }
+
+    #[test]
+    fn test_server_reuses_oldest_device() {
+        let start = Instant::now();
+        let mut server = server();
+        for token in 0..DeviceId::COUNT as u64 {
+            claim(&mut server, token, client(token as u16), start + Duration::from_secs(token));
+        }
+        // The first client claims again, so the second one is the least recently claimed
+        claim(&mut server, 0, client(0), start + Duration::from_secs(100));
+        server.handle(&datagram(ClientMessages::SetActive(device(1))), client(1)).unwrap();
+        assert_eq!(claim(&mut server, 1000, client(1000), start + Duration::from_secs(101)), device(1));
+        assert_eq!(server.owner(device(1)), Some(client(1000)));
+        assert_eq!(server.active(), None);
+    }
+
+    #[test]
+    fn test_server_ignores_inactive_devices() {
+        let mut server = server();
+        server.handle(&datagram(ClientMessages::SetPixel(device(1), 0, Rgb::new(9, 9, 9))), client(1)).unwrap();
+        assert_eq!(server.sink().get(0), Rgb::new(0, 0, 0));
+
+        server.handle(&datagram(ClientMessages::SetActive(device(2))), client(1)).unwrap();
+        assert_eq!(server.active(), Some(device(2)));
+        server.handle(&datagram(ClientMessages::SetPixel(device(1), 0, Rgb::new(9, 9, 9))), client(1)).unwrap();
+        assert_eq!(server.sink().get(0), Rgb::new(0, 0, 0));
+        server.handle(&datagram(ClientMessages::SetPixel(device(2), 3, Rgb::new(1, 2, 3))), client(1)).unwrap();
+        assert_eq!(server.sink().get(3), Rgb::new(1, 2, 3));
+    }
+
+    #[test]
+    fn test_server_send_pixels() {
+        let mut server = server();
+        let mut frame = Frame::new(3);
+        frame.fill((7, 7, 7));
+        frame.set(0, (1, 2, 3));
+        server.handle(&datagram(ClientMessages::SetActive(device(0))), client(1)).unwrap();
+        server.sink_mut().fill(Rgb::WHITE);
+        let response = server.handle(&datagram(ClientMessages::SendPixels(device(0), frame)), client(1)).unwrap();
+        assert_eq!(response, None);
+        assert_eq!(server.sink().to_bytes()[..3], [1, 2, 3]);
+        assert_eq!(server.sink().get(2), Rgb::new(7, 7, 7));
+        // The strip is longer than the frame
+        assert_eq!(server.sink().get(3), Rgb::BLACK);
+    }
+
+    #[test]
+    fn test_server_status() {
+        let mut server = server();
+        server.set_health(40_000, -70);
+        server.handle(&datagram(ClientMessages::SetActive(device(1))), client(1)).unwrap();
+        let start = Instant::now();
+        for index in 0..10 {
+            let at = start + Duration::from_millis(100 * index);
+            server.handle_at(&datagram(ClientMessages::SendPixels(device(1), Frame::new(4))), client(1), at).unwrap();
+        }
+        server.handle_at(&datagram(ClientMessages::SendPixels(device(2), Frame::new(4))), client(2), start).unwrap();
+        assert!(server.handle(&[0, 0], client(2)).is_err());
+
+        let response = server.handle_at(&datagram(ClientMessages::GetStatus), client(3), start + Duration::from_secs(1)).unwrap();
+        let Some(ServerMessages::Status(status)) = response else {
+            panic!("Unexpected response {response:?}");
+        };
+        assert_eq!(status.frames_received, 11);
+        assert_eq!(status.frames_applied, 10);
+        assert_eq!(status.frames_dropped, 1);
+        assert_eq!(status.fps, 10.0);
+        assert_eq!(status.active, Some(device(1)));
+        assert_eq!((status.free_heap, status.rssi), (40_000, -70));
+        assert_eq!(status.last_error, crate::error::Error::InvalidFlag.to_string());
+
+        // The rate goes down once the frames stop
+        assert_eq!(server.status_at(start + Duration::from_secs(5)).fps, 2.0);
+        server.report_error("RMT timeout");
+        assert_eq!(server.status().last_error, "RMT timeout");
+    }
+
+    fn timed(server: &mut Server<Frame>, pts: Duration, level: u8, now: Instant) {
+        let mut frame = Frame::new(4);
+        frame.fill((level, level, level));
+        let message = ClientMessages::SendTimedPixels(device(1), pts.as_micros() as u64, frame);
+        server.handle_at(&datagram(message), client(1), now).unwrap();
+    }
+
+    #[test]
+    fn test_server_synchronized_playout() {
+        let mut server = server();
+        let start = server.started;
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let response = server.handle_at(&datagram(ClientMessages::TimeRequest(7)), client(1), at(1500)).unwrap();
+        assert_eq!(response, Some(ServerMessages::TimeResponse(7, 1_500_000, 1_500_000)));
+
+        server.handle_at(&datagram(ClientMessages::SetActive(device(1))), client(1), start).unwrap();
+        timed(&mut server, Duration::from_millis(200), 2, at(0));
+        timed(&mut server, Duration::from_millis(100), 1, at(0));
+        timed(&mut server, Duration::from_millis(300), 3, at(0));
+        assert_eq!(server.next_frame_in(at(50)), Some(Duration::from_millis(50)));
+        server.tick_at(at(99));
+        assert_eq!(server.sink().get(0), Rgb::BLACK);
+        server.tick_at(at(100));
+        assert_eq!(server.sink().get(0), Rgb::new(1, 1, 1));
+
+        // The frame of 200ms is skipped when ticking late
+        server.tick_at(at(350));
+        assert_eq!(server.sink().get(0), Rgb::new(3, 3, 3));
+        assert_eq!(server.next_frame_in(at(350)), None);
+
+        // A late frame is shown right away, unless it is older than the frame on the LEDs
+        timed(&mut server, Duration::from_millis(250), 4, at(400));
+        assert_eq!(server.sink().get(0), Rgb::new(3, 3, 3));
+        timed(&mut server, Duration::from_millis(380), 5, at(400));
+        assert_eq!(server.sink().get(0), Rgb::new(5, 5, 5));
+
+        let status = server.status_at(at(400));
+        assert_eq!((status.frames_received, status.frames_applied, status.frames_dropped), (5, 3, 2));
+    }
+
+    #[test]
+    fn test_server_playout_buffer() {
+        let mut server = server();
+        let start = server.started;
+        server.handle_at(&datagram(ClientMessages::SetActive(device(1))), client(1), start).unwrap();
+        for index in 0..=Server::<Frame>::MAX_PENDING_FRAMES as u64 {
+            timed(&mut server, Duration::from_secs(10 - index), index as u8, start);
+        }
+        // The furthest frame does not fit
+        assert_eq!(server.next_frame_in(start), Some(Duration::from_secs(2)));
+        assert_eq!(server.status_at(start).frames_dropped, 1);
+
+        // An untimed frame replaces the timed ones
+        server.handle_at(&datagram(ClientMessages::SendPixels(device(1), Frame::new(4))), client(1), start).unwrap();
+        assert_eq!(server.next_frame_in(start), None);
+        assert_eq!(server.status_at(start).frames_dropped, 1 + Server::<Frame>::MAX_PENDING_FRAMES as u32);
+    }
+
+    #[test]
+    fn test_server_rejects_malformed_datagrams() {
+        let mut server = server();
+        assert_eq!(server.handle(&[0, 0], client(1)), Err(crate::error::Error::InvalidFlag));
+        assert_eq!(server.handle(&[crate::constants::CLIENT_FLAG], client(1)), Err(crate::error::Error::InvalidMessageLength));
+        server.handle(&datagram(ClientMessages::SetActive(device(0))), client(1)).unwrap();
+        server.handle(&datagram(ClientMessages::SetPixel(device(0), 200, Rgb::new(1, 1, 1))), client(1)).unwrap();
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_server_sealed_messages() {
+        use crate::crypto::{Cipher, Key};
+        use crate::error::Error;
+
+        let key = Key::from([3; 32]);
+        let mut server = server();
+        let mut cipher = Cipher::new(4, &key);
+        server.set_cipher(Some(Cipher::new(4, &key)));
+
+        let hello = server.respond(&datagram(ClientMessages::Hello), client(1)).unwrap().unwrap();
+        assert!(!Cipher::is_sealed(&hello));
+        assert_eq!(server.respond(&datagram(ClientMessages::GetStatus), client(1)), Err(Error::NotSealed));
+        assert_eq!(server.status().last_error, "The datagram is not encrypted");
+
+        let request = cipher.seal(&datagram(ClientMessages::GetStatus)).unwrap();
+        let response = server.respond(&request, client(1)).unwrap().unwrap();
+        let response = ServerMessages::try_from(&cipher.open(&response).unwrap()[..]).unwrap();
+        assert!(matches!(response, ServerMessages::Status(_)));
+        assert_eq!(server.respond(&request, client(1)), Err(Error::Replayed));
+
+        let request = cipher.seal(&datagram(ClientMessages::SetActive(device(1)))).unwrap();
+        assert_eq!(server.respond(&request, client(1)), Ok(None));
+        assert_eq!(server.active(), Some(device(1)));
+    }
+
+    #[test]
+    fn test_server_group_pixels() {
+        let mut server = Server::new(
+            crate::frame::Frame::new(4),
+            Advertisement { led_count: 4, id: [7; 6], ..Default::default() },
+        );
+        let slices = vec![
+            crate::client::Slice { server: [1; 6], source: 0, len: 2, target: 0 },
+            crate::client::Slice { server: [7; 6], source: 2, len: 3, target: 2 },
+        ];
+        let pixels = Frame::from_bytes(&(0..15).collect::<Vec<u8>>()).unwrap();
+        server.handle(&datagram(ClientMessages::SetActive(device(0))), client(1)).unwrap();
+        server.handle(&datagram(ClientMessages::SendGroupPixels(device(0), slices.clone(), pixels)), client(1)).unwrap();
+        // Only the slice of this server is shown, cut to the length of the strip
+        assert_eq!(server.sink().to_bytes(), vec![0, 0, 0, 0, 0, 0, 6, 7, 8, 9, 10, 11]);
+
+        let mut white = Frame::new(5);
+        white.fill(Rgb::WHITE);
+        server.handle(&datagram(ClientMessages::SendGroupPixels(device(1), slices, white)), client(1)).unwrap();
+        assert_eq!(server.sink().get(2), Rgb::new(6, 7, 8));
+    }
+
+    #[test]
+    fn test_timeout_releases_segments() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut server = server();
+        server.set_timeout(Some(Duration::from_secs(5)));
+        server.set_segments(vec![Segment::new("left", 0, 2), Segment::new("right", 2, 2)]).unwrap();
+        server.handle_at(&datagram(ClientMessages::SetSegmentActive(device(2), 0)), client(2), at(0)).unwrap();
+        let red = Frame::from_bytes(&[255, 0, 0, 255, 0, 0]).unwrap();
+        server.handle_at(&datagram(ClientMessages::SendSegmentPixels(device(2), 0, red.clone())), client(2), at(0)).unwrap();
+
+        // The owner keeps its segment while it is sending
+        server.handle_at(&datagram(ClientMessages::SetSegmentActive(device(3), 0)), client(3), at(3)).unwrap();
+        assert_eq!(server.segment_owner(0), Some(device(2)));
+        server.handle_at(&datagram(ClientMessages::SendSegmentPixels(device(2), 0, red)), client(2), at(4)).unwrap();
+        server.tick_at(at(8));
+        server.handle_at(&datagram(ClientMessages::SetSegmentActive(device(3), 0)), client(3), at(8)).unwrap();
+        assert_eq!(server.segment_owner(0), Some(device(2)));
+        assert_eq!(server.sink().get(0), Rgb::new(255, 0, 0));
+
+        // A silent owner is released and the idle mode takes the zone back
+        server.tick_at(at(9));
+        assert_eq!(server.segment_owner(0), None);
+        assert!(server.is_idle());
+        server.tick_at(at(11));
+        assert_eq!(server.sink().get(0), Rgb::BLACK);
+        server.handle_at(&datagram(ClientMessages::SetSegmentActive(device(3), 0)), client(3), at(11)).unwrap();
+        assert_eq!(server.segment_owner(0), Some(device(3)));
+    }
+
+    fn lit_server(start: Instant) -> Server<crate::frame::Frame> {
+        let mut server = server();
+        server.set_timeout(Some(Duration::from_secs(5)));
+        server.handle_at(&datagram(ClientMessages::SetActive(device(1))), client(1), start).unwrap();
+        let mut frame = Frame::new(4);
+        frame.fill((200, 200, 200));
+        server.handle_at(&datagram(ClientMessages::SendPixels(device(1), frame)), client(1), start).unwrap();
+        server
+    }
+
+    #[test]
+    fn test_timeout_fades_to_off() {
+        let start = Instant::now();
+        let mut server = lit_server(start);
+        server.tick_at(start + Duration::from_secs(4));
+        assert_eq!(server.active(), Some(device(1)));
+        assert!(!server.is_idle());
+
+        server.tick_at(start + Duration::from_secs(6));
+        assert_eq!(server.active(), None);
+        assert!(server.is_idle());
+        assert_eq!(server.sink().get(0), Rgb::new(200, 200, 200));
+
+        server.tick_at(start + Duration::from_millis(6500));
+        assert_eq!(server.sink().get(3), Rgb::new(100, 100, 100));
+        server.tick_at(start + Duration::from_secs(7));
+        assert_eq!(server.sink().get(3), Rgb::new(0, 0, 0));
+
+        // The released device has to acquire the strip again
+        server.handle_at(&datagram(ClientMessages::SetPixel(device(1), 0, Rgb::new(9, 9, 9))), client(1), start + Duration::from_secs(8)).unwrap();
+        assert_eq!(server.sink().get(0), Rgb::new(0, 0, 0));
+        server.handle_at(&datagram(ClientMessages::SetActive(device(1))), client(1), start + Duration::from_secs(8)).unwrap();
+        assert!(!server.is_idle());
+    }
+
+    #[test]
+    fn test_heartbeat_keeps_device_active() {
+        let start = Instant::now();
+        let mut server = lit_server(start);
+        server.handle_at(&datagram(ClientMessages::Heartbeat(device(1))), client(1), start + Duration::from_secs(4)).unwrap();
+        // Only the active device can keep the strip
+        server.handle_at(&datagram(ClientMessages::Heartbeat(device(2))), client(1), start + Duration::from_secs(8)).unwrap();
+        server.tick_at(start + Duration::from_secs(8));
+        assert_eq!(server.active(), Some(device(1)));
+        server.tick_at(start + Duration::from_secs(9));
+        assert_eq!(server.active(), None);
+    }
+
+    #[test]
+    fn test_idle_modes() {
+        let start = Instant::now();
+        let mut server = lit_server(start);
+        server.set_idle_mode(IdleMode::Solid(Rgb::new(1, 2, 3)));
+        server.set_fade(Duration::ZERO);
+        server.tick_at(start + Duration::from_secs(5));
+        assert_eq!(server.sink().get(2), Rgb::new(1, 2, 3));
+
+        server.set_idle_mode(IdleMode::Breathe(Rgb::new(100, 0, 0)));
+        server.tick_at(start + Duration::from_secs(5));
+        assert_eq!(server.sink().get(0), Rgb::new(0, 0, 0));
+        server.tick_at(start + Duration::from_secs(7));
+        assert_eq!(server.sink().get(0), Rgb::new(100, 0, 0));
+
+        server.set_idle_mode(IdleMode::Rainbow);
+        server.tick_at(start + Duration::from_secs(5));
+        assert_eq!(server.sink().get(0), Rgb::new(255, 0, 0));
+        assert_eq!(server.sink().get(2), Rgb::new(0, 255, 255));
+    }
+
+    #[test]
+    fn test_no_timeout_by_default() {
+        let start = Instant::now();
+        let mut server = server();
+        server.handle_at(&datagram(ClientMessages::SetActive(device(1))), client(1), start).unwrap();
+        server.tick_at(start + Duration::from_secs(3600));
+        assert_eq!(server.active(), Some(device(1)));
+    }
+}
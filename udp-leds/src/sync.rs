@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// One exchange of time messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// Microseconds the server clock is ahead of the client clock
+    pub offset: i64,
+    /// Time spent on the network, without the processing time of the server
+    pub round_trip: Duration,
+}
+
+impl Sample {
+    /// Computes the sample from the four timestamps of an exchange
+    pub fn new(t1: u64, t2: u64, t3: u64, t4: u64) -> Self {
+        let (t1, t2, t3, t4) = (t1 as i64, t2 as i64, t3 as i64, t4 as i64);
+        Sample {
+            offset: ((t2 - t1) + (t3 - t4)) / 2,
+            round_trip: Duration::from_micros(((t4 - t1) - (t3 - t2)).max(0) as u64),
+        }
+    }
+}
+
+/**
+ * # Clock sync
+ * NTP-style estimation of the offset between the clock of a client and the clock of a server
+ *
+ * The clocks count microseconds, the client stamps its time request when sending it (t1), the server stamps
+ * it when receiving it (t2) and when answering (t3), and the client stamps the answer when receiving it (t4)
+ * Assuming both legs of the round trip take as long, the server clock is ahead by ((t2 - t1) + (t3 - t4)) / 2
+ *
+ * The last samples are kept and the one with the shortest round trip is trusted,
+ * as it is the one the least skewed by asymmetric delays
+ */
+#[derive(Debug, Clone)]
+pub struct ClockSync {
+    epoch: Instant,
+    samples: VecDeque<Sample>,
+}
+
+impl Default for ClockSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClockSync {
+    /// Number of samples kept
+    pub const MAX_SAMPLES: usize = 8;
+
+    /// Starts the clock of the client, without any sample
+    pub fn new() -> Self {
+        ClockSync {
+            epoch: Instant::now(),
+            samples: VecDeque::with_capacity(Self::MAX_SAMPLES),
+        }
+    }
+
+    /// The clock of the client at the given instant, in microseconds
+    pub fn local(&self, at: Instant) -> u64 {
+        at.saturating_duration_since(self.epoch).as_micros() as u64
+    }
+
+    /// Adds a sample, forgetting the oldest one when there are too many
+    pub fn add(&mut self, sample: Sample) {
+        if self.samples.len() == Self::MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Forgets the samples, after a change of server
+    pub fn reset(&mut self) {
+        self.samples.clear();
+    }
+
+    /// The most trusted sample, `None` until a sample was added
+    pub fn best(&self) -> Option<Sample> {
+        self.samples.iter().min_by_key(|sample| sample.round_trip).copied()
+    }
+
+    /// The clock of the server at the given instant, in microseconds
+    pub fn to_server(&self, at: Instant) -> Option<u64> {
+        let offset = self.best()?.offset;
+        Some((self.local(at) as i64 + offset).max(0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        // The server is 1000µs ahead, each leg takes 50µs and the server takes 10µs to answer
+        let sample = Sample::new(100, 1150, 1160, 210);
+        assert_eq!(sample.offset, 1000);
+        assert_eq!(sample.round_trip, Duration::from_micros(100));
+    }
+
+    #[test]
+    fn test_best_sample() {
+        let mut sync = ClockSync::new();
+        let at = Instant::now();
+        assert_eq!(sync.to_server(at), None);
+
+        sync.add(Sample { offset: 5000, round_trip: Duration::from_millis(20) });
+        sync.add(Sample { offset: 1000, round_trip: Duration::from_millis(2) });
+        sync.add(Sample { offset: 3000, round_trip: Duration::from_millis(10) });
+        assert_eq!(sync.to_server(at), Some(sync.local(at) + 1000));
+
+        for _ in 0..ClockSync::MAX_SAMPLES {
+            sync.add(Sample { offset: -10, round_trip: Duration::from_millis(5) });
+        }
+        assert_eq!(sync.best().unwrap().offset, -10);
+        sync.reset();
+        assert_eq!(sync.best(), None);
+    }
+}
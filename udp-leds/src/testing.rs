@@ -0,0 +1,617 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::{
+    client::ClientMessages,
+    config::Config,
+    constants::MAX_SEALED_LENGTH,
+    device::DeviceId,
+    error::Error,
+    frame::Frame,
+    layout::Layout,
+    ota::Updater,
+    segment::Segment,
+    server::{Advertisement, Server},
+};
+
+/// A message received by the mock server
+#[derive(Debug, Clone, PartialEq)]
+pub struct Received {
+    /// Time elapsed between the start of the mock server and the reception of the message
+    pub at: Duration,
+    pub from: SocketAddr,
+    pub message: ClientMessages,
+    /// Whether the message was dropped by an injected fault instead of being handled
+    pub dropped: bool,
+}
+
+/// Faults injected by the mock server
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Faults {
+    /// Number of upcoming datagrams to drop
+    pub drop_next: usize,
+    /// Delay applied before handling each datagram
+    pub delay: Duration,
+    /// Ignore the set active messages
+    pub refuse_set_active: bool,
+}
+
+#[derive(Debug)]
+struct State {
+    server: Server<Frame>,
+    received: Vec<Received>,
+    faults: Faults,
+    flash: Arc<Mutex<Flash>>,
+    rebooted: bool,
+}
+
+/// Firmware images of the mock server, kept in memory
+#[derive(Debug, Default)]
+struct Flash {
+    writing: Vec<u8>,
+    committed: Option<Vec<u8>>,
+}
+
+impl Updater for Arc<Mutex<Flash>> {
+    fn begin(&mut self, _size: u32) -> Result<(), Error> {
+        self.lock().unwrap().writing.clear();
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.lock().unwrap().writing.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), Error> {
+        let mut flash = self.lock().unwrap();
+        flash.committed = Some(std::mem::take(&mut flash.writing));
+        Ok(())
+    }
+
+    fn abort(&mut self) {
+        self.lock().unwrap().writing.clear();
+    }
+}
+
+/**
+ * # Mock server
+ * In-process LED server bound to an ephemeral loopback port, for the integration tests of the clients
+ *
+ * Every decoded message is recorded with its reception time and the usual server logic is applied to them
+ * Faults can be injected to check how the clients behave with a lossy network or an unwilling server
+ *
+ * ```
+ * use std::time::Duration;
+ * use udp_leds::{testing::MockServer, Client, DeviceId, Frame, Rgb};
+ *
+ * let server = MockServer::start(4).unwrap();
+ * let mut client = Client::bind("127.0.0.1:0").unwrap();
+ * client.connect(server.addr()).unwrap();
+ * client.acquire(DeviceId::new(1).unwrap()).unwrap();
+ *
+ * let mut frame = Frame::new(4);
+ * frame.fill(Rgb::new(1, 2, 3));
+ * client.send_frame(&frame).unwrap();
+ *
+ * assert!(server.wait_for_frames(1, Duration::from_secs(1)));
+ * server.assert_frame(0, &frame);
+ * ```
+ */
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<(Mutex<State>, Condvar)>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Starts a mock server driving `led_count` LEDs
+    pub fn start(led_count: u16) -> Result<Self, Error> {
+        Self::with_advertisement(Advertisement {
+            led_count,
+            name: "mock".to_string(),
+            ..Default::default()
+        })
+    }
+
+    /// Starts a mock server advertising itself with the given advertisement
+    pub fn with_advertisement(advertisement: Advertisement) -> Result<Self, Error> {
+        let socket = UdpSocket::bind("127.0.0.1:0")?;
+        socket.set_read_timeout(Some(Duration::from_millis(10)))?;
+        let addr = socket.local_addr()?;
+
+        let led_count = match advertisement.led_count {
+            0 => crate::constants::MAX_LED_COUNT,
+            count => count as usize,
+        };
+        let flash = Arc::new(Mutex::new(Flash::default()));
+        let mut server = Server::new(Frame::new(led_count), advertisement);
+        server.set_updater(flash.clone());
+        let state = Arc::new((
+            Mutex::new(State {
+                server,
+                received: Vec::new(),
+                faults: Faults::default(),
+                flash,
+                rebooted: false,
+            }),
+            Condvar::new(),
+        ));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = std::thread::spawn({
+            let state = state.clone();
+            let stop = stop.clone();
+            move || serve(socket, state, stop)
+        });
+
+        Ok(MockServer {
+            addr,
+            state,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// Address the mock server listens on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Replaces the injected faults
+    pub fn set_faults(&self, faults: Faults) {
+        self.state.0.lock().unwrap().faults = faults;
+    }
+
+    /// Drops the next `count` datagrams
+    pub fn drop_next(&self, count: usize) {
+        self.state.0.lock().unwrap().faults.drop_next = count;
+    }
+
+    /// Requires the clients to seal their messages with the keys of the cipher
+    #[cfg(feature = "crypto")]
+    pub fn set_cipher(&self, cipher: Option<crate::crypto::Cipher>) {
+        self.state.0.lock().unwrap().server.set_cipher(cipher);
+    }
+
+    /// Delays the handling of every datagram
+    pub fn set_delay(&self, delay: Duration) {
+        self.state.0.lock().unwrap().faults.delay = delay;
+    }
+
+    /// Ignores the set active messages
+    pub fn refuse_set_active(&self, refuse: bool) {
+        self.state.0.lock().unwrap().faults.refuse_set_active = refuse;
+    }
+
+    /// Every message received so far
+    pub fn received(&self) -> Vec<Received> {
+        self.state.0.lock().unwrap().received.clone()
+    }
+
+    /// The frames received so far and not dropped, whatever the device that sent them
+    pub fn frames(&self) -> Vec<Frame> {
+        let state = self.state.0.lock().unwrap();
+        let len = state.server.sink().len();
+        state
+            .received
+            .iter()
+            .filter(|received| !received.dropped)
+            .filter_map(|received| match &received.message {
+                ClientMessages::SendPixels(_, pixels) | ClientMessages::SendTimedPixels(_, _, pixels) => {
+                    let mut frame = Frame::new(len);
+                    frame.copy_at(0, pixels.as_slice());
+                    Some(frame)
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Current state of the emulated LEDs
+    pub fn pixels(&self) -> Frame {
+        self.state.0.lock().unwrap().server.sink().clone()
+    }
+
+    /// The device currently allowed to update the LEDs
+    pub fn active(&self) -> Option<DeviceId> {
+        self.state.0.lock().unwrap().server.active()
+    }
+
+    /// The clock of the server the presentation timestamps refer to, in microseconds
+    pub fn clock(&self) -> u64 {
+        self.state.0.lock().unwrap().server.clock_at(Instant::now())
+    }
+
+    /// The config of the server, changed by the clients with set config messages
+    pub fn config(&self) -> Config {
+        self.state.0.lock().unwrap().server.config()
+    }
+
+    /// The segment table of the server, changed by the clients with set segments messages
+    pub fn segments(&self) -> Vec<Segment> {
+        self.state.0.lock().unwrap().server.segments().to_vec()
+    }
+
+    /// The layout of the server, changed by the clients with set layout messages
+    pub fn layout(&self) -> Layout {
+        self.state.0.lock().unwrap().server.layout().clone()
+    }
+
+    /// The firmware image committed by the clients, booted on the next restart
+    pub fn firmware(&self) -> Option<Vec<u8>> {
+        self.state.0.lock().unwrap().flash.lock().unwrap().committed.clone()
+    }
+
+    /// Whether a client restarted the server on a committed image
+    pub fn rebooted(&self) -> bool {
+        self.state.0.lock().unwrap().rebooted
+    }
+
+    /// Waits until at least `count` messages were received, returns false on timeout
+    pub fn wait_for_messages(&self, count: usize, timeout: Duration) -> bool {
+        self.wait(timeout, |state| state.received.len() >= count)
+    }
+
+    /// Waits until at least `count` frames were received, returns false on timeout
+    pub fn wait_for_frames(&self, count: usize, timeout: Duration) -> bool {
+        self.wait(timeout, |state| {
+            state
+                .received
+                .iter()
+                .filter(|received| !received.dropped && matches!(received.message, ClientMessages::SendPixels(..) | ClientMessages::SendTimedPixels(..)))
+                .count()
+                >= count
+        })
+    }
+
+    /// Panics if the frame number `index` is not equal to `expected`
+    ///
+    /// Only the first `expected.len()` pixels are compared
+    #[track_caller]
+    pub fn assert_frame(&self, index: usize, expected: &Frame) {
+        let frames = self.frames();
+        let Some(frame) = frames.get(index) else {
+            panic!("Frame {index} was not received, only {} frames were", frames.len());
+        };
+        assert_eq!(&frame[..expected.len()], expected.as_slice(), "Frame {index} differs from the expected frame");
+    }
+
+    /// Panics if the messages received differ from the expected ones, ignoring their timestamps
+    #[track_caller]
+    pub fn assert_messages(&self, expected: &[ClientMessages]) {
+        let received: Vec<ClientMessages> = self.received().into_iter().map(|received| received.message).collect();
+        assert_eq!(received, expected);
+    }
+
+    fn wait(&self, timeout: Duration, done: impl Fn(&State) -> bool) -> bool {
+        let (state, condvar) = &*self.state;
+        let guard = state.lock().unwrap();
+        let (_guard, result) = condvar.wait_timeout_while(guard, timeout, |state| !done(state)).unwrap();
+        !result.timed_out()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn serve(socket: UdpSocket, state: Arc<(Mutex<State>, Condvar)>, stop: Arc<AtomicBool>) {
+    let start = Instant::now();
+    let mut buf = [0; MAX_SEALED_LENGTH];
+    while !stop.load(Ordering::Relaxed) {
+        // Shows the timed frames, the socket timeout keeps the loop turning
+        state.0.lock().unwrap().server.tick();
+        let Ok((size, from)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        let at = start.elapsed();
+        let Ok((message, sealed)) = state.0.lock().unwrap().server.receive(&buf[..size]) else {
+            continue;
+        };
+
+        let delay = state.0.lock().unwrap().faults.delay;
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+
+        let response = {
+            let (state, condvar) = &*state;
+            let mut state = state.lock().unwrap();
+            let refused = state.faults.refuse_set_active && matches!(message, ClientMessages::SetActive(_));
+            let dropped = state.faults.drop_next > 0;
+            if dropped {
+                state.faults.drop_next -= 1;
+            }
+            state.received.push(Received { at, from, message: message.clone(), dropped });
+            condvar.notify_all();
+            if dropped || refused {
+                continue;
+            }
+            let response = match state.server.apply_at(message, from, Instant::now()) {
+                Ok(Some(response)) => state.server.reply(response, sealed).ok(),
+                _ => None,
+            };
+            state.rebooted |= state.server.take_reboot();
+            response
+        };
+
+        if let Some(response) = response {
+            let _ = socket.send_to(&response, from);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::Client, color::Rgb, config::ColorOrder};
+
+    fn device(id: u8) -> DeviceId {
+        DeviceId::new(id).unwrap()
+    }
+
+    fn connected(server: &MockServer) -> Client {
+        let mut client = Client::bind("127.0.0.1:0").unwrap();
+        client.connect(server.addr()).unwrap();
+        client
+    }
+
+    #[test]
+    fn test_records_messages() {
+        let server = MockServer::start(2).unwrap();
+        let mut client = connected(&server);
+        client.acquire(device(4)).unwrap();
+        client.set_pixel(1, (5, 6, 7)).unwrap();
+
+        assert!(server.wait_for_messages(3, Duration::from_secs(1)));
+        server.assert_messages(&[
+            ClientMessages::Hello,
+            ClientMessages::SetActive(device(4)),
+            ClientMessages::SetPixel(device(4), 1, Rgb::new(5, 6, 7)),
+        ]);
+        let received = server.received();
+        assert!(received.windows(2).all(|pair| pair[0].at <= pair[1].at));
+        assert_eq!(server.active(), Some(device(4)));
+        assert_eq!(server.pixels().get(1), Rgb::new(5, 6, 7));
+    }
+
+    #[test]
+    fn test_claimed_devices() {
+        let server = MockServer::start(2).unwrap();
+        let first = connected(&server);
+        let mut second = connected(&server);
+        let device = first.claim().unwrap();
+        assert_ne!(second.claim().unwrap(), device);
+
+        // A restarted client gets its id back from its token and takes it over
+        second.set_token(first.token());
+        assert_eq!(second.claim(), Ok(device));
+        second.acquire(device).unwrap();
+        assert!(server.wait_for_messages(6, Duration::from_secs(1)));
+        assert_eq!(server.active(), Some(device));
+    }
+
+    #[test]
+    fn test_status() {
+        let server = MockServer::start(2).unwrap();
+        let mut client = connected(&server);
+        client.acquire(device(4)).unwrap();
+        client.send_frame(&Frame::new(2)).unwrap();
+        assert!(server.wait_for_frames(1, Duration::from_secs(1)));
+
+        let status = client.status().unwrap();
+        assert_eq!(status.frames_applied, 1);
+        assert_eq!(status.active, Some(device(4)));
+    }
+
+    #[test]
+    fn test_segments() {
+        let server = MockServer::start(6).unwrap();
+        let mut top = connected(&server);
+        let mut bottom = connected(&server);
+        let segments = [Segment::new("top", 0, 3), Segment { reversed: true, ..Segment::new("bottom", 3, 3) }];
+        assert_eq!(top.set_segments(&segments), Err(Error::InvalidSegment));
+        top.acquire(device(1)).unwrap();
+        top.set_segments(&segments).unwrap();
+        assert_eq!(server.segments(), segments);
+        assert_eq!(top.set_segments(&[Segment::new("long", 0, 7)]), Err(Error::InvalidSegment));
+        assert_eq!(bottom.find_segment("bottom"), Ok(1));
+        assert_eq!(bottom.find_segment("left"), Err(Error::InvalidSegment));
+
+        top.acquire_segment(device(1), 0).unwrap();
+        bottom.acquire_segment(device(2), 1).unwrap();
+        top.send_segment(0, &Frame::from_bytes(&[1, 1, 1]).unwrap()).unwrap();
+        bottom.send_segment(1, &Frame::from_bytes(&[2, 2, 2, 3, 3, 3]).unwrap()).unwrap();
+        assert!(server.wait_for_messages(12, Duration::from_secs(1)));
+        let mut expected = Frame::new(6);
+        expected.set(0, Rgb::new(1, 1, 1));
+        expected.set(4, Rgb::new(3, 3, 3));
+        expected.set(5, Rgb::new(2, 2, 2));
+        assert_eq!(server.pixels(), expected);
+    }
+
+    #[test]
+    fn test_layout() {
+        use crate::layout::{Corner, Direction, Rectangle};
+
+        let server = MockServer::start(20).unwrap();
+        let mut client = connected(&server);
+        assert_eq!(client.layout(), Ok(Layout::Unknown));
+        let layout = Layout::Rectangle(Rectangle {
+            top: 6,
+            right: 3,
+            bottom: 6,
+            left: 3,
+            start: Corner::BottomRight,
+            direction: Direction::CounterClockwise,
+            corner_gap: 0,
+            bottom_gap: 2,
+        });
+        assert_eq!(client.set_layout(&layout), Err(Error::InvalidLayout));
+        client.acquire(device(1)).unwrap();
+        client.set_layout(&layout).unwrap();
+        assert_eq!(server.layout(), layout);
+        assert_eq!(client.layout(), Ok(layout.clone()));
+        assert_eq!(client.set_layout(&Layout::Points(vec![Default::default(); 21])), Err(Error::InvalidLayout));
+        assert_eq!(server.layout(), layout);
+    }
+
+    #[test]
+    fn test_firmware_update() {
+        use crate::ota::MAX_CHUNK_LENGTH;
+
+        let server = MockServer::start(2).unwrap();
+        let mut client = connected(&server);
+        client.acquire(device(1)).unwrap();
+        let image: Vec<u8> = (0..MAX_CHUNK_LENGTH * 3 + 10).map(|index| index as u8).collect();
+        let mut progress = Vec::new();
+        client.update_firmware(&image, |offset, size| progress.push((offset, size))).unwrap();
+        assert_eq!(progress.first(), Some(&(0, image.len() as u32)));
+        assert_eq!(progress.last(), Some(&(image.len() as u32, image.len() as u32)));
+        assert_eq!(server.firmware(), Some(image));
+        assert!(server.rebooted());
+    }
+
+    #[test]
+    fn test_firmware_update_resumes() {
+        use crate::ota::MAX_CHUNK_LENGTH;
+
+        let server = MockServer::start(2).unwrap();
+        let mut client = connected(&server);
+        client.acquire(device(1)).unwrap();
+        let image = vec![3; MAX_CHUNK_LENGTH * 2 + 1];
+        // The server goes silent once the first chunk is received
+        let result = client.update_firmware(&image, |offset, _| {
+            if offset == MAX_CHUNK_LENGTH as u32 {
+                server.drop_next(Client::UPDATE_ATTEMPTS);
+            }
+        });
+        assert_eq!(result, Err(Error::Timeout));
+        assert_eq!(server.firmware(), None);
+
+        client.update_firmware(&image, |_, _| ()).unwrap();
+        assert_eq!(server.firmware(), Some(image));
+        let first_chunks = server
+            .received()
+            .iter()
+            .filter(|received| matches!(received.message, ClientMessages::OtaChunk(0, _)))
+            .count();
+        assert_eq!(first_chunks, 1);
+    }
+
+    #[test]
+    fn test_config() {
+        let server = MockServer::start(2).unwrap();
+        let mut client = connected(&server);
+        let mut config = client.config().unwrap();
+        assert_eq!((config.led_count, config.name.as_str()), (2, "mock"));
+
+        // Only the client of the active device changes the config
+        config.led_count = 5;
+        assert_eq!(client.set_config(&config), Err(Error::InvalidConfig));
+        client.acquire(device(1)).unwrap();
+        config.color_order = ColorOrder::Grb;
+        client.set_config(&config).unwrap();
+        assert_eq!(server.config(), config);
+        assert_eq!(server.pixels().len(), 5);
+        assert_eq!(client.set_config(&Config { name: String::new(), ..config }), Err(Error::InvalidConfig));
+    }
+
+    #[test]
+    fn test_synchronized_frames() {
+        let server = MockServer::start(2).unwrap();
+        let mut client = connected(&server);
+        assert_eq!(client.send_frame_at(&Frame::new(2), Instant::now()), Err(Error::NoDevice));
+        client.acquire(device(1)).unwrap();
+        assert_eq!(client.send_frame_at(&Frame::new(2), Instant::now()), Err(Error::NotSynchronized));
+
+        let sample = client.sync_clock(4).unwrap();
+        assert!(sample.round_trip < Duration::from_millis(50));
+        let estimated = client.clock().to_server(Instant::now()).unwrap();
+        assert!(estimated.abs_diff(server.clock()) < 20_000, "{estimated} {}", server.clock());
+
+        let mut frame = Frame::new(2);
+        frame.fill(Rgb::RED);
+        client.send_frame_at(&frame, Instant::now() + Duration::from_millis(300)).unwrap();
+        assert!(server.wait_for_frames(1, Duration::from_secs(1)));
+        assert_eq!(server.pixels(), Frame::new(2));
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(server.pixels(), frame);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_sealed_messages() {
+        use crate::crypto::{Cipher, Key};
+
+        let key = Key::from([7; 32]);
+        let server = MockServer::start(2).unwrap();
+        server.set_cipher(Some(Cipher::new(1, &key)));
+
+        // Plain clients can still discover the server but not use it
+        let mut plain = connected(&server);
+        plain.acquire(device(2)).unwrap();
+        assert_eq!(plain.status(), Err(Error::Timeout));
+        assert_eq!(server.received().len(), 1);
+
+        let mut client = Client::bind("127.0.0.1:0").unwrap();
+        client.set_cipher(server.addr(), Cipher::new(1, &Key::from([8; 32]))).unwrap();
+        assert_eq!(client.connect(server.addr()).err(), Some(Error::Timeout));
+        client.set_cipher(server.addr(), Cipher::new(1, &key)).unwrap();
+        client.connect(server.addr()).unwrap();
+        let device = client.claim().unwrap();
+        client.acquire(device).unwrap();
+        let mut frame = Frame::new(2);
+        frame.fill(Rgb::BLUE);
+        client.send_frame(&frame).unwrap();
+        assert!(server.wait_for_frames(1, Duration::from_secs(1)));
+        assert_eq!(server.pixels(), frame);
+        assert_eq!(server.active(), Some(device));
+    }
+
+    #[test]
+    fn test_drop_fault() {
+        let server = MockServer::start(2).unwrap();
+        server.drop_next(1);
+        let mut client = Client::bind("127.0.0.1:0").unwrap();
+        assert_eq!(client.connect(server.addr()), Err(Error::Timeout));
+        assert!(server.received()[0].dropped);
+        client.connect(server.addr()).unwrap();
+    }
+
+    #[test]
+    fn test_refuse_set_active_fault() {
+        let server = MockServer::start(2).unwrap();
+        server.refuse_set_active(true);
+        let mut client = connected(&server);
+        client.acquire(device(4)).unwrap();
+        let mut frame = Frame::new(2);
+        frame.fill((1, 1, 1));
+        client.send_frame(&frame).unwrap();
+
+        assert!(server.wait_for_frames(1, Duration::from_secs(1)));
+        server.assert_frame(0, &frame);
+        assert_eq!(server.active(), None);
+        assert_eq!(server.pixels(), Frame::new(2));
+    }
+
+    #[test]
+    fn test_delay_fault() {
+        let server = MockServer::start(2).unwrap();
+        server.set_delay(Duration::from_millis(100));
+        let start = Instant::now();
+        connected(&server);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,38 @@
+use std::io::Read;
+
+/// Appends a LEB128 varint
+pub(crate) fn write(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Reads a LEB128 varint whose first byte may already have been read
+///
+/// Returns `None` if the varint is truncated or too long
+pub(crate) fn read(inner: &mut impl Read, first: Option<u8>) -> Option<u64> {
+    let mut next = || {
+        let mut byte = [0; 1];
+        inner.read_exact(&mut byte).ok()?;
+        Some(byte[0])
+    };
+    let mut byte = match first {
+        Some(byte) => byte,
+        None => next()?,
+    };
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        byte = next()?;
+    }
+    None
+}
@@ -1,3 +1,18 @@
+/// LED count until one is set over the protocol
 pub const LED_COUNT: u8 = 10;
+/// Number of LEDs the signal buffer is sized for, the LED count set over the protocol is capped to it
+pub const MAX_LED_COUNT: usize = 150;
+/// GPIO of the data line until one is set over the protocol
+pub const OUTPUT_PIN: u8 = 32;
+pub const MAX_FPS: u8 = 30;
+/// Milliseconds of silence after which the active device is released and the LEDs turn off
+pub const RECEIVER_TIMEOUT_MS: u64 = 10_000;
+/// Milliseconds between two updates of the free heap and the RSSI reported in the status
+pub const HEALTH_INTERVAL_MS: u64 = 1_000;
+/// Key the clients have to encrypt their messages with (64 hexadecimal digits), given at build time
+pub const KEY: Option<&'static str> = option_env!("LED_KEY");
+/// Id of the key, to be bumped when the key is rotated
+pub const KEY_ID: u8 = 0;
+pub const DEVICE_NAME: &'static str = "ambilight";
 pub const WIFI_SSID: &'static str = "SFR_FA68";
 pub const WIFI_PASS: &'static str = "7qcv59ikth9mgh55mesz";
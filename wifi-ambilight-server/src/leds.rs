@@ -6,6 +6,9 @@ use std::{
     sync::{Arc, Mutex},
     time::Duration,
 };
+use log::warn;
+use udp_leds::config::{ColorOrder, Config};
+use udp_leds::{LedSink, Rgb};
 
 const T0H: Duration = Duration::from_nanos(350);
 const T1H: Duration = Duration::from_nanos(700);
@@ -13,6 +16,14 @@ const T0L: Duration = Duration::from_nanos(800);
 const T1L: Duration = Duration::from_nanos(600);
 const RESET: Duration = Duration::from_millis(1);
 
+/// How the pixels are sent to the strip, set by the config
+#[derive(Debug, Clone, Copy)]
+struct Output {
+    count: usize,
+    order: ColorOrder,
+}
+
+/// Pixels of a strip of at most `L` LEDs
 #[derive(Debug, Clone)]
 pub struct Leds<const L: usize>
 where
@@ -20,6 +31,7 @@ where
     [(); L * 3 * 8]:,
 {
     pixels: Arc<Mutex<[u8; L * 3]>>,
+    output: Arc<Mutex<Output>>,
 }
 
 impl<const L: usize> Leds<L>
@@ -30,12 +42,29 @@ where
     pub fn new() -> Self {
         Self {
             pixels: Arc::new(Mutex::new([255; L * 3])),
+            output: Arc::new(Mutex::new(Output { count: L, order: ColorOrder::default() })),
+        }
+    }
+
+    /// Drives the first `count` LEDs only, with the channels in the given order
+    pub fn configure(&self, count: usize, order: ColorOrder) {
+        if count > L {
+            warn!("The strip is limited to {L} LEDs, {count} were asked");
         }
+        *self.output.lock().unwrap() = Output { count: count.min(L), order };
     }
 
     pub fn set(&self, bytes: &[u8]) {
         let mut pixels = self.pixels.lock().unwrap();
-        pixels.copy_from_slice(&bytes[..{ L * 3 }]);
+        let len = bytes.len().min(L * 3);
+        pixels[..len].copy_from_slice(&bytes[..len]);
+    }
+
+    pub fn set_pixel(&self, index: usize, color: Rgb) {
+        if index < L {
+            let mut pixels = self.pixels.lock().unwrap();
+            pixels[index * 3..index * 3 + 3].copy_from_slice(&<[u8; 3]>::from(color));
+        }
     }
 
     pub fn to_rmt_signal(&self, freq: Hertz) -> FixedLengthSignal<{ L * 3 * 8 }> {
@@ -48,8 +77,14 @@ where
             Pulse::new_with_duration(freq, PinState::High, &T0H).unwrap(),
             Pulse::new_with_duration(freq, PinState::Low, &T0L).unwrap(),
         );
+        let output = *self.output.lock().unwrap();
         let pixels = self.pixels.lock().unwrap();
-        for (i, byte) in pixels.iter().enumerate() {
+        // The LEDs past the configured count are kept black
+        let bytes = pixels.chunks_exact(3).enumerate().flat_map(|(index, pixel)| match index < output.count {
+            true => output.order.apply(Rgb::new(pixel[0], pixel[1], pixel[2])),
+            false => [0; 3],
+        });
+        for (i, byte) in bytes.enumerate() {
             for bit in 0..8 {
                 let bit = byte & (1 << bit) != 0;
                 let pair = if bit { one } else { zero };
@@ -70,6 +105,28 @@ where
     }
 }
 
+impl<const L: usize> LedSink for Leds<L>
+where
+    [(); L * 3]:,
+    [(); L * 3 * 8]:,
+{
+    fn set_pixels(&mut self, pixels: &[u8]) {
+        self.set(pixels);
+    }
+
+    fn set_pixel(&mut self, index: usize, color: Rgb) {
+        Leds::set_pixel(self, index, color);
+    }
+
+    fn configure(&mut self, config: &Config) {
+        Leds::configure(self, config.led_count as usize, config.color_order);
+    }
+
+    fn max_led_count(&self) -> usize {
+        L
+    }
+}
+
 pub fn led_update_loop<const L: usize>(leds: Leds<L>, rmt: TxRmtDriver) -> !
 where
     [(); L * 3]:,
@@ -80,6 +137,6 @@ where
     loop {
         let signal = leds.to_rmt_signal(freq);
         rmt.start_blocking(&signal).unwrap();
-        std::thread::sleep(Duration::from_millis(1000));
+        std::thread::sleep(Duration::from_millis(1000 / crate::constants::MAX_FPS as u64));
     }
 }
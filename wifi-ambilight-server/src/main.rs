@@ -3,65 +3,100 @@ mod constants;
 mod error;
 pub mod leds;
 mod logging;
+mod ota;
+mod storage;
 mod wifi;
 
-use std::net::UdpSocket;
+use std::net::{Ipv4Addr, UdpSocket};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use esp_idf_hal::gpio::PinDriver;
+use esp_idf_hal::gpio::{AnyOutputPin, PinDriver};
 use esp_idf_hal::peripheral::Peripheral;
 use esp_idf_hal::peripherals::Peripherals;
 use esp_idf_hal::rmt::config::{Loop, TransmitConfig};
-use esp_idf_hal::rmt::{PinState, TxRmtDriver};
+use esp_idf_hal::rmt::{PinState, TxRmtDriver, CHANNEL0};
 use esp_idf_svc::eventloop::EspSystemEventLoop;
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_sys::EspError;
 use log::{debug, error, info, warn};
-
-const CLIENT_FLAG_BYTE: u8 = 0b0110_1011;
-const SERVER_FLAG_BYTE: u8 = 0b1110_0110;
-const INSTRUCTION_MASK: u8 = 0b11000000;
-const DEVICE_ID_MASK: u8 = 0b000111111;
+use udp_leds::config::{ColorOrder, Config};
+use udp_leds::constants::MAX_SEALED_LENGTH;
+use udp_leds::crypto::{Cipher, Key};
+use udp_leds::mdns;
+use udp_leds::server::{Advertisement, Server};
 
 use log::{Level, Metadata, Record};
 
 use crate::logging::SimpleLogger;
 
-const fn is_hello(header: u8) -> bool {
-    header & INSTRUCTION_MASK == 0b11000000
-}
-
-const fn is_set_active(header: u8) -> bool {
-    header & INSTRUCTION_MASK == 0b01000000
-}
-
 fn main() {
     esp_idf_sys::link_patches();
     let sysloop = EspSystemEventLoop::take().unwrap();
     log::set_logger(&SimpleLogger).unwrap();
     info!("Starting up");
 
+    // The config set over the protocol replaces the constants once stored
+    let mut storage = storage::Storage::new(EspDefaultNvsPartition::take().unwrap()).expect("Couldn't open the storage");
+    let mut config = storage.load_config().unwrap_or_else(|| Config {
+        led_count: constants::LED_COUNT as u16,
+        color_order: ColorOrder::default(),
+        output_pin: constants::OUTPUT_PIN,
+        name: constants::DEVICE_NAME.to_string(),
+    });
+    info!("Config : {config:?}");
+
     // Retrieve the output pin and channel from peripherals.
     let peripherals = Peripherals::take().unwrap();
-    let channel = peripherals.rmt.channel0;
-    let rmt_pin = peripherals.pins.gpio32;
+    let mut channel = peripherals.rmt.channel0;
     let modem = peripherals.modem;
     debug!("Peripherals taken");
 
     let _wifi = wifi::setup_wifi(modem, sysloop);
     debug!("Wifi initialized");
 
+    let id = wifi::mac_address();
+    let ip = wifi::wait_for_ip(&_wifi);
+    let (name, led_count) = (config.name.clone(), config.led_count);
+    std::thread::spawn(move || {
+        let service = mdns::Service::new(&name, id, Some(ip), udp_leds::constants::PORT, led_count);
+        let responder = match mdns::Responder::new(service) {
+            Ok(responder) => responder,
+            Err(err) => {
+                error!("Couldn't start the mDNS responder : {err}");
+                return;
+            }
+        };
+        if let Err(err) = responder.announce() {
+            warn!("Couldn't announce the mDNS service : {err}");
+        }
+        if let Err(err) = responder.run() {
+            error!("mDNS responder stopped : {err}");
+        }
+    });
+    debug!("mDNS responder started on {ip}");
+
     // Initializing the pixels
-    let leds = leds::Leds::<{ constants::LED_COUNT as usize }>::new();
+    let leds = leds::Leds::<{ constants::MAX_LED_COUNT }>::new();
     debug!("Pixels initialized");
 
     // Initializing the rmt transmitter
     // Prepare the config.
-    let config = TransmitConfig::new()
+    let rmt_config = TransmitConfig::new()
         .clock_divider(1)
         .idle(Some(PinState::Low))
         .looping(Loop::None);
 
-    // Create the transmitter
-    let rmt = TxRmtDriver::new(channel, rmt_pin, &config).unwrap();
+    // Create the transmitter, a pin that cannot drive the strip would otherwise stop the controller on every boot
+    // The channel is only cloned for the first attempt, which gives it back on failure
+    let rmt = match new_rmt(unsafe { channel.clone_unchecked() }, config.output_pin, &rmt_config) {
+        Ok(rmt) => rmt,
+        Err(err) => {
+            error!("Couldn't drive GPIO {} : {err}, falling back to GPIO {}", config.output_pin, constants::OUTPUT_PIN);
+            config.output_pin = constants::OUTPUT_PIN;
+            new_rmt(channel, constants::OUTPUT_PIN, &rmt_config).unwrap()
+        }
+    };
     debug!("RMT initialized");
 
     std::thread::spawn({
@@ -71,48 +106,125 @@ fn main() {
 
     debug!("Thread created");
 
-    let udp = UdpSocket::bind("0.0.0.0:52772").expect("Couldn't create the UDP socket");
-    let mut buf: [u8; 780] = [0; 780];
-    let mut active: u8 = 255;
+    let udp = UdpSocket::bind(("0.0.0.0", udp_leds::constants::PORT)).expect("Couldn't create the UDP socket");
+    // Wake up regularly so the server can notice a silent client and fade out
+    udp.set_read_timeout(Some(Duration::from_millis(1000 / constants::MAX_FPS as u64)))
+        .expect("Couldn't set the read timeout");
+    if let Err(err) = udp.join_multicast_v4(&udp_leds::constants::MULTICAST_GROUP, &Ipv4Addr::UNSPECIFIED) {
+        warn!("Couldn't join the multicast group : {err}");
+    }
+    let mut buf: [u8; MAX_SEALED_LENGTH] = [0; MAX_SEALED_LENGTH];
+    let mut server = Server::new(leds, Advertisement {
+        max_fps: constants::MAX_FPS,
+        id,
+        ..Default::default()
+    });
+    if let Err(err) = server.set_config(config.clone()) {
+        error!("Invalid config : {err}");
+    }
+    if let Err(err) = server.set_segments(storage.load_segments()) {
+        error!("Invalid segments : {err}");
+    }
+    if let Err(err) = server.set_layout(storage.load_layout()) {
+        error!("Invalid layout : {err}");
+    }
+    server.set_timeout(Some(Duration::from_millis(constants::RECEIVER_TIMEOUT_MS)));
+    if let Some(key) = constants::KEY {
+        let key: Key = key.parse().expect("LED_KEY should be 64 hexadecimal digits");
+        server.set_cipher(Some(Cipher::new(constants::KEY_ID, &key)));
+        info!("Encryption enabled with key {}", constants::KEY_ID);
+    }
+    server.set_updater(ota::OtaUpdater::default());
     debug!("UDP initialized");
 
     info!("Initialization complete");
 
+    let mut last_health: Option<Instant> = None;
+    // The new firmware is kept once it joined the network and served a client, otherwise the bootloader rolls back
+    let mut marked_valid = false;
     loop {
-        //std::thread::sleep(std::time::Duration::from_millis(1000));
-        let (size, addr) = if let Ok((size, addr)) = udp.recv_from(&mut buf) {
-            (size, addr)
-        } else {
-            error!("Error recieving a packet");
-            continue;
+        server.tick();
+        if last_health.map_or(true, |at| at.elapsed() >= Duration::from_millis(constants::HEALTH_INTERVAL_MS)) {
+            let free_heap = unsafe { esp_idf_sys::esp_get_free_heap_size() };
+            server.set_health(free_heap, wifi::rssi());
+            last_health = Some(Instant::now());
+        }
+        // Wake up in time for the next timed frame
+        let interval = Duration::from_millis(1000 / constants::MAX_FPS as u64);
+        let wait = server.next_frame_in(Instant::now()).map_or(interval, |next| next.min(interval));
+        if let Err(err) = udp.set_read_timeout(Some(wait.max(Duration::from_millis(1)))) {
+            warn!("Couldn't set the read timeout : {err}");
+        }
+        let (size, addr) = match udp.recv_from(&mut buf) {
+            Ok((size, addr)) => (size, addr),
+            Err(err) if matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+            Err(err) => {
+                error!("Error recieving a packet");
+                server.report_error(format!("Couldn't receive : {err}"));
+                continue;
+            }
+        };
+        debug!("Recieved {} bytes from {}", size, addr);
+
+        let handled = match server.respond(&buf[..size], addr) {
+            Ok(Some(resp)) => {
+                if let Err(err) = udp.send_to(&resp, addr) {
+                    warn!("Couldn't answer {addr} : {err}");
+                }
+                true
+            }
+            Ok(None) => true,
+            Err(err) => {
+                warn!("Recieved a maleformed package : {err}");
+                false
+            }
         };
-        println!("Recieved {} bytes from {}", size, addr);
+        if handled && !marked_valid {
+            info!("Firmware confirmed");
+            ota::OtaUpdater::mark_valid();
+            marked_valid = true;
+        }
 
-        if size < 2 || buf[0] != CLIENT_FLAG_BYTE {
-            warn!("Recieved a maleformed package");
-            continue;
+        if let Some(segments) = server.take_segments() {
+            info!("New segments : {segments:?}");
+            if let Err(err) = storage.save_segments(&segments) {
+                error!("Couldn't store the segments : {err}");
+                server.report_error(format!("Couldn't store the segments : {err}"));
+            }
         }
 
-        let header = buf[1];
-        if is_hello(header) {
-            debug!("Recieved a hello package");
-            let resp = [SERVER_FLAG_BYTE, 0b1100_0000];
-            udp.send_to(&resp, addr);
-            continue;
+        if let Some(layout) = server.take_layout() {
+            info!("New layout : {layout:?}");
+            if let Err(err) = storage.save_layout(&layout) {
+                error!("Couldn't store the layout : {err}");
+                server.report_error(format!("Couldn't store the layout : {err}"));
+            }
         }
 
-        let device = header & DEVICE_ID_MASK;
-        if is_set_active(header) {
-            println!("Recieved set active ({device})");
-            active = device;
+        if server.take_reboot() {
+            info!("Restarting on the new firmware");
+            unsafe { esp_idf_sys::esp_restart() };
         }
 
-        if device != active {
-            println!("Recieved a package for a different device");
-            continue;
+        if let Some(changed) = server.take_config() {
+            info!("New config : {changed:?}");
+            if let Err(err) = storage.save_config(&changed) {
+                error!("Couldn't store the config : {err}");
+                server.report_error(format!("Couldn't store the config : {err}"));
+            } else if changed.output_pin != config.output_pin || changed.name != config.name {
+                // The RMT driver and the mDNS responder are set up once, they pick the change up on restart
+                info!("Restarting to apply the config");
+                unsafe { esp_idf_sys::esp_restart() };
+            }
         }
+    }
+}
 
-        let data = &buf[2..size];
-        leds.set(data);
+/// Drives the data line of the strip from the given GPIO, the pins are only known by their number at runtime
+fn new_rmt(channel: CHANNEL0, pin: u8, config: &TransmitConfig) -> Result<TxRmtDriver<'static>, EspError> {
+    if !Config::OUTPUT_PINS.contains(&pin) {
+        return Err(EspError::from_infallible::<{ esp_idf_sys::ESP_ERR_INVALID_ARG }>());
     }
+    let pin = unsafe { AnyOutputPin::new(pin as i32) };
+    TxRmtDriver::new(channel, pin, config)
 }
@@ -0,0 +1,60 @@
+use esp_idf_sys::{esp, esp_ota_handle_t, esp_partition_t, EspError};
+use log::{info, warn};
+use udp_leds::error::Error;
+use udp_leds::ota::Updater;
+
+/// Writes the images received over the network to the OTA partition that is not running
+#[derive(Default)]
+pub struct OtaUpdater {
+    handle: Option<(esp_ota_handle_t, *const esp_partition_t)>,
+}
+
+// The partition table lives in flash for the whole life of the program
+unsafe impl Send for OtaUpdater {}
+
+impl OtaUpdater {
+    /// Keeps the running image, otherwise the bootloader rolls back to the previous one on the next restart
+    pub fn mark_valid() {
+        if let Err(err) = esp!(unsafe { esp_idf_sys::esp_ota_mark_app_valid_cancel_rollback() }) {
+            warn!("Couldn't mark the firmware as valid : {err}");
+        }
+    }
+}
+
+fn failed(err: EspError) -> Error {
+    Error::UpdateFailed(err.to_string())
+}
+
+impl Updater for OtaUpdater {
+    fn begin(&mut self, size: u32) -> Result<(), Error> {
+        self.abort();
+        let partition = unsafe { esp_idf_sys::esp_ota_get_next_update_partition(std::ptr::null()) };
+        if partition.is_null() {
+            return Err(Error::UpdateFailed("no OTA partition".to_string()));
+        }
+        let mut handle: esp_ota_handle_t = 0;
+        esp!(unsafe { esp_idf_sys::esp_ota_begin(partition, size as usize, &mut handle) }).map_err(failed)?;
+        self.handle = Some((handle, partition));
+        info!("Receiving a firmware of {size} bytes");
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        let (handle, _) = self.handle.ok_or(Error::UpdateFailed("no update in progress".to_string()))?;
+        esp!(unsafe { esp_idf_sys::esp_ota_write(handle, data.as_ptr().cast(), data.len()) }).map_err(failed)
+    }
+
+    fn commit(&mut self) -> Result<(), Error> {
+        let (handle, partition) = self.handle.take().ok_or(Error::UpdateFailed("no update in progress".to_string()))?;
+        esp!(unsafe { esp_idf_sys::esp_ota_end(handle) }).map_err(failed)?;
+        esp!(unsafe { esp_idf_sys::esp_ota_set_boot_partition(partition) }).map_err(failed)?;
+        info!("Firmware committed, booted on the next restart");
+        Ok(())
+    }
+
+    fn abort(&mut self) {
+        if let Some((handle, _)) = self.handle.take() {
+            unsafe { esp_idf_sys::esp_ota_abort(handle) };
+        }
+    }
+}
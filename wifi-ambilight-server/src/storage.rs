@@ -0,0 +1,90 @@
+use esp_idf_svc::nvs::{EspDefaultNvs, EspDefaultNvsPartition};
+use esp_idf_sys::EspError;
+use log::warn;
+use udp_leds::config::Config;
+use udp_leds::layout::Layout;
+use udp_leds::segment::Segment;
+
+const NAMESPACE: &str = "leds";
+const CONFIG_KEY: &str = "config";
+const SEGMENTS_KEY: &str = "segments";
+const LAYOUT_KEY: &str = "layout";
+
+/// Settings kept in flash across restarts
+pub struct Storage {
+    nvs: EspDefaultNvs,
+}
+
+impl Storage {
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        Ok(Self {
+            nvs: EspDefaultNvs::new(partition, NAMESPACE, true)?,
+        })
+    }
+
+    /// The stored config, `None` before the first config was stored
+    pub fn load_config(&self) -> Option<Config> {
+        let mut buf = [0; 64];
+        match self.nvs.get_raw(CONFIG_KEY, &mut buf) {
+            Ok(Some(bytes)) => match Config::from_bytes(bytes) {
+                Ok(config) => Some(config),
+                Err(err) => {
+                    warn!("Ignoring the stored config : {err}");
+                    None
+                }
+            },
+            Ok(None) => None,
+            Err(err) => {
+                warn!("Couldn't read the stored config : {err}");
+                None
+            }
+        }
+    }
+
+    pub fn save_config(&mut self, config: &Config) -> Result<(), EspError> {
+        self.nvs.set_raw(CONFIG_KEY, &config.to_bytes())?;
+        Ok(())
+    }
+
+    /// The stored segment table, empty before the first table was stored
+    pub fn load_segments(&self) -> Vec<Segment> {
+        let mut buf = [0; 512];
+        match self.nvs.get_raw(SEGMENTS_KEY, &mut buf) {
+            Ok(Some(bytes)) => Segment::table_from_bytes(bytes).unwrap_or_else(|err| {
+                warn!("Ignoring the stored segments : {err}");
+                Vec::new()
+            }),
+            Ok(None) => Vec::new(),
+            Err(err) => {
+                warn!("Couldn't read the stored segments : {err}");
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn save_segments(&mut self, segments: &[Segment]) -> Result<(), EspError> {
+        self.nvs.set_raw(SEGMENTS_KEY, &Segment::table_to_bytes(segments))?;
+        Ok(())
+    }
+
+    /// The stored layout, unknown before the first layout was stored
+    pub fn load_layout(&self) -> Layout {
+        let mut buf = [0; 600];
+        match self.nvs.get_raw(LAYOUT_KEY, &mut buf) {
+            Ok(Some(bytes)) => Layout::from_bytes(bytes).unwrap_or_else(|err| {
+                warn!("Ignoring the stored layout : {err}");
+                Layout::Unknown
+            }),
+            Ok(None) => Layout::Unknown,
+            Err(err) => {
+                warn!("Couldn't read the stored layout : {err}");
+                Layout::Unknown
+            }
+        }
+    }
+
+    pub fn save_layout(&mut self, layout: &Layout) -> Result<(), EspError> {
+        self.nvs.set_raw(LAYOUT_KEY, &layout.to_bytes())?;
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use std::{net::Ipv4Addr, time::Duration};
+
 use embedded_svc::wifi::{ClientConfiguration, Configuration};
 use esp_idf_hal::modem::Modem;
 use esp_idf_svc::{
@@ -23,3 +25,33 @@ pub fn setup_wifi(modem: Modem, sysloop: EspEventLoop<System>) -> EspWifi<'stati
 
     wifi
 }
+
+pub fn wait_for_ip(wifi: &EspWifi<'static>) -> Ipv4Addr {
+    loop {
+        if let Ok(info) = wifi.sta_netif().get_ip_info() {
+            let ip = Ipv4Addr::from(info.ip.octets());
+            if !ip.is_unspecified() {
+                return ip;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Strength of the signal of the access point in dBm, 0 if not connected
+pub fn rssi() -> i8 {
+    let mut info = esp_idf_sys::wifi_ap_record_t::default();
+    match unsafe { esp_idf_sys::esp_wifi_sta_get_ap_info(&mut info) } {
+        esp_idf_sys::ESP_OK => info.rssi,
+        _ => 0,
+    }
+}
+
+/// The factory MAC address of the chip, used as the unique id of the server
+pub fn mac_address() -> [u8; 6] {
+    let mut mac = [0; 6];
+    unsafe {
+        esp_idf_sys::esp_efuse_mac_get_default(mac.as_mut_ptr());
+    }
+    mac
+}